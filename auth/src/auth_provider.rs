@@ -0,0 +1,205 @@
+//! Ways of authenticating JSON-RPC requests to premium RPC endpoints.
+//!
+//! [HttpSenderWithHeaders](crate::HttpSenderWithHeaders) asks its [AuthProvider] for
+//! headers before each request, and for the URL to send it to. When the endpoint answers
+//! 401 Unauthorized, the sender calls [AuthProvider::refresh] and retries the request once
+//! if the provider could renew its credentials.
+use crate::token_provider::TokenProvider;
+use async_trait::async_trait;
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue, AUTHORIZATION};
+use reqwest::Url;
+use std::sync::Arc;
+
+#[async_trait]
+pub trait AuthProvider: Send + Sync {
+    /// Headers to add to the next request.
+    async fn headers(&self) -> anyhow::Result<HeaderMap>;
+
+    /// The URL to send requests for the configured endpoint `url` to.
+    fn endpoint(&self, url: &str) -> anyhow::Result<String> {
+        Ok(url.to_string())
+    }
+
+    /// Called when the endpoint rejected a request sent with `rejected`.
+    /// Returns the headers to retry with, or `None` if there is nothing to renew.
+    async fn refresh(&self, _rejected: &HeaderMap) -> anyhow::Result<Option<HeaderMap>> {
+        Ok(None)
+    }
+
+    /// Whether [AuthProvider::endpoint] puts credentials in the URL,
+    /// in which case it must be kept out of logs and error messages.
+    fn secret_endpoint(&self) -> bool {
+        false
+    }
+}
+
+fn header_map(name: HeaderName, value: &str) -> anyhow::Result<HeaderMap> {
+    let mut value = HeaderValue::from_str(value)?;
+    value.set_sensitive(true);
+    let mut headers = HeaderMap::new();
+    headers.insert(name, value);
+    Ok(headers)
+}
+
+/// `Authorization: Bearer <token>`, with tokens from a [TokenProvider]:
+/// a fixed [StaticToken](crate::StaticToken), or a renewable
+/// [GenesysGoTokenProvider](crate::GenesysGoTokenProvider).
+pub struct BearerAuth(pub Arc<dyn TokenProvider>);
+
+impl BearerAuth {
+    pub fn new<P: TokenProvider + 'static>(tokens: P) -> Self {
+        Self(Arc::new(tokens))
+    }
+}
+
+#[async_trait]
+impl AuthProvider for BearerAuth {
+    async fn headers(&self) -> anyhow::Result<HeaderMap> {
+        header_map(AUTHORIZATION, &format!("Bearer {}", self.0.token().await?))
+    }
+
+    async fn refresh(&self, rejected: &HeaderMap) -> anyhow::Result<Option<HeaderMap>> {
+        let stale = rejected
+            .get(AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .unwrap_or_default();
+        let token = self.0.refresh(stale).await?;
+        Ok(Some(header_map(
+            AUTHORIZATION,
+            &format!("Bearer {}", token),
+        )?))
+    }
+}
+
+/// HTTP basic auth, `Authorization: Basic <base64 of username:password>`.
+pub struct BasicAuth {
+    pub username: String,
+    pub password: Option<String>,
+}
+
+#[async_trait]
+impl AuthProvider for BasicAuth {
+    async fn headers(&self) -> anyhow::Result<HeaderMap> {
+        let credentials = format!(
+            "{}:{}",
+            self.username,
+            self.password.as_deref().unwrap_or_default()
+        );
+        header_map(
+            AUTHORIZATION,
+            &format!("Basic {}", base64::encode(credentials)),
+        )
+    }
+}
+
+/// An API key sent in a custom header, e.g. `x-api-key: <key>`.
+pub struct HeaderAuth {
+    pub name: HeaderName,
+    pub value: String,
+}
+
+impl HeaderAuth {
+    pub fn new(name: &str, value: String) -> anyhow::Result<Self> {
+        Ok(Self {
+            name: HeaderName::from_bytes(name.as_bytes())?,
+            value,
+        })
+    }
+}
+
+#[async_trait]
+impl AuthProvider for HeaderAuth {
+    async fn headers(&self) -> anyhow::Result<HeaderMap> {
+        header_map(self.name.clone(), &self.value)
+    }
+}
+
+/// Where [UrlKeyAuth] puts the key.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum UrlKeyPlacement {
+    /// As a query parameter with this name, e.g. `?api-key=<key>`.
+    Query(String),
+    /// As the last path segment, e.g. `https://rpc.example.com/v2/<key>`.
+    Path,
+}
+
+/// An API key embedded in the endpoint URL.
+pub struct UrlKeyAuth {
+    pub key: String,
+    pub placement: UrlKeyPlacement,
+}
+
+#[async_trait]
+impl AuthProvider for UrlKeyAuth {
+    async fn headers(&self) -> anyhow::Result<HeaderMap> {
+        Ok(HeaderMap::new())
+    }
+
+    fn endpoint(&self, url: &str) -> anyhow::Result<String> {
+        let mut url = Url::parse(url)?;
+        match &self.placement {
+            UrlKeyPlacement::Query(name) => {
+                url.query_pairs_mut().append_pair(name, &self.key);
+            }
+            UrlKeyPlacement::Path => {
+                url.path_segments_mut()
+                    .map_err(|_| anyhow::anyhow!("cannot add an API key to this URL path"))?
+                    .pop_if_empty()
+                    .push(&self.key);
+            }
+        }
+        Ok(url.to_string())
+    }
+
+    fn secret_endpoint(&self) -> bool {
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::StaticToken;
+
+    #[tokio::test]
+    async fn builds_credentials() {
+        let bearer = BearerAuth::new(StaticToken("jwt".to_string()));
+        assert_eq!(bearer.headers().await.unwrap()[AUTHORIZATION], "Bearer jwt");
+
+        let basic = BasicAuth {
+            username: "user".to_string(),
+            password: Some("pass".to_string()),
+        };
+        assert_eq!(
+            basic.headers().await.unwrap()[AUTHORIZATION],
+            "Basic dXNlcjpwYXNz"
+        );
+
+        let header = HeaderAuth::new("x-api-key", "key".to_string()).unwrap();
+        assert_eq!(header.headers().await.unwrap()["x-api-key"], "key");
+        assert_eq!(header.refresh(&HeaderMap::new()).await.unwrap(), None);
+    }
+
+    #[test]
+    fn embeds_keys_in_url() {
+        let query = UrlKeyAuth {
+            key: "k3y".to_string(),
+            placement: UrlKeyPlacement::Query("api-key".to_string()),
+        };
+        assert_eq!(
+            query
+                .endpoint("https://rpc.example.com/?cluster=mainnet")
+                .unwrap(),
+            "https://rpc.example.com/?cluster=mainnet&api-key=k3y"
+        );
+        let path = UrlKeyAuth {
+            key: "k3y".to_string(),
+            placement: UrlKeyPlacement::Path,
+        };
+        assert_eq!(
+            path.endpoint("https://rpc.example.com/v2/").unwrap(),
+            "https://rpc.example.com/v2/k3y"
+        );
+    }
+}
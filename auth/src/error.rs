@@ -0,0 +1,52 @@
+use reqwest::StatusCode;
+use std::time::Duration;
+use thiserror::Error;
+
+/// Why a GenesysGo sign-in failed.
+#[derive(Debug, Error)]
+pub enum AuthError {
+    /// The portal rejected the signed sign-in message, e.g. because it was signed
+    /// by the wrong key or the configured message differs from the portal's.
+    #[error("GenesysGo rejected the sign-in signature for {signer} ({status}): {message}")]
+    InvalidSignature {
+        signer: String,
+        status: StatusCode,
+        message: String,
+    },
+    /// There is no premium account with this ID, or the signer does not own it.
+    #[error("unknown GenesysGo account ID {account_id:?} ({status}): {message}")]
+    UnknownAccount {
+        account_id: String,
+        status: StatusCode,
+        message: String,
+    },
+    /// Too many sign-in attempts, retry after the given delay if the portal sent one.
+    #[error("GenesysGo sign-in is rate limited{}", retry_hint(.retry_after))]
+    RateLimited { retry_after: Option<Duration> },
+    /// The portal answered with a body that isn't what the sign-in step expects.
+    #[error("malformed response to GenesysGo sign-in step {step}: {source}, body: {body}")]
+    MalformedResponse {
+        step: u8,
+        body: String,
+        source: serde_json::Error,
+    },
+    /// Any other unsuccessful status.
+    #[error("GenesysGo sign-in step {step} failed ({status}): {message}")]
+    Http {
+        step: u8,
+        status: StatusCode,
+        message: String,
+    },
+    #[error("GenesysGo sign-in request failed: {0}")]
+    Request(#[from] reqwest::Error),
+    /// The configured message can't be put in the configured [SignInFormat](crate::SignInFormat).
+    #[error("cannot build the GenesysGo sign-in message: {0}")]
+    Message(String),
+}
+
+fn retry_hint(retry_after: &Option<Duration>) -> String {
+    match retry_after {
+        Some(delay) => format!(", retry after {}s", delay.as_secs()),
+        None => String::new(),
+    }
+}
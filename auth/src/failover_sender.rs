@@ -0,0 +1,318 @@
+//! An [RpcSender] over several endpoints, e.g. a premium endpoint with a public
+//! fallback, each [HttpSenderWithHeaders] with its own auth.
+use crate::cassette::redact_url;
+use crate::http_sender::{HttpSenderWithHeaders, RetryStats};
+use anyhow::anyhow;
+use async_trait::async_trait;
+use log::*;
+use reqwest::StatusCode;
+use serde_json::Value;
+use solana_client::client_error::{ClientError, ClientErrorKind, Result as ClientResult};
+use solana_client::rpc_custom_error::JSON_RPC_SERVER_ERROR_NODE_UNHEALTHY;
+use solana_client::rpc_request::{RpcError, RpcRequest};
+use solana_client::rpc_sender::{RpcSender, RpcTransportStats};
+use std::sync::RwLock;
+use std::time::{Duration, Instant};
+use tokio::time::sleep;
+
+/// How long an endpoint which failed is tried only after the others.
+pub const DEFAULT_COOLDOWN: Duration = Duration::from_secs(30);
+
+struct Endpoint {
+    sender: HttpSenderWithHeaders,
+    /// Until when the endpoint is considered unhealthy.
+    unhealthy_until: RwLock<Option<Instant>>,
+}
+
+impl Endpoint {
+    fn is_healthy(&self) -> bool {
+        match *self.unhealthy_until.read().unwrap() {
+            Some(until) => Instant::now() >= until,
+            None => true,
+        }
+    }
+}
+
+/// Sends each request to the first healthy endpoint, in the order given, and fails
+/// over to the next on connection errors and timeouts, `5xx` and `429` responses,
+/// failures to authenticate and unhealthy nodes. An endpoint which failed goes to the
+/// back of the line for a cooldown. Other `4xx` responses and errors the node answered
+/// deliberately, such as a failed simulation, are returned as is.
+///
+/// With hedging, a read request still unanswered after the hedge delay is also sent
+/// to the next endpoint, unless that one is cooling down, and the first successful
+/// response wins.
+pub struct FailoverSender {
+    endpoints: Vec<Endpoint>,
+    hedge_after: Option<Duration>,
+    cooldown: Duration,
+}
+
+impl FailoverSender {
+    /// Fail over from the first of `senders` to the others.
+    pub fn new(senders: Vec<HttpSenderWithHeaders>) -> anyhow::Result<Self> {
+        if senders.is_empty() {
+            return Err(anyhow!("at least one RPC endpoint is required"));
+        }
+        Ok(Self {
+            endpoints: senders
+                .into_iter()
+                .map(|sender| Endpoint {
+                    sender,
+                    unhealthy_until: RwLock::new(None),
+                })
+                .collect(),
+            hedge_after: None,
+            cooldown: DEFAULT_COOLDOWN,
+        })
+    }
+
+    /// Hedge read requests which take longer than `delay`.
+    pub fn with_hedging(mut self, delay: Option<Duration>) -> Self {
+        self.hedge_after = delay;
+        self
+    }
+
+    pub fn with_cooldown(mut self, cooldown: Duration) -> Self {
+        self.cooldown = cooldown;
+        self
+    }
+
+    /// Retries of all endpoints together.
+    pub fn retry_stats(&self) -> RetryStats {
+        self.endpoints
+            .iter()
+            .map(|endpoint| endpoint.sender.retry_stats())
+            .fold(RetryStats::default(), |total, stats| RetryStats {
+                retries: total.retries + stats.retries,
+                retried_requests: total.retried_requests + stats.retried_requests,
+                exhausted_requests: total.exhausted_requests + stats.exhausted_requests,
+            })
+    }
+
+    /// Healthy endpoints first, each group in the configured order.
+    fn order(&self) -> Vec<usize> {
+        let (mut healthy, unhealthy): (Vec<usize>, Vec<usize>) =
+            (0..self.endpoints.len()).partition(|i| self.endpoints[*i].is_healthy());
+        healthy.extend(unhealthy);
+        healthy
+    }
+
+    fn mark(&self, i: usize, result: &ClientResult<Value>) {
+        let endpoint = &self.endpoints[i];
+        match result {
+            Ok(_) => *endpoint.unhealthy_until.write().unwrap() = None,
+            Err(err) if fails_over(err) => {
+                warn!(
+                    "RPC endpoint {} failed, trying the next one: {}",
+                    redact_url(&endpoint.sender.url()),
+                    err
+                );
+                *endpoint.unhealthy_until.write().unwrap() = Some(Instant::now() + self.cooldown);
+            }
+            Err(_) => {}
+        }
+    }
+
+    async fn send_to(&self, i: usize, request: RpcRequest, params: Value) -> ClientResult<Value> {
+        let result = self.endpoints[i].sender.send(request, params).await;
+        self.mark(i, &result);
+        result
+    }
+
+    /// Send to each endpoint of `order` in turn until one doesn't fail over.
+    async fn send_in_order(
+        &self,
+        order: &[usize],
+        request: RpcRequest,
+        params: Value,
+    ) -> ClientResult<Value> {
+        let mut last_err = None;
+        for i in order {
+            match self.send_to(*i, request, params.clone()).await {
+                Err(err) if fails_over(&err) => last_err = Some(err),
+                result => return result,
+            }
+        }
+        Err(last_err.expect("at least one endpoint"))
+    }
+
+    /// Send to `first`, and also to `second` if `first` hasn't answered after `delay`.
+    async fn hedged(
+        &self,
+        first: usize,
+        second: usize,
+        delay: Duration,
+        request: RpcRequest,
+        params: Value,
+    ) -> ClientResult<Value> {
+        let primary = self.send_to(first, request, params.clone());
+        let backup = async {
+            sleep(delay).await;
+            debug!(
+                "JSON-RPC {} slower than {:?}, hedging with {}",
+                request,
+                delay,
+                redact_url(&self.endpoints[second].sender.url())
+            );
+            self.send_to(second, request, params).await
+        };
+        tokio::pin!(primary, backup);
+        tokio::select! {
+            result = &mut primary => match result {
+                Err(err) if fails_over(&err) => backup.await,
+                result => result,
+            },
+            result = &mut backup => match result {
+                Err(_) => primary.await,
+                result => result,
+            },
+        }
+    }
+}
+
+/// Whether another endpoint might succeed where this one failed with `err`.
+fn fails_over(err: &ClientError) -> bool {
+    match err.kind() {
+        ClientErrorKind::Reqwest(err) => match err.status() {
+            Some(status) => status.is_server_error() || status == StatusCode::TOO_MANY_REQUESTS,
+            None => true,
+        },
+        ClientErrorKind::Io(_) => true,
+        ClientErrorKind::RpcError(RpcError::RpcResponseError { code, .. }) => {
+            *code == JSON_RPC_SERVER_ERROR_NODE_UNHEALTHY
+        }
+        // Failures to get credentials, the other endpoints have their own auth.
+        ClientErrorKind::RpcError(RpcError::ForUser(_)) => true,
+        _ => false,
+    }
+}
+
+/// Requests which only read state, and so can be sent to several endpoints at once.
+fn is_read(request: &RpcRequest) -> bool {
+    !matches!(
+        request,
+        RpcRequest::SendTransaction | RpcRequest::RequestAirdrop
+    )
+}
+
+#[async_trait]
+impl RpcSender for FailoverSender {
+    async fn send(&self, request: RpcRequest, params: Value) -> ClientResult<Value> {
+        let order = self.order();
+        let mut start = 0;
+        // Endpoints cooling down are only tried once the healthy ones failed.
+        let hedge_pair = match order.as_slice() {
+            [first, second, ..] if self.endpoints[*second].is_healthy() => Some((*first, *second)),
+            _ => None,
+        };
+        if let (Some(delay), true, Some((first, second))) =
+            (self.hedge_after, is_read(&request), hedge_pair)
+        {
+            match self
+                .hedged(first, second, delay, request, params.clone())
+                .await
+            {
+                Err(err) if fails_over(&err) && order.len() > 2 => start = 2,
+                result => return result,
+            }
+        }
+        self.send_in_order(&order[start..], request, params).await
+    }
+
+    /// The sum over all endpoints.
+    fn get_transport_stats(&self) -> RpcTransportStats {
+        self.endpoints
+            .iter()
+            .map(|endpoint| endpoint.sender.get_transport_stats())
+            .fold(RpcTransportStats::default(), |total, stats| {
+                RpcTransportStats {
+                    request_count: total.request_count + stats.request_count,
+                    elapsed_time: total.elapsed_time + stats.elapsed_time,
+                    rate_limited_time: total.rate_limited_time + stats.rate_limited_time,
+                }
+            })
+    }
+
+    /// The endpoint requests currently go to first.
+    fn url(&self) -> String {
+        self.endpoints[self.order()[0]].sender.url()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::retry::RetryPolicy;
+    use serde_json::json;
+    use shadow_drive_test_support::{MockResponse, MockServer};
+
+    /// An RPC node answering every request with `version` after `delay`.
+    fn mock_rpc(version: &str, delay: Duration) -> MockServer {
+        MockServer::always(MockResponse::rpc_result(json!(version)).with_delay(delay)).unwrap()
+    }
+
+    fn sender(url: &str) -> HttpSenderWithHeaders {
+        HttpSenderWithHeaders::new(url, None).with_retry_policy(RetryPolicy::none())
+    }
+
+    #[tokio::test]
+    async fn fails_over_and_hedges() {
+        let fallback = mock_rpc("fallback", Duration::ZERO);
+        let failover =
+            FailoverSender::new(vec![sender("http://127.0.0.1:1"), sender(fallback.url())])
+                .unwrap();
+        let version = failover.send(RpcRequest::GetVersion, Value::Null).await;
+        assert_eq!(version.unwrap(), "fallback");
+        // The dead endpoint cools down behind the fallback.
+        assert_eq!(failover.url(), fallback.url());
+        assert_eq!(failover.get_transport_stats().request_count, 2);
+
+        let slow = mock_rpc("slow", Duration::from_secs(5));
+        let hedging = FailoverSender::new(vec![sender(slow.url()), sender(fallback.url())])
+            .unwrap()
+            .with_hedging(Some(Duration::from_millis(50)));
+        let version = hedging.send(RpcRequest::GetVersion, Value::Null).await;
+        assert_eq!(version.unwrap(), "fallback");
+
+        assert!(FailoverSender::new(vec![]).is_err());
+    }
+
+    #[tokio::test]
+    async fn fails_over_only_on_server_errors() {
+        let fallback = mock_rpc("fallback", Duration::ZERO);
+        for status in [502, 503, 429] {
+            let broken = MockServer::always(MockResponse::new(status, "")).unwrap();
+            let failover =
+                FailoverSender::new(vec![sender(broken.url()), sender(fallback.url())]).unwrap();
+            let version = failover.send(RpcRequest::GetVersion, Value::Null).await;
+            assert_eq!(version.unwrap(), "fallback", "{}", status);
+        }
+
+        let requests = fallback.received().len();
+        let rejecting = MockServer::always(MockResponse::new(400, "")).unwrap();
+        let failover =
+            FailoverSender::new(vec![sender(rejecting.url()), sender(fallback.url())]).unwrap();
+        assert!(failover
+            .send(RpcRequest::GetVersion, Value::Null)
+            .await
+            .is_err());
+        assert_eq!(fallback.received().len(), requests);
+    }
+
+    #[tokio::test]
+    async fn does_not_hedge_with_endpoints_cooling_down() {
+        let slow = mock_rpc("slow", Duration::from_millis(300));
+        let broken = MockServer::always(MockResponse::new(503, "")).unwrap();
+        let hedging = FailoverSender::new(vec![sender(slow.url()), sender(broken.url())])
+            .unwrap()
+            .with_hedging(Some(Duration::from_millis(50)));
+        assert!(hedging
+            .send_to(1, RpcRequest::GetVersion, Value::Null)
+            .await
+            .is_err());
+        let version = hedging.send(RpcRequest::GetVersion, Value::Null).await;
+        assert_eq!(version.unwrap(), "slow");
+        assert_eq!(broken.received().len(), 1);
+    }
+}
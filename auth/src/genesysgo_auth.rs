@@ -1,12 +1,235 @@
+use crate::error::AuthError;
+use crate::offchain_message::OffchainMessage;
+use crate::siws::SiwsMessage;
 use anyhow::anyhow;
-use reqwest::Url;
+use log::debug;
+use reqwest::header::RETRY_AFTER;
+use reqwest::{Response, StatusCode};
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
 use solana_sdk::bs58;
-use solana_sdk::signature::Signer;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::{Signature, Signer};
+use std::time::Duration;
 
-const SIGNIN_MSG: &str = "Sign in to GenesysGo Shadow Platform.";
-const SIGNIN_URL_STEP1: &str = "https://portal.genesysgo.net/api/signin";
-const SIGNIN_URL_STEP2: &str = "https://portal.genesysgo.net/api/premium/token";
+pub const SIGNIN_MSG: &str = "Sign in to GenesysGo Shadow Platform.";
+pub const DEFAULT_PORTAL_URL: &str = "https://portal.genesysgo.net";
+
+/// How the sign-in message is presented to the signer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum SignInFormat {
+    /// The raw UTF-8 message, which is what the production portal checks.
+    /// Hardware wallets refuse to sign it.
+    Raw,
+    /// The message in a Solana [OffchainMessage] envelope, which hardware
+    /// wallets supporting off-chain messages can sign.
+    Offchain,
+    /// A [SiwsMessage] for the portal's domain with the message as its statement,
+    /// a fresh nonce and the issue time, in an [OffchainMessage] envelope,
+    /// so that the portal can reject replayed signatures.
+    Siws,
+}
+
+/// Where and how to sign in. The defaults are the production GenesysGo portal;
+/// override them for staging or a local mock.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GenesysGoAuthConfig {
+    /// Base URL of the portal, the API lives under `/api`.
+    pub portal_url: String,
+    /// The message signed in sign-in step #1.
+    pub message: String,
+    pub format: SignInFormat,
+}
+
+impl Default for GenesysGoAuthConfig {
+    fn default() -> Self {
+        Self {
+            portal_url: DEFAULT_PORTAL_URL.to_string(),
+            message: SIGNIN_MSG.to_string(),
+            format: SignInFormat::Raw,
+        }
+    }
+}
+
+/// The bytes signed for sign-in step #1, and their signature.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SignedMessage {
+    pub message: Vec<u8>,
+    pub signature: Signature,
+}
+
+impl GenesysGoAuthConfig {
+    fn signin_url(&self) -> String {
+        format!("{}/api/signin", self.portal_url.trim_end_matches('/'))
+    }
+
+    fn token_url(&self, account_id: &str) -> String {
+        format!(
+            "{}/api/premium/token/{}",
+            self.portal_url.trim_end_matches('/'),
+            account_id
+        )
+    }
+
+    /// The bytes to sign for `pubkey` in the configured [SignInFormat].
+    /// SIWS messages differ on every call.
+    pub fn message_bytes(&self, pubkey: &Pubkey) -> Result<Vec<u8>, AuthError> {
+        let message = match self.format {
+            SignInFormat::Raw => return Ok(self.message.as_bytes().to_vec()),
+            SignInFormat::Offchain => self.message.clone(),
+            SignInFormat::Siws => {
+                let domain = reqwest::Url::parse(&self.portal_url)
+                    .ok()
+                    .and_then(|url| url.host_str().map(|host| host.to_string()))
+                    .ok_or_else(|| {
+                        AuthError::Message(format!("no domain in portal URL {}", self.portal_url))
+                    })?;
+                SiwsMessage::new(&domain, *pubkey)
+                    .with_statement(&self.message)
+                    .with_uri(&self.portal_url)
+                    .to_string()
+            }
+        };
+        let envelope = OffchainMessage::new(message.as_bytes())
+            .map_err(|e| AuthError::Message(e.to_string()))?;
+        Ok(envelope.serialize())
+    }
+
+    /// Sign the configured message, for [GenesysGoAuthConfig::sign_in_with_signature].
+    pub fn sign_message(&self, signer: &dyn Signer) -> Result<SignedMessage, AuthError> {
+        let message = self.message_bytes(&signer.pubkey())?;
+        Ok(SignedMessage {
+            signature: signer.sign_message(&message),
+            message,
+        })
+    }
+
+    /// Complete both sign-in steps, returning the token for RPC requests.
+    pub async fn sign_in(
+        &self,
+        signer: &dyn Signer,
+        account_id: &str,
+    ) -> Result<String, AuthError> {
+        self.sign_in_with_signature(&signer.pubkey(), &self.sign_message(signer)?, account_id)
+            .await
+    }
+
+    /// Sign in with a signature of the configured message made ahead of time,
+    /// for callers which can't hold on to the signer across requests.
+    pub async fn sign_in_with_signature(
+        &self,
+        pubkey: &Pubkey,
+        signed: &SignedMessage,
+        account_id: &str,
+    ) -> Result<String, AuthError> {
+        let client = reqwest::Client::new();
+        let resp = self.step_1(pubkey, signed, &client).await?;
+        let resp = self.step_2(account_id, &resp.token, &client).await?;
+        Ok(resp.token)
+    }
+
+    /// First request, acquire a JWT needed for the second request.
+    /// Unless the message is [SignInFormat::Raw], the signed bytes are sent along,
+    /// as the portal can't reconstruct them.
+    pub async fn step_1(
+        &self,
+        pubkey: &Pubkey,
+        signed: &SignedMessage,
+        client: &reqwest::Client,
+    ) -> Result<GenesysGoAuthResponse, AuthError> {
+        let body = GenesysGoAuth {
+            message: bs58::encode(signed.signature.as_ref()).into_string(),
+            signer: pubkey.to_string(),
+            signed_message: match self.format {
+                SignInFormat::Raw => None,
+                _ => Some(bs58::encode(&signed.message).into_string()),
+            },
+        };
+        let resp = client
+            .post(self.signin_url())
+            .header("Content-Type", "application/json")
+            .body(serde_json::to_string(&body).expect("serialize sign-in body"))
+            .send()
+            .await?;
+        debug!(
+            "GenesysGo sign-in step 1 for {}: {}",
+            body.signer,
+            resp.status()
+        );
+        match resp.status() {
+            status if status.is_success() => parse_body(1, resp).await,
+            status @ (StatusCode::BAD_REQUEST
+            | StatusCode::UNAUTHORIZED
+            | StatusCode::FORBIDDEN) => Err(AuthError::InvalidSignature {
+                signer: body.signer,
+                status,
+                message: resp.text().await.unwrap_or_default(),
+            }),
+            _ => Err(unexpected(1, resp).await),
+        }
+    }
+
+    /// Second request, uses the Bearer token from the first sign-in step,
+    /// and acquires JWT used to authenticate normal RPC requests.
+    pub async fn step_2(
+        &self,
+        account_id: &str,
+        step_1_auth_token: &str,
+        client: &reqwest::Client,
+    ) -> Result<TokenResponse, AuthError> {
+        let resp = client
+            .post(self.token_url(account_id))
+            .header("Content-Type", "application/json")
+            .bearer_auth(step_1_auth_token)
+            .send()
+            .await?;
+        debug!(
+            "GenesysGo sign-in step 2 for account {}: {}",
+            account_id,
+            resp.status()
+        );
+        match resp.status() {
+            status if status.is_success() => parse_body(2, resp).await,
+            status @ (StatusCode::FORBIDDEN | StatusCode::NOT_FOUND) => {
+                Err(AuthError::UnknownAccount {
+                    account_id: account_id.to_string(),
+                    status,
+                    message: resp.text().await.unwrap_or_default(),
+                })
+            }
+            _ => Err(unexpected(2, resp).await),
+        }
+    }
+}
+
+async fn parse_body<T: DeserializeOwned>(step: u8, resp: Response) -> Result<T, AuthError> {
+    let body = resp.text().await?;
+    serde_json::from_str(&body).map_err(|source| AuthError::MalformedResponse {
+        step,
+        body,
+        source,
+    })
+}
+
+/// Error for a status not specific to either sign-in step.
+async fn unexpected(step: u8, resp: Response) -> AuthError {
+    let status = resp.status();
+    if status == StatusCode::TOO_MANY_REQUESTS {
+        let retry_after = resp
+            .headers()
+            .get(RETRY_AFTER)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.parse().ok())
+            .map(Duration::from_secs);
+        return AuthError::RateLimited { retry_after };
+    }
+    AuthError::Http {
+        step,
+        status,
+        message: resp.text().await.unwrap_or_default(),
+    }
+}
 
 /// The response object for sign-in Step #1.
 #[derive(Debug, Serialize, Deserialize)]
@@ -35,58 +258,47 @@ pub struct TokenResponse {
 
 /// The request body for sign-in Step #1.
 #[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
 pub struct GenesysGoAuth {
     message: String, // signed and base-58 encoded SIGNIN_MSG
     signer: String,
+    /// Base-58 encoded off-chain message, if that is what was signed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    signed_message: Option<String>,
 }
 
-pub async fn sign_in(signer: &dyn Signer, account_id: &str) -> anyhow::Result<String> {
-    let client = reqwest::Client::new();
-    let resp = sign_in_step_1(signer, &client).await?;
-    let resp = sign_in_step_2(account_id, &resp.token, &client).await?;
-    Ok(resp.token)
-    // let signature = signer.sign_message(SIGNIN_MSG.as_bytes());
-    // let body = GenesysGoAuth {
-    //     message: bs58::encode(signature.as_ref()).into_string(),
-    //     signer: signer.pubkey().to_string(),
-    // };
-    // let client = reqwest::Client::new();
-    // // First request, acquire a JWT needed for the second request.
-    // let resp = client
-    //     .post(Url::parse(SIGNIN_URL_STEP1)?)
-    //     .header("Content-Type", "application/json")
-    //     .body(serde_json::to_string(&body)?)
-    //     .send()
-    //     .await?;
-    // let auth_resp: GenesysGoAuthResponse = serde_json::from_str(&resp.text().await?)?;
-    // // Second request, acquire JWT to be included with authenticated requests.
-    // let step2_url = SIGNIN_URL_STEP2.to_owned() + "/" + account_id;
-    // let token1 = format!("Bearer {}", auth_resp.token);
-    // let resp = client
-    //     .post(Url::parse(&step2_url)?)
-    //     .header("Content-Type", "application/json")
-    //     .header("Authorization", &token1)
-    //     .send()
-    //     .await?;
-    // let resp: TokenResponse = serde_json::from_str(&resp.text().await?)?;
-    // Ok(resp.token)
+/// Sign in to the production portal, see [GenesysGoAuthConfig::sign_in].
+pub async fn sign_in(signer: &dyn Signer, account_id: &str) -> Result<String, AuthError> {
+    GenesysGoAuthConfig::default()
+        .sign_in(signer, account_id)
+        .await
 }
 
-/// First request, acquire a JWT needed for the second request.
-pub async fn sign_in_step_1(signer: &dyn Signer, client: &reqwest::Client) -> anyhow::Result<GenesysGoAuthResponse> {
-    let signature = signer.sign_message(SIGNIN_MSG.as_bytes());
-    let body = GenesysGoAuth {
-        message: bs58::encode(signature.as_ref()).into_string(),
-        signer: signer.pubkey().to_string(),
+/// Sign in with a signature of [SIGNIN_MSG] made ahead of time, for callers
+/// which can't hold on to the signer across requests.
+pub async fn sign_in_with_signature(
+    pubkey: &Pubkey,
+    signature: &Signature,
+    account_id: &str,
+) -> Result<String, AuthError> {
+    let signed = SignedMessage {
+        message: SIGNIN_MSG.as_bytes().to_vec(),
+        signature: *signature,
     };
-    let resp = client
-        .post(Url::parse(SIGNIN_URL_STEP1)?)
-        .header("Content-Type", "application/json")
-        .body(serde_json::to_string(&body)?)
-        .send()
-        .await?;
-    let auth_resp: GenesysGoAuthResponse = serde_json::from_str(&resp.text().await?)?;
-    Ok(auth_resp)
+    GenesysGoAuthConfig::default()
+        .sign_in_with_signature(pubkey, &signed, account_id)
+        .await
+}
+
+/// First request, acquire a JWT needed for the second request.
+pub async fn sign_in_step_1(
+    signer: &dyn Signer,
+    client: &reqwest::Client,
+) -> Result<GenesysGoAuthResponse, AuthError> {
+    let config = GenesysGoAuthConfig::default();
+    config
+        .step_1(&signer.pubkey(), &config.sign_message(signer)?, client)
+        .await
 }
 
 /// Second request, uses the Bearer token from the first sign-in step,
@@ -95,28 +307,147 @@ pub async fn sign_in_step_2(
     account_id: &str,
     step_1_auth_token: &str,
     client: &reqwest::Client,
-) -> anyhow::Result<TokenResponse> {
-    let step2_url = SIGNIN_URL_STEP2.to_owned() + "/" + account_id;
-    let bearer_token = format!("Bearer {}", step_1_auth_token);
-    let resp = client
-        .post(Url::parse(&step2_url)?)
-        .header("Content-Type", "application/json")
-        .header("Authorization", &bearer_token)
-        .send()
-        .await?;
-    let resp: TokenResponse = serde_json::from_str(&resp.text().await?)?;
-    Ok(resp)
+) -> Result<TokenResponse, AuthError> {
+    GenesysGoAuthConfig::default()
+        .step_2(account_id, step_1_auth_token, client)
+        .await
+}
+
+/// Hostnames of GenesysGo RPC endpoints: `genesysgo.net`, `genesysgo.com`
+/// and their subdomains, e.g. `us-west-1.genesysgo.net`.
+fn is_genesysgo_host(host: &str) -> bool {
+    let host = host.trim_end_matches('.').to_ascii_lowercase();
+    ["genesysgo.net", "genesysgo.com"]
+        .iter()
+        .any(|domain| host == *domain || host.ends_with(&format!(".{}", domain)))
 }
 
 /// If you only have a URL to a shadow RPC endpoint,
 /// this will obtain the account ID necessary to perform a sign-in.
+///
+/// The account ID is the last non-empty path segment of a GenesysGo URL, e.g.
+/// `https://us-west-1.genesysgo.net/<account id>/`. Ports, query parameters and
+/// fragments are ignored.
 pub fn parse_account_id_from_url(genesysgo_url: String) -> anyhow::Result<String> {
-    if !genesysgo_url.contains("genesysgo") {
+    let url = reqwest::Url::parse(genesysgo_url.trim())
+        .map_err(|e| anyhow!("Could not parse genesysgo url {}: {}", genesysgo_url, e))?;
+    if !matches!(url.host_str(), Some(host) if is_genesysgo_host(host)) {
         return Err(anyhow!("Not a genesysgo URL, cannot infer Account ID"));
     }
-    let pieces = genesysgo_url.split("/");
-    let last = pieces
-        .last()
-        .ok_or(anyhow!("Could not parse genesysgo url: {}", &genesysgo_url))?;
-    Ok(last.to_string())
+    url.path_segments()
+        .and_then(|segments| segments.rev().find(|s| !s.is_empty()))
+        .map(|segment| segment.to_string())
+        .ok_or_else(|| {
+            anyhow!(
+                "No account ID in genesysgo url {}, expected one as the last path segment",
+                url.origin().ascii_serialization()
+            )
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use shadow_drive_test_support::{MockResponse, MockServer};
+    use solana_sdk::signature::Keypair;
+
+    /// A portal answering every request with `status` and `body`.
+    fn mock_portal(status: u16, body: &str) -> (MockServer, GenesysGoAuthConfig) {
+        let response = MockResponse::new(status, body).with_header("retry-after", "30");
+        let portal = MockServer::always(response).unwrap();
+        let config = GenesysGoAuthConfig {
+            portal_url: portal.url().to_string(),
+            ..Default::default()
+        };
+        (portal, config)
+    }
+
+    #[tokio::test]
+    async fn classifies_sign_in_errors() {
+        let signer = Keypair::new();
+        let client = reqwest::Client::new();
+
+        let (_portal, config) = mock_portal(401, "bad signature");
+        let signed = config.sign_message(&signer).unwrap();
+        let err = config.step_1(&signer.pubkey(), &signed, &client).await;
+        assert!(matches!(err, Err(AuthError::InvalidSignature { .. })));
+
+        let (_portal, config) = mock_portal(404, "");
+        let err = config.step_2("account", "jwt", &client).await;
+        assert!(matches!(err, Err(AuthError::UnknownAccount { .. })));
+
+        let (_portal, config) = mock_portal(429, "");
+        let err = config.step_2("account", "jwt", &client).await;
+        assert!(matches!(
+            err,
+            Err(AuthError::RateLimited { retry_after: Some(delay) }) if delay.as_secs() == 30
+        ));
+
+        let (_portal, config) = mock_portal(200, "<html>");
+        let err = config.step_2("account", "jwt", &client).await;
+        assert!(matches!(
+            err,
+            Err(AuthError::MalformedResponse { step: 2, .. })
+        ));
+    }
+
+    #[test]
+    fn formats_sign_in_messages() {
+        let signer = Keypair::new();
+        let mut config = GenesysGoAuthConfig::default();
+        assert_eq!(
+            config.sign_message(&signer).unwrap().message,
+            SIGNIN_MSG.as_bytes()
+        );
+
+        config.format = SignInFormat::Offchain;
+        let signed = config.sign_message(&signer).unwrap();
+        let envelope = OffchainMessage::deserialize(&signed.message).unwrap();
+        assert_eq!(envelope.message(), SIGNIN_MSG.as_bytes());
+        assert!(envelope.verify(&signer.pubkey(), &signed.signature));
+
+        config.format = SignInFormat::Siws;
+        let signed = config.sign_message(&signer).unwrap();
+        let message = crate::siws::verify_sign_in(
+            &signed.message,
+            &signed.signature,
+            &signer.pubkey(),
+            "portal.genesysgo.net",
+            Duration::from_secs(60),
+        )
+        .unwrap();
+        assert_eq!(message.statement.as_deref(), Some(SIGNIN_MSG));
+        assert_ne!(config.sign_message(&signer).unwrap(), signed);
+    }
+
+    #[test]
+    fn parses_account_id_from_url() {
+        let cases = [
+            ("https://rpc.genesysgo.net/abc-123", Some("abc-123")),
+            ("https://rpc.genesysgo.net/abc-123/", Some("abc-123")),
+            ("https://rpc.genesysgo.net:8443/abc-123", Some("abc-123")),
+            ("https://rpc.genesysgo.net/abc-123?mode=1", Some("abc-123")),
+            ("https://rpc.genesysgo.net/abc-123/#rpc", Some("abc-123")),
+            ("https://rpc.genesysgo.net/v1/abc-123", Some("abc-123")),
+            ("https://genesysgo.net/abc-123", Some("abc-123")),
+            ("https://rpc.eu.GenesysGo.com/abc-123", Some("abc-123")),
+            (" https://rpc.genesysgo.net/abc-123 ", Some("abc-123")),
+            ("https://rpc.genesysgo.net", None),
+            ("https://rpc.genesysgo.net/", None),
+            ("https://rpc.genesysgo.net/?account=abc-123", None),
+            ("https://rpc.example.com/genesysgo", None),
+            ("https://genesysgo.net.example.com/abc-123", None),
+            ("https://notgenesysgo.net/abc-123", None),
+            ("us-west-1.genesysgo.net/abc-123", None),
+            ("", None),
+        ];
+        for (url, expected) in cases {
+            assert_eq!(
+                parse_account_id_from_url(url.to_string()).ok().as_deref(),
+                expected,
+                "{}",
+                url
+            );
+        }
+    }
 }
@@ -1,13 +1,19 @@
 /// Copied from `solana-rpc-client` crate, modified [HttpSender]
 /// to allow for passing in default headers. This is useful for
 /// passing auth headers to RPC services like GenesysGo.
-use reqwest::header::HeaderMap;
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
 use serde::Deserialize;
 use serde_json::{json, Value};
+use solana_client::client_error::ClientError;
 use solana_client::rpc_custom_error as custom_error;
 use solana_client::rpc_request::{RpcError, RpcRequest, RpcResponseErrorData};
 use solana_client::rpc_response::RpcSimulateTransactionResult;
 use solana_client::rpc_sender::{RpcSender, RpcTransportStats};
+use crate::auth_provider::{AuthProvider, BearerAuth};
+use crate::cassette::{describe_failure, redact_url, Cassette, Interaction};
+use crate::metrics::{RequestRecord, TransportMetrics, RPC};
+use crate::retry::RetryPolicy;
+use crate::token_provider::TokenProvider;
 use {
     async_trait::async_trait,
     log::*,
@@ -37,6 +43,22 @@ pub struct HttpSenderWithHeaders {
     url: String,
     request_id: AtomicU64,
     stats: RwLock<RpcTransportStats>,
+    auth: Option<Arc<dyn AuthProvider>>,
+    retry: RetryPolicy,
+    retry_stats: RwLock<RetryStats>,
+    metrics: Option<Arc<TransportMetrics>>,
+    cassette: Option<Arc<Cassette>>,
+}
+
+/// Retries made by a [HttpSenderWithHeaders], which [RpcTransportStats] has no room for.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct RetryStats {
+    /// Attempts after the first, across all requests.
+    pub retries: u64,
+    /// Requests which needed at least one retry.
+    pub retried_requests: u64,
+    /// Requests which ran out of attempts or time while still failing.
+    pub exhausted_requests: u64,
 }
 
 /// Nonblocking [`RpcSender`] over HTTP.
@@ -68,6 +90,11 @@ impl HttpSenderWithHeaders {
         if let Some(headers) = headers {
             default_headers.extend(headers);
         }
+        debug!(
+            "RPC sender for {} with headers {:?}",
+            redact_url(&url.to_string()),
+            redact_headers(&default_headers)
+        );
 
         let client = Arc::new(
             reqwest::Client::builder()
@@ -83,22 +110,154 @@ impl HttpSenderWithHeaders {
             url: url.to_string(),
             request_id: AtomicU64::new(0),
             stats: RwLock::new(RpcTransportStats::default()),
+            auth: None,
+            retry: RetryPolicy::default(),
+            retry_stats: RwLock::new(RetryStats::default()),
+            metrics: None,
+            cassette: None,
+        }
+    }
+
+    /// Retry failed requests as `policy` says, instead of by the [RetryPolicy::default].
+    pub fn with_retry_policy(mut self, policy: RetryPolicy) -> Self {
+        self.retry = policy;
+        self
+    }
+
+    /// Also record every request in `metrics`, which may be shared with other senders.
+    pub fn with_metrics(mut self, metrics: Arc<TransportMetrics>) -> Self {
+        self.metrics = Some(metrics);
+        self
+    }
+
+    /// Record the outcome of every request to `cassette`, responses and failures
+    /// alike, or, if it is being replayed, answer from it without sending anything.
+    pub fn with_cassette(mut self, cassette: Arc<Cassette>) -> Self {
+        self.cassette = Some(cassette);
+        self
+    }
+
+    pub fn retry_stats(&self) -> RetryStats {
+        self.retry_stats.read().unwrap().clone()
+    }
+
+    /// The delay before the next attempt at a request, if it may be retried,
+    /// see [RetryPolicy::next_delay]. Records the retry in the [RetryStats].
+    fn next_retry(
+        &self,
+        attempts: &mut u32,
+        started: Instant,
+        retry_after: Option<Duration>,
+    ) -> Option<Duration> {
+        let delay = self.retry.next_delay(*attempts, started, retry_after);
+        let mut stats = self.retry_stats.write().unwrap();
+        match delay {
+            Some(_) => {
+                stats.retries += 1;
+                if *attempts == 1 {
+                    stats.retried_requests += 1;
+                }
+                *attempts += 1;
+            }
+            None if *attempts > 1 => stats.exhausted_requests += 1,
+            None => {}
+        }
+        delay
+    }
+
+    /// Record the final outcome of a request, after any retries, to the cassette.
+    /// The endpoint's path is redacted, as it may hold an API key or account ID.
+    fn record(
+        &self,
+        request_id: u64,
+        request: RpcRequest,
+        params: &Option<Value>,
+        status: u16,
+        response: Value,
+        error: Option<String>,
+    ) {
+        let cassette = match &self.cassette {
+            Some(cassette) => cassette,
+            None => return,
+        };
+        let interaction = Interaction {
+            transport: RPC.to_string(),
+            method: request.to_string(),
+            url: redact_url(&self.url),
+            request: params.clone().unwrap_or_default(),
+            status,
+            headers: Default::default(),
+            response,
+            base64: false,
+            error,
+        };
+        if let Err(err) = cassette.record_interaction(&interaction) {
+            warn!(
+                "Unable to record JSON-RPC response #{}: {}",
+                request_id, err
+            );
         }
     }
+
+    /// Authenticate each request with `provider`. If the endpoint answers
+    /// 401 Unauthorized, the credentials are refreshed and the request retried once.
+    pub fn with_auth_provider(mut self, provider: Arc<dyn AuthProvider>) -> Self {
+        self.auth = Some(provider);
+        self
+    }
+
+    /// Send a bearer token from `provider` with each request, see [Self::with_auth_provider].
+    pub fn with_token_provider(self, provider: Arc<dyn TokenProvider>) -> Self {
+        self.with_auth_provider(Arc::new(BearerAuth(provider)))
+    }
+}
+
+fn auth_error(err: anyhow::Error) -> RpcError {
+    RpcError::ForUser(format!("unable to authenticate RPC request: {:#}", err))
+}
+
+/// A recorded failure, as the same kind of error it was when recorded: an error
+/// status, so that it fails over and is reported alike, or no response at all.
+fn replayed_failure(status: u16, error: String) -> ClientError {
+    if let Ok(status) = StatusCode::from_u16(status) {
+        if !status.is_success() {
+            let response = http::Response::builder()
+                .status(status)
+                .body(Vec::new())
+                .expect("a response with only a status");
+            if let Err(err) = reqwest::Response::from(response).error_for_status() {
+                return err.into();
+            }
+        }
+    }
+    std::io::Error::new(std::io::ErrorKind::Other, format!("{} (replayed)", error)).into()
 }
 
 struct StatsUpdater<'a> {
     stats: &'a RwLock<RpcTransportStats>,
     request_start_time: Instant,
     rate_limited_time: Duration,
+    metrics: Option<(&'a TransportMetrics, RpcRequest)>,
+    attempts: u32,
+    bytes_sent: u64,
+    bytes_received: u64,
+    failed: bool,
 }
 
 impl<'a> StatsUpdater<'a> {
-    fn new(stats: &'a RwLock<RpcTransportStats>) -> Self {
+    fn new(
+        stats: &'a RwLock<RpcTransportStats>,
+        metrics: Option<(&'a TransportMetrics, RpcRequest)>,
+    ) -> Self {
         Self {
             stats,
             request_start_time: Instant::now(),
             rate_limited_time: Duration::default(),
+            metrics,
+            attempts: 1,
+            bytes_sent: 0,
+            bytes_received: 0,
+            failed: true,
         }
     }
 
@@ -109,13 +268,73 @@ impl<'a> StatsUpdater<'a> {
 
 impl<'a> Drop for StatsUpdater<'a> {
     fn drop(&mut self) {
+        let elapsed = Instant::now().duration_since(self.request_start_time);
         let mut stats = self.stats.write().unwrap();
         stats.request_count += 1;
-        stats.elapsed_time += Instant::now().duration_since(self.request_start_time);
+        stats.elapsed_time += elapsed;
         stats.rate_limited_time += self.rate_limited_time;
+        if let Some((metrics, request)) = &self.metrics {
+            metrics.record(
+                RPC,
+                &request.to_string(),
+                RequestRecord {
+                    elapsed,
+                    rate_limited: self.rate_limited_time,
+                    bytes_sent: self.bytes_sent,
+                    bytes_received: self.bytes_received,
+                    retries: u64::from(self.attempts - 1),
+                    failed: self.failed,
+                },
+            );
+        }
     }
 }
 
+/// Parse a `Name: value` header, e.g. from `--rpc-header`. The value is marked
+/// sensitive, so that [redact_headers] keeps it out of logs.
+pub fn parse_header(header: &str) -> anyhow::Result<(HeaderName, HeaderValue)> {
+    let (name, value) = header
+        .split_once(':')
+        .ok_or_else(|| anyhow::anyhow!("expected a header as \"Name: value\", got {:?}", header))?;
+    let name = HeaderName::from_bytes(name.trim().as_bytes())
+        .map_err(|_| anyhow::anyhow!("invalid header name {:?}", name.trim()))?;
+    let mut value = HeaderValue::from_str(value.trim())
+        .map_err(|_| anyhow::anyhow!("invalid value for header {}", name))?;
+    value.set_sensitive(true);
+    Ok((name, value))
+}
+
+/// Headers whose values are never secret, and so are logged as they are.
+const LOGGED_HEADERS: [header::HeaderName; 5] = [
+    header::ACCEPT,
+    header::CONTENT_LENGTH,
+    header::CONTENT_TYPE,
+    header::RETRY_AFTER,
+    header::USER_AGENT,
+];
+
+/// Header names and values, fit for logging: only the values of well-known
+/// headers such as `Content-Type` are kept, all others, including every header
+/// given by the user, are replaced by `<redacted>`. The auth scheme is kept.
+pub fn redact_headers(headers: &HeaderMap) -> Vec<(String, String)> {
+    headers
+        .iter()
+        .map(|(name, value)| {
+            let value = if name == header::AUTHORIZATION || name == header::PROXY_AUTHORIZATION {
+                match value.to_str().ok().and_then(|v| v.split_once(' ')) {
+                    Some((scheme, _)) => format!("{} <redacted>", scheme),
+                    None => "<redacted>".to_string(),
+                }
+            } else if value.is_sensitive() || !LOGGED_HEADERS.contains(name) {
+                "<redacted>".to_string()
+            } else {
+                value.to_str().unwrap_or("<binary>").to_string()
+            };
+            (name.to_string(), value)
+        })
+        .collect()
+}
+
 pub fn build_request_json(req: &RpcRequest, id: u64, params: Value) -> Value {
     let jsonrpc = "2.0";
     json!({
@@ -126,6 +345,56 @@ pub fn build_request_json(req: &RpcRequest, id: u64, params: Value) -> Value {
     })
 }
 
+/// The error of a JSON-RPC response, if it is one.
+fn response_error(json: &Value) -> Option<RpcError> {
+    if !json["error"].is_object() {
+        return None;
+    }
+    let error = match serde_json::from_value::<RpcErrorObject>(json["error"].clone()) {
+        Ok(rpc_error_object) => {
+            let data = match rpc_error_object.code {
+                custom_error::JSON_RPC_SERVER_ERROR_SEND_TRANSACTION_PREFLIGHT_FAILURE => {
+                    match serde_json::from_value::<RpcSimulateTransactionResult>(
+                        json["error"]["data"].clone(),
+                    ) {
+                        Ok(data) => RpcResponseErrorData::SendTransactionPreflightFailure(data),
+                        Err(err) => {
+                            debug!(
+                                "Failed to deserialize RpcSimulateTransactionResult: {:?}",
+                                err
+                            );
+                            RpcResponseErrorData::Empty
+                        }
+                    }
+                }
+                custom_error::JSON_RPC_SERVER_ERROR_NODE_UNHEALTHY => {
+                    match serde_json::from_value::<custom_error::NodeUnhealthyErrorData>(
+                        json["error"]["data"].clone(),
+                    ) {
+                        Ok(custom_error::NodeUnhealthyErrorData { num_slots_behind }) => {
+                            RpcResponseErrorData::NodeUnhealthy { num_slots_behind }
+                        }
+                        Err(_err) => RpcResponseErrorData::Empty,
+                    }
+                }
+                _ => RpcResponseErrorData::Empty,
+            };
+
+            RpcError::RpcResponseError {
+                code: rpc_error_object.code,
+                message: rpc_error_object.message,
+                data,
+            }
+        }
+        Err(err) => RpcError::RpcRequestError(format!(
+            "Failed to deserialize RPC error response: {} [{}]",
+            serde_json::to_string(&json["error"]).unwrap(),
+            err
+        )),
+    };
+    Some(error)
+}
+
 #[async_trait]
 impl RpcSender for HttpSenderWithHeaders {
     fn get_transport_stats(&self) -> RpcTransportStats {
@@ -137,93 +406,214 @@ impl RpcSender for HttpSenderWithHeaders {
         request: RpcRequest,
         params: serde_json::Value,
     ) -> solana_client::client_error::Result<serde_json::Value> {
-        let mut stats_updater = StatsUpdater::new(&self.stats);
+        let mut stats_updater = StatsUpdater::new(
+            &self.stats,
+            self.metrics.as_deref().map(|metrics| (metrics, request)),
+        );
 
         let request_id = self.request_id.fetch_add(1, Ordering::Relaxed);
+        let recorded_params = self.cassette.as_ref().map(|_| params.clone());
         let request_json = build_request_json(&request, request_id, params).to_string();
+        debug!(
+            "JSON-RPC request #{} {} to {}",
+            request_id,
+            request,
+            redact_url(&self.url)
+        );
+        trace!("JSON-RPC request #{} body: {}", request_id, request_json);
+
+        if let Some(cassette) = self.cassette.as_ref().filter(|c| c.is_replaying()) {
+            let interaction = cassette
+                .replay_interaction(
+                    RPC,
+                    &request.to_string(),
+                    &self.url,
+                    &recorded_params.unwrap_or_default(),
+                )
+                .map_err(|err| RpcError::RpcRequestError(err.to_string()))?;
+            debug!(
+                "JSON-RPC response #{} replayed from {}",
+                request_id,
+                cassette.path().display()
+            );
+            if let Some(error) = interaction.error {
+                return Err(replayed_failure(interaction.status, error));
+            }
+            let mut json = interaction.response;
+            let result = match response_error(&json) {
+                Some(err) => Err(err.into()),
+                None => Ok(json["result"].take()),
+            };
+            stats_updater.failed = result.is_err();
+            return result;
+        }
 
-        let mut too_many_requests_retries = 5;
+        let (endpoint, mut credentials) = match &self.auth {
+            Some(provider) => (
+                provider.endpoint(&self.url).map_err(auth_error)?,
+                provider.headers().await.map_err(auth_error)?,
+            ),
+            None => (self.url.clone(), HeaderMap::new()),
+        };
+        // Keep API keys embedded in the URL out of error messages.
+        let secret_endpoint = self.auth.as_ref().map_or(false, |auth| auth.secret_endpoint());
+        let redact = |err: reqwest::Error| {
+            if secret_endpoint {
+                err.without_url()
+            } else {
+                err
+            }
+        };
+        let mut unauthorized_retries = 1;
+        let started = Instant::now();
         loop {
+            stats_updater.bytes_sent += request_json.len() as u64;
             let response = {
                 let client = self.client.clone();
                 let request_json = request_json.clone();
                 client
-                    .post(&self.url)
+                    .post(&endpoint)
                     .header(CONTENT_TYPE, "application/json")
+                    .headers(credentials.clone())
                     .body(request_json)
                     .send()
                     .await
-            }?;
+            };
+            let response = match response {
+                Ok(response) => response,
+                Err(err) => {
+                    if self.retry.retries_error(request, &err) {
+                        if let Some(delay) =
+                            self.next_retry(&mut stats_updater.attempts, started, None)
+                        {
+                            debug!(
+                                "JSON-RPC request #{} failed: {}, attempt {}, pausing for {:?}",
+                                request_id,
+                                redact(err),
+                                stats_updater.attempts,
+                                delay
+                            );
+                            sleep(delay).await;
+                            continue;
+                        }
+                    }
+                    let failure = describe_failure(&err);
+                    self.record(
+                        request_id,
+                        request,
+                        &recorded_params,
+                        0,
+                        Value::Null,
+                        Some(failure),
+                    );
+                    return Err(redact(err).into());
+                }
+            };
+            debug!("JSON-RPC response #{}: {}", request_id, response.status());
 
             if !response.status().is_success() {
-                if response.status() == StatusCode::TOO_MANY_REQUESTS
-                    && too_many_requests_retries > 0
-                {
-                    let mut duration = Duration::from_millis(500);
-                    if let Some(retry_after) = response.headers().get(RETRY_AFTER) {
-                        if let Ok(retry_after) = retry_after.to_str() {
-                            if let Ok(retry_after) = retry_after.parse::<u64>() {
-                                if retry_after < 120 {
-                                    duration = Duration::from_secs(retry_after);
-                                }
-                            }
+                if let (StatusCode::UNAUTHORIZED, Some(provider)) = (response.status(), &self.auth) {
+                    if unauthorized_retries > 0 {
+                        unauthorized_retries -= 1;
+                        if let Some(renewed) =
+                            provider.refresh(&credentials).await.map_err(auth_error)?
+                        {
+                            debug!("Unauthorized: refreshed RPC credentials, retrying");
+                            credentials = renewed;
+                            continue;
+                        }
+                    }
+                }
+                if self.retry.retries_status(request, response.status()) {
+                    let retry_after = response
+                        .headers()
+                        .get(RETRY_AFTER)
+                        .and_then(|value| value.to_str().ok())
+                        .and_then(|value| value.parse::<u64>().ok())
+                        .map(Duration::from_secs);
+                    if let Some(delay) =
+                        self.next_retry(&mut stats_updater.attempts, started, retry_after)
+                    {
+                        debug!(
+                            "JSON-RPC request #{}: server responded with {}, attempt {}, pausing for {:?}",
+                            request_id,
+                            response.status(),
+                            stats_updater.attempts,
+                            delay
+                        );
+                        sleep(delay).await;
+                        if response.status() == StatusCode::TOO_MANY_REQUESTS {
+                            stats_updater.add_rate_limited_time(delay);
                         }
+                        continue;
                     }
+                }
+                let err = redact(response.error_for_status_ref().unwrap_err());
+                let failure = describe_failure(&err);
+                let status = response.status().as_u16();
+                let body = response.text().await.unwrap_or_default();
+                self.record(
+                    request_id,
+                    request,
+                    &recorded_params,
+                    status,
+                    Value::String(body),
+                    Some(failure),
+                );
+                return Err(err.into());
+            }
 
-                    too_many_requests_retries -= 1;
+            let status = response.status().as_u16();
+            let body = match response.bytes().await {
+                Ok(body) => body,
+                Err(err) => {
+                    let failure = describe_failure(&err);
+                    self.record(
+                        request_id,
+                        request,
+                        &recorded_params,
+                        status,
+                        Value::Null,
+                        Some(failure),
+                    );
+                    return Err(redact(err).into());
+                }
+            };
+            stats_updater.bytes_received += body.len() as u64;
+            let mut json = match serde_json::from_slice::<serde_json::Value>(&body) {
+                Ok(json) => json,
+                Err(err) => {
+                    self.record(
+                        request_id,
+                        request,
+                        &recorded_params,
+                        status,
+                        Value::String(String::from_utf8_lossy(&body).into_owned()),
+                        Some(format!("invalid JSON-RPC response: {}", err)),
+                    );
+                    return Err(err.into());
+                }
+            };
+            trace!("JSON-RPC response #{} body: {}", request_id, json);
+            if json["error"]["code"].as_i64()
+                == Some(custom_error::JSON_RPC_SERVER_ERROR_NODE_UNHEALTHY)
+            {
+                if let Some(delay) = self.next_retry(&mut stats_updater.attempts, started, None) {
                     debug!(
-                                "Too many requests: server responded with {:?}, {} retries left, pausing for {:?}",
-                                response, too_many_requests_retries, duration
-                            );
-
-                    sleep(duration).await;
-                    stats_updater.add_rate_limited_time(duration);
+                        "JSON-RPC request #{}: node is unhealthy, attempt {}, pausing for {:?}",
+                        request_id, stats_updater.attempts, delay
+                    );
+                    sleep(delay).await;
                     continue;
                 }
-                return Err(response.error_for_status().unwrap_err().into());
             }
-
-            let mut json = response.json::<serde_json::Value>().await?;
-            if json["error"].is_object() {
-                return match serde_json::from_value::<RpcErrorObject>(json["error"].clone()) {
-                    Ok(rpc_error_object) => {
-                        let data = match rpc_error_object.code {
-                                    custom_error::JSON_RPC_SERVER_ERROR_SEND_TRANSACTION_PREFLIGHT_FAILURE => {
-                                        match serde_json::from_value::<RpcSimulateTransactionResult>(json["error"]["data"].clone()) {
-                                            Ok(data) => RpcResponseErrorData::SendTransactionPreflightFailure(data),
-                                            Err(err) => {
-                                                debug!("Failed to deserialize RpcSimulateTransactionResult: {:?}", err);
-                                                RpcResponseErrorData::Empty
-                                            }
-                                        }
-                                    },
-                                    custom_error::JSON_RPC_SERVER_ERROR_NODE_UNHEALTHY => {
-                                        match serde_json::from_value::<custom_error::NodeUnhealthyErrorData>(json["error"]["data"].clone()) {
-                                            Ok(custom_error::NodeUnhealthyErrorData {num_slots_behind}) => RpcResponseErrorData::NodeUnhealthy {num_slots_behind},
-                                            Err(_err) => {
-                                                RpcResponseErrorData::Empty
-                                            }
-                                        }
-                                    },
-                                    _ => RpcResponseErrorData::Empty
-                                };
-
-                        Err(RpcError::RpcResponseError {
-                            code: rpc_error_object.code,
-                            message: rpc_error_object.message,
-                            data,
-                        }
-                        .into())
-                    }
-                    Err(err) => Err(RpcError::RpcRequestError(format!(
-                        "Failed to deserialize RPC error response: {} [{}]",
-                        serde_json::to_string(&json["error"]).unwrap(),
-                        err
-                    ))
-                    .into()),
-                };
-            }
-            return Ok(json["result"].take());
+            self.record(request_id, request, &recorded_params, status, json.clone(), None);
+            let result = match response_error(&json) {
+                Some(err) => Err(err.into()),
+                None => Ok(json["result"].take()),
+            };
+            stats_updater.failed = result.is_err();
+            return result;
         }
     }
 
@@ -235,6 +625,48 @@ impl RpcSender for HttpSenderWithHeaders {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::token_provider::StaticToken;
+    use solana_client::client_error::ClientErrorKind;
+    use shadow_drive_test_support::{MockResponse, MockServer};
+
+    #[test]
+    fn redacts_credentials() {
+        let mut headers = HeaderMap::new();
+        headers.append(
+            header::AUTHORIZATION,
+            header::HeaderValue::from_static("Bearer secret.jwt.token"),
+        );
+        headers.append(
+            header::CONTENT_TYPE,
+            header::HeaderValue::from_static("application/json"),
+        );
+        let (name, value) = parse_header("X-Tenant: acme").unwrap();
+        headers.append(name, value);
+        // Given without `parse_header`, and so not marked sensitive.
+        headers.append("x-api-key", header::HeaderValue::from_static("k3y"));
+        let (name, value) = parse_header("Content-Type: application/json").unwrap();
+        headers.append(name, value);
+        let redacted = redact_headers(&headers);
+        assert_eq!(
+            redacted,
+            vec![
+                ("authorization".to_string(), "Bearer <redacted>".to_string()),
+                ("content-type".to_string(), "application/json".to_string()),
+                ("content-type".to_string(), "<redacted>".to_string()),
+                ("x-tenant".to_string(), "<redacted>".to_string()),
+                ("x-api-key".to_string(), "<redacted>".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn parses_headers() {
+        let (name, value) = parse_header("x-api-key:  k3y ").unwrap();
+        assert_eq!(name, "x-api-key");
+        assert_eq!(value, "k3y");
+        assert!(parse_header("x-api-key k3y").is_err());
+        assert!(parse_header("bad name: value").is_err());
+    }
 
     #[tokio::test(flavor = "multi_thread")]
     async fn http_sender_on_tokio_multi_thread() {
@@ -251,4 +683,171 @@ mod tests {
             .send(RpcRequest::GetVersion, serde_json::Value::Null)
             .await;
     }
+
+    /// Hands out "old" until refreshed, then "new".
+    struct RotatingToken(std::sync::Mutex<&'static str>, AtomicU64);
+
+    #[async_trait]
+    impl TokenProvider for RotatingToken {
+        async fn token(&self) -> anyhow::Result<String> {
+            Ok(self.0.lock().unwrap().to_string())
+        }
+
+        async fn refresh(&self, _stale: &str) -> anyhow::Result<String> {
+            self.1.fetch_add(1, Ordering::Relaxed);
+            *self.0.lock().unwrap() = "new";
+            Ok("new".to_string())
+        }
+    }
+
+    #[tokio::test]
+    async fn refreshes_token_on_unauthorized() {
+        let server = MockServer::spawn(|request| {
+            if request.header("authorization") == Some("Bearer new") {
+                MockResponse::rpc_result(json!("1.0.0"))
+            } else {
+                MockResponse::new(401, "")
+            }
+        })
+        .unwrap();
+
+        let provider = Arc::new(RotatingToken(
+            std::sync::Mutex::new("old"),
+            AtomicU64::new(0),
+        ));
+        let http_sender =
+            HttpSenderWithHeaders::new(server.url(), None).with_token_provider(provider.clone());
+        let result = http_sender
+            .send(RpcRequest::GetVersion, serde_json::Value::Null)
+            .await
+            .unwrap();
+        assert_eq!(result, "1.0.0");
+        assert_eq!(provider.1.load(Ordering::Relaxed), 1);
+    }
+
+    #[tokio::test]
+    async fn retries_unavailable_nodes() {
+        // Unavailable, then unhealthy, then fine.
+        let server = MockServer::sequence(vec![
+            MockResponse::new(503, ""),
+            MockResponse::rpc_error(-32005, "Node is unhealthy"),
+            MockResponse::rpc_result(json!("1.0.0")),
+        ])
+        .unwrap();
+        let url = server.url();
+
+        let policy = RetryPolicy {
+            base_backoff: Duration::from_millis(1),
+            ..Default::default()
+        };
+        let metrics = Arc::new(TransportMetrics::new());
+        let http_sender = HttpSenderWithHeaders::new(url, None)
+            .with_retry_policy(policy)
+            .with_metrics(metrics.clone());
+        let result = http_sender
+            .send(RpcRequest::GetVersion, serde_json::Value::Null)
+            .await
+            .unwrap();
+        assert_eq!(result, "1.0.0");
+        assert_eq!(
+            http_sender.retry_stats(),
+            RetryStats {
+                retries: 2,
+                retried_requests: 1,
+                exhausted_requests: 0,
+            }
+        );
+        let stats = metrics.snapshot();
+        assert_eq!(
+            (stats[0].method.as_str(), stats[0].requests),
+            ("getVersion", 1)
+        );
+        assert_eq!((stats[0].retries, stats[0].errors), (2, 0));
+        assert!(stats[0].bytes_received > 0);
+
+        let http_sender =
+            HttpSenderWithHeaders::new(url, None).with_retry_policy(RetryPolicy::none());
+        assert!(http_sender
+            .send(RpcRequest::GetVersion, serde_json::Value::Null)
+            .await
+            .is_err());
+        assert_eq!(http_sender.retry_stats(), RetryStats::default());
+    }
+
+    #[tokio::test]
+    async fn does_not_resend_transactions_after_timeouts() {
+        let slow = MockServer::always(
+            MockResponse::rpc_result(json!("signature")).with_delay(Duration::from_millis(500)),
+        )
+        .unwrap();
+        let policy = RetryPolicy {
+            base_backoff: Duration::from_millis(1),
+            ..Default::default()
+        };
+        let http_sender =
+            HttpSenderWithHeaders::new_with_timeout(slow.url(), Duration::from_millis(50), None)
+                .with_retry_policy(policy);
+        let params = json!(["transaction", { "encoding": "base64" }]);
+        assert!(http_sender
+            .send(RpcRequest::SendTransaction, params)
+            .await
+            .is_err());
+        assert_eq!(slow.received().len(), 1);
+        assert!(http_sender
+            .send(RpcRequest::GetVersion, serde_json::Value::Null)
+            .await
+            .is_err());
+        assert_eq!(slow.received().len(), 5);
+    }
+
+    #[tokio::test]
+    async fn records_and_replays() {
+        let server = MockServer::spawn(|request| match request.rpc_method().as_str() {
+            "getVersion" => MockResponse::rpc_result(json!("1.0.0")),
+            _ => MockResponse::new(403, "forbidden"),
+        })
+        .unwrap();
+        // A path-embedded API key, which must stay out of the cassette.
+        let url = format!("{}/secret-key", server.url());
+        let url = url.as_str();
+
+        let path = std::env::temp_dir().join(format!("rpc-{}.jsonl", std::process::id()));
+        let token: Arc<dyn TokenProvider> = Arc::new(StaticToken("secret.jwt".to_string()));
+        let recording = HttpSenderWithHeaders::new(url, None)
+            .with_token_provider(token)
+            .with_cassette(Arc::new(Cassette::record(&path).unwrap()));
+        let version = recording
+            .send(RpcRequest::GetVersion, serde_json::Value::Null)
+            .await;
+        assert_eq!(version.unwrap(), "1.0.0");
+        let slot = recording
+            .send(RpcRequest::GetSlot, serde_json::Value::Null)
+            .await;
+        assert!(slot.is_err());
+        let recorded = std::fs::read_to_string(&path).unwrap();
+        assert!(!recorded.contains("secret"));
+        assert_eq!(recorded.lines().count(), 2);
+
+        // The replay doesn't need the server.
+        let replaying = HttpSenderWithHeaders::new(url, None)
+            .with_cassette(Arc::new(Cassette::replay(&path).unwrap()));
+        let version = replaying
+            .send(RpcRequest::GetVersion, serde_json::Value::Null)
+            .await;
+        assert_eq!(version.unwrap(), "1.0.0");
+        let err = replaying
+            .send(RpcRequest::GetSlot, serde_json::Value::Null)
+            .await
+            .unwrap_err();
+        match err.kind() {
+            ClientErrorKind::Reqwest(err) => assert_eq!(err.status(), Some(StatusCode::FORBIDDEN)),
+            kind => panic!("expected the recorded status, got {:?}", kind),
+        }
+        assert!(replaying
+            .send(RpcRequest::GetBalance, json!(["unrecorded"]))
+            .await
+            .is_err());
+        assert_eq!(server.received().len(), 2);
+        std::fs::remove_file(path).unwrap();
+    }
 }
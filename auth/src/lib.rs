@@ -1,10 +1,36 @@
+pub mod auth_provider;
+pub mod cassette;
+pub mod error;
+pub mod failover_sender;
 pub mod genesysgo_auth;
 pub mod http_sender;
+pub mod metrics;
+pub mod offchain_message;
+pub mod retry;
+pub mod siws;
+pub mod token_cache;
+pub mod token_provider;
 
-pub use http_sender::HttpSenderWithHeaders;
+pub use auth_provider::{
+    AuthProvider, BasicAuth, BearerAuth, HeaderAuth, UrlKeyAuth, UrlKeyPlacement,
+};
+pub use cassette::Cassette;
+pub use error::AuthError;
+pub use failover_sender::FailoverSender;
+pub use http_sender::{HttpSenderWithHeaders, RetryStats};
+pub use metrics::TransportMetrics;
+pub use offchain_message::OffchainMessage;
+pub use retry::RetryPolicy;
+pub use siws::{verify_sign_in, SiwsMessage};
+pub use token_cache::{cached_sign_in, cached_sign_in_with_config, TokenCache};
+pub use token_provider::{GenesysGoTokenProvider, StaticToken, TokenProvider};
 pub use genesysgo_auth::{
     sign_in,
     sign_in_step_1,
     sign_in_step_2,
+    sign_in_with_signature,
+    GenesysGoAuthConfig,
+    SignInFormat,
+    SignedMessage,
     parse_account_id_from_url,
 };
\ No newline at end of file
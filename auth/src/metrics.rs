@@ -0,0 +1,237 @@
+//! Per-method request metrics of a run, shared by every sender and HTTP client
+//! built for it, e.g. for a `--stats` summary or a Prometheus textfile.
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// `transport` label of JSON-RPC requests.
+pub const RPC: &str = "rpc";
+
+/// `transport` label of the CLI's own HTTP requests to the Shadow Drive server
+/// and file host; those the Shadow Drive SDK sends itself aren't recorded.
+pub const DRIVE: &str = "drive";
+
+/// One request, including any retries.
+#[derive(Debug, Default, Clone)]
+pub struct RequestRecord {
+    pub elapsed: Duration,
+    /// Time spent waiting out 429 responses.
+    pub rate_limited: Duration,
+    pub bytes_sent: u64,
+    pub bytes_received: u64,
+    pub retries: u64,
+    pub failed: bool,
+}
+
+#[derive(Debug, Default)]
+struct MethodMetrics {
+    latencies: Vec<Duration>,
+    rate_limited: Duration,
+    errors: u64,
+    retries: u64,
+    bytes_sent: u64,
+    bytes_received: u64,
+}
+
+/// Metrics by transport and method. Every latency is kept, so that exact
+/// percentiles can be given; a run makes at most a few hundred thousand requests.
+#[derive(Debug, Default)]
+pub struct TransportMetrics {
+    methods: Mutex<BTreeMap<(&'static str, String), MethodMetrics>>,
+}
+
+/// Totals of one method, as in [TransportMetrics::snapshot].
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct MethodStats {
+    pub transport: &'static str,
+    pub method: String,
+    pub requests: u64,
+    pub errors: u64,
+    pub retries: u64,
+    #[serde(serialize_with = "seconds")]
+    pub p50: Duration,
+    #[serde(serialize_with = "seconds")]
+    pub p95: Duration,
+    #[serde(serialize_with = "seconds")]
+    pub total: Duration,
+    #[serde(serialize_with = "seconds")]
+    pub rate_limited: Duration,
+    pub bytes_sent: u64,
+    pub bytes_received: u64,
+}
+
+fn seconds<S: serde::Serializer>(duration: &Duration, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_f64(duration.as_secs_f64())
+}
+
+/// The `quantile` of `sorted` latencies, by the nearest-rank method.
+fn percentile(sorted: &[Duration], quantile: f64) -> Duration {
+    if sorted.is_empty() {
+        return Duration::ZERO;
+    }
+    let rank = (quantile * sorted.len() as f64).ceil() as usize;
+    sorted[rank.clamp(1, sorted.len()) - 1]
+}
+
+impl TransportMetrics {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record a request to `method` over `transport`, e.g. [RPC] and `getBalance`.
+    pub fn record(&self, transport: &'static str, method: &str, record: RequestRecord) {
+        let mut methods = self.methods.lock().unwrap();
+        let metrics = methods.entry((transport, method.to_string())).or_default();
+        metrics.latencies.push(record.elapsed);
+        metrics.rate_limited += record.rate_limited;
+        metrics.errors += record.failed as u64;
+        metrics.retries += record.retries;
+        metrics.bytes_sent += record.bytes_sent;
+        metrics.bytes_received += record.bytes_received;
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.methods.lock().unwrap().is_empty()
+    }
+
+    /// Totals so far, by transport and then method.
+    pub fn snapshot(&self) -> Vec<MethodStats> {
+        let methods = self.methods.lock().unwrap();
+        methods
+            .iter()
+            .map(|((transport, method), metrics)| {
+                let mut sorted = metrics.latencies.clone();
+                sorted.sort();
+                MethodStats {
+                    transport,
+                    method: method.clone(),
+                    requests: sorted.len() as u64,
+                    errors: metrics.errors,
+                    retries: metrics.retries,
+                    p50: percentile(&sorted, 0.5),
+                    p95: percentile(&sorted, 0.95),
+                    total: sorted.iter().sum(),
+                    rate_limited: metrics.rate_limited,
+                    bytes_sent: metrics.bytes_sent,
+                    bytes_received: metrics.bytes_received,
+                }
+            })
+            .collect()
+    }
+
+    /// The metrics in the Prometheus text exposition format, e.g. for
+    /// the node exporter's textfile collector.
+    pub fn to_prometheus(&self) -> String {
+        let stats = self.snapshot();
+        let mut out = String::new();
+        let labels = |stat: &MethodStats| {
+            format!(
+                "transport=\"{}\",method=\"{}\"",
+                stat.transport,
+                stat.method.replace('\\', "\\\\").replace('"', "\\\"")
+            )
+        };
+        let mut counter = |name: &str, help: &str, value: fn(&MethodStats) -> u64| {
+            let _ = writeln!(out, "# HELP shadow_drive_{} {}", name, help);
+            let _ = writeln!(out, "# TYPE shadow_drive_{} counter", name);
+            for stat in &stats {
+                let _ = writeln!(
+                    out,
+                    "shadow_drive_{}{{{}}} {}",
+                    name,
+                    labels(stat),
+                    value(stat)
+                );
+            }
+        };
+        counter("requests_total", "Requests sent.", |s| s.requests);
+        counter("request_errors_total", "Requests which failed.", |s| {
+            s.errors
+        });
+        counter("request_retries_total", "Retried attempts.", |s| s.retries);
+        counter("sent_bytes_total", "Request body bytes sent.", |s| {
+            s.bytes_sent
+        });
+        counter(
+            "received_bytes_total",
+            "Response body bytes received.",
+            |s| s.bytes_received,
+        );
+        let name = "shadow_drive_request_duration_seconds";
+        let _ = writeln!(out, "# HELP {} Request latency, including retries.", name);
+        let _ = writeln!(out, "# TYPE {} summary", name);
+        for stat in &stats {
+            let labels = labels(stat);
+            for (quantile, value) in [("0.5", stat.p50), ("0.95", stat.p95)] {
+                let _ = writeln!(
+                    out,
+                    "{}{{{},quantile=\"{}\"}} {}",
+                    name,
+                    labels,
+                    quantile,
+                    value.as_secs_f64()
+                );
+            }
+            let _ = writeln!(
+                out,
+                "{}_sum{{{}}} {}",
+                name,
+                labels,
+                stat.total.as_secs_f64()
+            );
+            let _ = writeln!(out, "{}_count{{{}}} {}", name, labels, stat.requests);
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(millis: u64) -> RequestRecord {
+        RequestRecord {
+            elapsed: Duration::from_millis(millis),
+            bytes_sent: 100,
+            bytes_received: 1000,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn summarizes_by_method() {
+        let metrics = TransportMetrics::new();
+        for millis in 1..=100 {
+            metrics.record(RPC, "getBalance", request(millis));
+        }
+        metrics.record(
+            DRIVE,
+            "get-object-data",
+            RequestRecord {
+                retries: 2,
+                failed: true,
+                ..request(40)
+            },
+        );
+
+        let stats = metrics.snapshot();
+        assert_eq!(stats.len(), 2);
+        let balance = stats.iter().find(|s| s.method == "getBalance").unwrap();
+        assert_eq!(balance.requests, 100);
+        assert_eq!(balance.p50, Duration::from_millis(50));
+        assert_eq!(balance.p95, Duration::from_millis(95));
+        assert_eq!(balance.bytes_received, 100_000);
+        assert_eq!((stats[0].errors, stats[0].retries), (1, 2));
+
+        let text = metrics.to_prometheus();
+        assert!(text.contains(
+            "shadow_drive_requests_total{transport=\"rpc\",method=\"getBalance\"} 100\n"
+        ));
+        assert!(text.contains(
+            "shadow_drive_request_duration_seconds{transport=\"rpc\",method=\"getBalance\",quantile=\"0.95\"} 0.095\n"
+        ));
+        assert!(text.contains("# TYPE shadow_drive_request_duration_seconds summary\n"));
+    }
+}
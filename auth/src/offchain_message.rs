@@ -0,0 +1,178 @@
+//! The Solana off-chain message format, version 0, as signed by the Ledger
+//! Solana app and `solana sign-offchain-message`:
+//!
+//! | Bytes | Field |
+//! |---|---|
+//! | 16 | Signing domain, `"\xffsolana offchain"` |
+//! | 1 | Header version, `0` |
+//! | 1 | [MessageFormat] |
+//! | 2 | Message length, little-endian |
+//! | ..  | The message |
+//!
+//! The signing domain can't begin a transaction, so a signature of an off-chain
+//! message can never be replayed as a transaction signature.
+use anyhow::anyhow;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::{Signature, Signer};
+
+/// Prefix of every serialized off-chain message.
+pub const SIGNING_DOMAIN: &[u8; 16] = b"\xffsolana offchain";
+
+/// Length of the version 0 header: signing domain, version, format and length.
+pub const HEADER_LEN: usize = SIGNING_DOMAIN.len() + 4;
+
+/// Longest message, in bytes.
+pub const MAX_LEN: usize = u16::MAX as usize - HEADER_LEN;
+
+/// Longest message the Ledger Solana app will sign, in bytes:
+/// the header and message must fit in a single packet.
+pub const MAX_LEN_LEDGER: usize = 1232 - HEADER_LEN;
+
+/// Which characters a message may contain. Hardware wallets only sign
+/// [MessageFormat::RestrictedAscii] and [MessageFormat::LimitedUtf8] messages.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MessageFormat {
+    /// Printable ASCII only, up to [MAX_LEN_LEDGER] bytes.
+    RestrictedAscii = 0,
+    /// UTF-8, up to [MAX_LEN_LEDGER] bytes.
+    LimitedUtf8 = 1,
+    /// UTF-8, up to [MAX_LEN] bytes.
+    ExtendedUtf8 = 2,
+}
+
+impl MessageFormat {
+    /// The most restrictive format `message` fits in.
+    fn of(message: &[u8]) -> anyhow::Result<Self> {
+        let utf8 = std::str::from_utf8(message).is_ok();
+        match message.len() {
+            0 => Err(anyhow!("off-chain message is empty")),
+            len if len <= MAX_LEN_LEDGER && is_printable_ascii(message) => {
+                Ok(MessageFormat::RestrictedAscii)
+            }
+            len if len <= MAX_LEN_LEDGER && utf8 => Ok(MessageFormat::LimitedUtf8),
+            len if len <= MAX_LEN && utf8 => Ok(MessageFormat::ExtendedUtf8),
+            len if len <= MAX_LEN => Err(anyhow!("off-chain message is not valid UTF-8")),
+            len => Err(anyhow!(
+                "off-chain message is {} bytes long, at most {} are allowed",
+                len,
+                MAX_LEN
+            )),
+        }
+    }
+}
+
+fn is_printable_ascii(message: &[u8]) -> bool {
+    message.iter().all(|c| (0x20..=0x7e).contains(c))
+}
+
+/// A version 0 off-chain message.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OffchainMessage {
+    format: MessageFormat,
+    message: Vec<u8>,
+}
+
+impl OffchainMessage {
+    /// Wrap `message` in the most restrictive [MessageFormat] it fits in.
+    pub fn new(message: &[u8]) -> anyhow::Result<Self> {
+        Ok(Self {
+            format: MessageFormat::of(message)?,
+            message: message.to_vec(),
+        })
+    }
+
+    pub fn format(&self) -> MessageFormat {
+        self.format
+    }
+
+    pub fn message(&self) -> &[u8] {
+        &self.message
+    }
+
+    /// The bytes which are signed: header and message.
+    pub fn serialize(&self) -> Vec<u8> {
+        let mut data = Vec::with_capacity(HEADER_LEN + self.message.len());
+        data.extend_from_slice(SIGNING_DOMAIN);
+        data.push(0);
+        data.push(self.format as u8);
+        data.extend_from_slice(&(self.message.len() as u16).to_le_bytes());
+        data.extend_from_slice(&self.message);
+        data
+    }
+
+    /// Parse serialized bytes, checking the header against the message.
+    pub fn deserialize(data: &[u8]) -> anyhow::Result<Self> {
+        if data.len() <= HEADER_LEN || !data.starts_with(SIGNING_DOMAIN) {
+            return Err(anyhow!("not an off-chain message"));
+        }
+        let version = data[SIGNING_DOMAIN.len()];
+        if version != 0 {
+            return Err(anyhow!("unsupported off-chain message version {}", version));
+        }
+        let format = data[SIGNING_DOMAIN.len() + 1];
+        let len = u16::from_le_bytes([data[HEADER_LEN - 2], data[HEADER_LEN - 1]]) as usize;
+        let message = &data[HEADER_LEN..];
+        if message.len() != len {
+            return Err(anyhow!(
+                "off-chain message header gives a length of {}, but it is {} bytes long",
+                len,
+                message.len()
+            ));
+        }
+        let parsed = Self::new(message)?;
+        // A message may be declared in a laxer format than the one it fits best.
+        if format < parsed.format as u8 || format > MessageFormat::ExtendedUtf8 as u8 {
+            return Err(anyhow!(
+                "off-chain message does not match its declared format {}",
+                format
+            ));
+        }
+        Ok(Self {
+            format: match format {
+                0 => MessageFormat::RestrictedAscii,
+                1 => MessageFormat::LimitedUtf8,
+                _ => MessageFormat::ExtendedUtf8,
+            },
+            ..parsed
+        })
+    }
+
+    /// Sign the serialized message. Signers which know the off-chain message format,
+    /// such as the Ledger Solana app, display the message before signing it.
+    pub fn sign(&self, signer: &dyn Signer) -> Signature {
+        signer.sign_message(&self.serialize())
+    }
+
+    pub fn verify(&self, pubkey: &Pubkey, signature: &Signature) -> bool {
+        signature.verify(pubkey.as_ref(), &self.serialize())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use solana_sdk::signature::Keypair;
+
+    #[test]
+    fn round_trips_and_verifies() {
+        let ascii = OffchainMessage::new(b"Sign in to GenesysGo Shadow Platform.").unwrap();
+        assert_eq!(ascii.format(), MessageFormat::RestrictedAscii);
+        let data = ascii.serialize();
+        assert_eq!(&data[..HEADER_LEN], b"\xffsolana offchain\x00\x00\x25\x00");
+        assert_eq!(OffchainMessage::deserialize(&data).unwrap(), ascii);
+
+        let multiline = OffchainMessage::new("héllo\nworld".as_bytes()).unwrap();
+        assert_eq!(multiline.format(), MessageFormat::LimitedUtf8);
+        let long = OffchainMessage::new(&[b'a'; MAX_LEN_LEDGER + 1]).unwrap();
+        assert_eq!(long.format(), MessageFormat::ExtendedUtf8);
+        assert!(OffchainMessage::new(b"").is_err());
+        assert!(OffchainMessage::new(&[0xff, 0xfe]).is_err());
+        assert!(OffchainMessage::deserialize(&data[..data.len() - 1]).is_err());
+        assert!(OffchainMessage::deserialize(b"Sign in to GenesysGo Shadow Platform.").is_err());
+
+        let signer = Keypair::new();
+        let signature = multiline.sign(&signer);
+        assert!(multiline.verify(&signer.pubkey(), &signature));
+        assert!(!ascii.verify(&signer.pubkey(), &signature));
+    }
+}
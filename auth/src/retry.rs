@@ -0,0 +1,166 @@
+use rand::Rng;
+use reqwest::StatusCode;
+use solana_client::rpc_request::RpcRequest;
+use std::time::{Duration, Instant};
+
+/// When [HttpSenderWithHeaders](crate::HttpSenderWithHeaders) retries a request:
+/// after connection errors and timeouts, statuses in `retryable_statuses` and
+/// node-unhealthy errors, up to `max_attempts` in all and within `deadline`.
+///
+/// `sendTransaction` is only retried when the node cannot have received it: after
+/// connection errors, `429` and `503`. After a timeout or a gateway error the first
+/// attempt may have landed, and its retry would fail with `AlreadyProcessed`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RetryPolicy {
+    /// Attempts per request, including the first. `1` disables retries.
+    pub max_attempts: u32,
+    /// Delay before the first retry, doubled for each one after.
+    pub base_backoff: Duration,
+    /// Longest delay between attempts, including one asked for with `Retry-After`.
+    pub max_backoff: Duration,
+    /// Fraction of each delay which is randomized, from `0.0` to `1.0`,
+    /// so that clients failing together don't retry together.
+    pub jitter: f64,
+    pub retryable_statuses: Vec<StatusCode>,
+    /// No retry is started past this long after the first attempt.
+    pub deadline: Option<Duration>,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 4,
+            base_backoff: Duration::from_millis(500),
+            max_backoff: Duration::from_secs(10),
+            jitter: 0.2,
+            retryable_statuses: vec![
+                StatusCode::TOO_MANY_REQUESTS,
+                StatusCode::BAD_GATEWAY,
+                StatusCode::SERVICE_UNAVAILABLE,
+                StatusCode::GATEWAY_TIMEOUT,
+            ],
+            deadline: Some(Duration::from_secs(60)),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Never retry.
+    pub fn none() -> Self {
+        Self {
+            max_attempts: 1,
+            ..Default::default()
+        }
+    }
+
+    /// Whether `request` may be retried after a response with `status`.
+    pub fn retries_status(&self, request: RpcRequest, status: StatusCode) -> bool {
+        self.retryable_statuses.contains(&status)
+            && (!is_send(request)
+                || matches!(
+                    status,
+                    StatusCode::TOO_MANY_REQUESTS | StatusCode::SERVICE_UNAVAILABLE
+                ))
+    }
+
+    /// Whether `request`, which failed with `err` before any response, may be retried.
+    pub fn retries_error(&self, request: RpcRequest, err: &reqwest::Error) -> bool {
+        err.is_connect() || (err.is_timeout() && !is_send(request))
+    }
+
+    /// Delay before retry number `retry`, counting from 1, without jitter.
+    pub fn backoff(&self, retry: u32) -> Duration {
+        let factor = 2u32.saturating_pow(retry.saturating_sub(1));
+        self.base_backoff
+            .checked_mul(factor)
+            .map_or(self.max_backoff, |delay| delay.min(self.max_backoff))
+    }
+
+    fn jittered(&self, delay: Duration) -> Duration {
+        let jitter = self.jitter.clamp(0.0, 1.0);
+        if jitter == 0.0 {
+            return delay;
+        }
+        delay.mul_f64(1.0 - jitter * rand::thread_rng().gen::<f64>())
+    }
+
+    /// The delay before retrying, if `attempts` have been made since `started`:
+    /// `retry_after` if the server asked for one, otherwise the backoff.
+    /// `None` once out of attempts or if the retry would start past the deadline.
+    pub fn next_delay(
+        &self,
+        attempts: u32,
+        started: Instant,
+        retry_after: Option<Duration>,
+    ) -> Option<Duration> {
+        if attempts >= self.max_attempts {
+            return None;
+        }
+        let delay = match retry_after {
+            Some(delay) => delay.min(self.max_backoff),
+            None => self.jittered(self.backoff(attempts)),
+        };
+        match self.deadline {
+            Some(deadline) if started.elapsed() + delay > deadline => None,
+            _ => Some(delay),
+        }
+    }
+}
+
+/// Requests which change state if the node receives them.
+fn is_send(request: RpcRequest) -> bool {
+    matches!(request, RpcRequest::SendTransaction)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn backs_off_exponentially() {
+        let policy = RetryPolicy {
+            jitter: 0.0,
+            ..Default::default()
+        };
+        assert_eq!(policy.backoff(1), Duration::from_millis(500));
+        assert_eq!(policy.backoff(3), Duration::from_secs(2));
+        assert_eq!(policy.backoff(10), Duration::from_secs(10));
+        assert_eq!(policy.backoff(u32::MAX), Duration::from_secs(10));
+
+        let started = Instant::now();
+        assert_eq!(
+            policy.next_delay(2, started, None),
+            Some(Duration::from_secs(1))
+        );
+        assert_eq!(
+            policy.next_delay(1, started, Some(Duration::from_secs(300))),
+            Some(Duration::from_secs(10))
+        );
+        assert_eq!(policy.next_delay(4, started, None), None);
+        assert_eq!(RetryPolicy::none().next_delay(1, started, None), None);
+
+        let short = RetryPolicy {
+            deadline: Some(Duration::from_millis(100)),
+            ..policy
+        };
+        assert_eq!(short.next_delay(1, started, None), None);
+
+        let jittered = RetryPolicy::default().next_delay(1, started, None).unwrap();
+        assert!(jittered <= Duration::from_millis(500) && jittered >= Duration::from_millis(400));
+    }
+
+    #[test]
+    fn retries_transactions_only_when_not_received() {
+        let policy = RetryPolicy::default();
+        for status in [
+            StatusCode::TOO_MANY_REQUESTS,
+            StatusCode::SERVICE_UNAVAILABLE,
+        ] {
+            assert!(policy.retries_status(RpcRequest::SendTransaction, status));
+        }
+        for status in [StatusCode::BAD_GATEWAY, StatusCode::GATEWAY_TIMEOUT] {
+            assert!(!policy.retries_status(RpcRequest::SendTransaction, status));
+            assert!(policy.retries_status(RpcRequest::GetBalance, status));
+        }
+    }
+}
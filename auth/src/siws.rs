@@ -0,0 +1,370 @@
+//! Sign-In-With-Solana messages: a structured, human-readable message binding a
+//! sign-in to a domain, a one-time nonce and an issue time, so that a server can
+//! reject signatures meant for another site and signatures replayed later.
+//!
+//! ```text
+//! portal.example.com wants you to sign in with your Solana account:
+//! 7YQ...kqK
+//!
+//! Sign in to GenesysGo Shadow Platform.
+//!
+//! URI: https://portal.example.com
+//! Version: 1
+//! Nonce: 3kG8sVf2pQ1xZ7aL
+//! Issued At: 2023-01-12T09:30:00.000Z
+//! ```
+//!
+//! The message is signed in an [OffchainMessage] envelope, see [SiwsMessage::sign].
+use anyhow::anyhow;
+use chrono::{DateTime, SecondsFormat, SubsecRound, Utc};
+use rand::distributions::Alphanumeric;
+use rand::Rng;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::{Signature, Signer};
+use std::fmt;
+use std::str::FromStr;
+use std::time::Duration;
+
+use crate::offchain_message::OffchainMessage;
+
+const PREAMBLE: &str = " wants you to sign in with your Solana account:";
+
+/// Issue times this far in the future are accepted, for clock skew.
+const CLOCK_SKEW: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SiwsMessage {
+    /// Host requesting the sign-in, e.g. `portal.genesysgo.net`.
+    pub domain: String,
+    pub address: Pubkey,
+    /// What the user is signing in to, shown by the wallet.
+    pub statement: Option<String>,
+    pub uri: Option<String>,
+    pub version: String,
+    pub chain_id: Option<String>,
+    /// At least 8 alphanumeric characters, never to be accepted twice.
+    pub nonce: String,
+    pub issued_at: DateTime<Utc>,
+    pub expiration_time: Option<DateTime<Utc>>,
+}
+
+/// A random 16-character alphanumeric nonce.
+pub fn generate_nonce() -> String {
+    rand::thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(16)
+        .collect()
+}
+
+fn timestamp(time: &DateTime<Utc>) -> String {
+    time.to_rfc3339_opts(SecondsFormat::Millis, true)
+}
+
+fn parse_timestamp(field: &str, value: &str) -> anyhow::Result<DateTime<Utc>> {
+    DateTime::parse_from_rfc3339(value)
+        .map(|time| time.with_timezone(&Utc))
+        .map_err(|e| anyhow!("invalid {} {:?}: {}", field, value, e))
+}
+
+impl SiwsMessage {
+    /// A message for `address` to sign in to `domain`, issued now with a fresh nonce.
+    pub fn new(domain: &str, address: Pubkey) -> Self {
+        Self {
+            domain: domain.to_string(),
+            address,
+            statement: None,
+            uri: None,
+            version: "1".to_string(),
+            chain_id: None,
+            nonce: generate_nonce(),
+            // The message only holds milliseconds.
+            issued_at: Utc::now().trunc_subsecs(3),
+            expiration_time: None,
+        }
+    }
+
+    pub fn with_statement(mut self, statement: &str) -> Self {
+        self.statement = Some(statement.to_string());
+        self
+    }
+
+    pub fn with_uri(mut self, uri: &str) -> Self {
+        self.uri = Some(uri.to_string());
+        self
+    }
+
+    /// Let the message expire `ttl` after it was issued.
+    pub fn expires_after(mut self, ttl: Duration) -> Self {
+        self.expiration_time = chrono::Duration::from_std(ttl)
+            .ok()
+            .map(|ttl| self.issued_at + ttl);
+        self
+    }
+
+    /// The envelope which is signed.
+    pub fn to_offchain_message(&self) -> anyhow::Result<OffchainMessage> {
+        OffchainMessage::new(self.to_string().as_bytes())
+    }
+
+    /// Sign the message in an [OffchainMessage] envelope, returning the signed bytes
+    /// and the signature, for the server to check with [verify_sign_in].
+    pub fn sign(&self, signer: &dyn Signer) -> anyhow::Result<(Vec<u8>, Signature)> {
+        let envelope = self.to_offchain_message()?;
+        Ok((envelope.serialize(), envelope.sign(signer)))
+    }
+
+    /// Check the message was meant for `domain` and is neither expired nor older
+    /// than `max_age` at `now`. Checking that the nonce wasn't used before is up to the caller.
+    pub fn validate(
+        &self,
+        domain: &str,
+        now: DateTime<Utc>,
+        max_age: Duration,
+    ) -> anyhow::Result<()> {
+        if !self.domain.eq_ignore_ascii_case(domain) {
+            return Err(anyhow!(
+                "sign-in message is for {}, not {}",
+                self.domain,
+                domain
+            ));
+        }
+        if self.nonce.len() < 8 || !self.nonce.chars().all(|c| c.is_ascii_alphanumeric()) {
+            return Err(anyhow!(
+                "sign-in message nonce must be at least 8 alphanumeric characters"
+            ));
+        }
+        let skew = chrono::Duration::from_std(CLOCK_SKEW).expect("clock skew fits");
+        if self.issued_at > now + skew {
+            return Err(anyhow!("sign-in message is issued in the future"));
+        }
+        let max_age = chrono::Duration::from_std(max_age)
+            .map_err(|_| anyhow!("maximum sign-in message age is out of range"))?;
+        if self.issued_at + max_age < now {
+            return Err(anyhow!(
+                "sign-in message was issued at {}, too long ago",
+                timestamp(&self.issued_at)
+            ));
+        }
+        if matches!(self.expiration_time, Some(expiration) if expiration <= now) {
+            return Err(anyhow!("sign-in message has expired"));
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for SiwsMessage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}{}\n{}\n", self.domain, PREAMBLE, self.address)?;
+        if let Some(statement) = &self.statement {
+            write!(f, "\n{}\n", statement)?;
+        }
+        writeln!(f)?;
+        if let Some(uri) = &self.uri {
+            writeln!(f, "URI: {}", uri)?;
+        }
+        writeln!(f, "Version: {}", self.version)?;
+        if let Some(chain_id) = &self.chain_id {
+            writeln!(f, "Chain ID: {}", chain_id)?;
+        }
+        writeln!(f, "Nonce: {}", self.nonce)?;
+        write!(f, "Issued At: {}", timestamp(&self.issued_at))?;
+        if let Some(expiration_time) = &self.expiration_time {
+            write!(f, "\nExpiration Time: {}", timestamp(expiration_time))?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for SiwsMessage {
+    type Err = anyhow::Error;
+
+    fn from_str(message: &str) -> anyhow::Result<Self> {
+        let mut lines = message.split('\n');
+        let domain = lines
+            .next()
+            .and_then(|line| line.strip_suffix(PREAMBLE))
+            .filter(|domain| !domain.is_empty())
+            .ok_or_else(|| anyhow!("not a Sign-In-With-Solana message"))?;
+        let address = lines
+            .next()
+            .ok_or_else(|| anyhow!("sign-in message has no address"))?;
+        let address = Pubkey::from_str(address)
+            .map_err(|_| anyhow!("invalid sign-in message address {:?}", address))?;
+
+        let mut statement = None;
+        let mut uri = None;
+        let mut version = None;
+        let mut chain_id = None;
+        let mut nonce = None;
+        let mut issued_at = None;
+        let mut expiration_time = None;
+        let mut fields_started = false;
+        for line in lines {
+            match line.split_once(": ") {
+                Some(("URI", value)) => uri = Some(value.to_string()),
+                Some(("Version", value)) => version = Some(value.to_string()),
+                Some(("Chain ID", value)) => chain_id = Some(value.to_string()),
+                Some(("Nonce", value)) => nonce = Some(value.to_string()),
+                Some(("Issued At", value)) => {
+                    issued_at = Some(parse_timestamp("issue time", value)?)
+                }
+                Some(("Expiration Time", value)) => {
+                    expiration_time = Some(parse_timestamp("expiration time", value)?)
+                }
+                _ if line.is_empty() && !fields_started => continue,
+                _ if !fields_started && statement.is_none() => {
+                    statement = Some(line.to_string());
+                    continue;
+                }
+                _ => return Err(anyhow!("unexpected line in sign-in message: {:?}", line)),
+            }
+            fields_started = true;
+        }
+        let version = version.ok_or_else(|| anyhow!("sign-in message has no version"))?;
+        if version != "1" {
+            return Err(anyhow!("unsupported sign-in message version {:?}", version));
+        }
+        let parsed = Self {
+            domain: domain.to_string(),
+            address,
+            statement,
+            uri,
+            version,
+            chain_id,
+            nonce: nonce.ok_or_else(|| anyhow!("sign-in message has no nonce"))?,
+            issued_at: issued_at.ok_or_else(|| anyhow!("sign-in message has no issue time"))?,
+            expiration_time,
+        };
+        Ok(parsed)
+    }
+}
+
+/// Server side of a sign-in: check `signature` of the off-chain message `signed`
+/// by `signer`, then parse and [validate](SiwsMessage::validate) the message it holds.
+/// Returns the message, whose nonce the caller should remember until it would expire.
+pub fn verify_sign_in(
+    signed: &[u8],
+    signature: &Signature,
+    signer: &Pubkey,
+    domain: &str,
+    max_age: Duration,
+) -> anyhow::Result<SiwsMessage> {
+    let envelope = OffchainMessage::deserialize(signed)?;
+    if !envelope.verify(signer, signature) {
+        return Err(anyhow!(
+            "invalid signature of the sign-in message by {}",
+            signer
+        ));
+    }
+    let message: SiwsMessage = std::str::from_utf8(envelope.message())?.parse()?;
+    if message.address != *signer {
+        return Err(anyhow!(
+            "sign-in message is for {}, but was signed by {}",
+            message.address,
+            signer
+        ));
+    }
+    message.validate(domain, Utc::now(), max_age)?;
+    Ok(message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use solana_sdk::signature::Keypair;
+
+    #[test]
+    fn signs_and_verifies() {
+        let signer = Keypair::new();
+        let message = SiwsMessage::new("portal.example.com", signer.pubkey())
+            .with_statement("Sign in to GenesysGo Shadow Platform.")
+            .with_uri("https://portal.example.com")
+            .expires_after(Duration::from_secs(300));
+        let text = message.to_string();
+        assert!(text.starts_with(&format!(
+            "portal.example.com wants you to sign in with your Solana account:\n{}\n\n\
+            Sign in to GenesysGo Shadow Platform.\n\nURI: https://portal.example.com\nVersion: 1\n",
+            signer.pubkey()
+        )));
+        assert_eq!(text.parse::<SiwsMessage>().unwrap(), message);
+
+        let (signed, signature) = message.sign(&signer).unwrap();
+        let max_age = Duration::from_secs(60);
+        assert!(verify_sign_in(
+            &signed,
+            &signature,
+            &signer.pubkey(),
+            "portal.example.com",
+            max_age
+        )
+        .is_ok());
+        assert!(verify_sign_in(
+            &signed,
+            &signature,
+            &signer.pubkey(),
+            "evil.example.com",
+            max_age
+        )
+        .is_err());
+        let other = Keypair::new();
+        assert!(verify_sign_in(
+            &signed,
+            &other.sign_message(&signed),
+            &other.pubkey(),
+            "portal.example.com",
+            max_age
+        )
+        .is_err());
+        assert!(verify_sign_in(
+            text.as_bytes(),
+            &signature,
+            &signer.pubkey(),
+            "portal.example.com",
+            max_age
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn rejects_stale_messages() {
+        let message = SiwsMessage::new("portal.example.com", Pubkey::new_unique());
+        let max_age = Duration::from_secs(60);
+        let now = message.issued_at;
+        assert!(message.validate("portal.example.com", now, max_age).is_ok());
+        assert!(message
+            .validate(
+                "portal.example.com",
+                now + chrono::Duration::minutes(2),
+                max_age
+            )
+            .is_err());
+        assert!(message
+            .validate(
+                "portal.example.com",
+                now - chrono::Duration::minutes(2),
+                max_age
+            )
+            .is_err());
+        let expiring = message.clone().expires_after(Duration::from_secs(10));
+        assert!(expiring
+            .validate(
+                "portal.example.com",
+                now + chrono::Duration::seconds(30),
+                max_age
+            )
+            .is_err());
+        let text = message.to_string();
+        assert!(text
+            .replace("Nonce: ", "Nonce: x")
+            .parse::<SiwsMessage>()
+            .is_ok());
+        assert!(text
+            .replace("Version: 1", "Version: 2")
+            .parse::<SiwsMessage>()
+            .is_err());
+        assert!(text
+            .replace("Issued At", "Issued")
+            .parse::<SiwsMessage>()
+            .is_err());
+    }
+}
@@ -0,0 +1,283 @@
+//! Keeps GenesysGo RPC tokens on disk between runs, so that a signature
+//! (and with it, a hardware wallet or passphrase prompt) is only needed
+//! when the cached token is about to expire.
+use crate::genesysgo_auth::{GenesysGoAuthConfig, DEFAULT_PORTAL_URL};
+use log::debug;
+use serde::{Deserialize, Serialize};
+use solana_sdk::hash::hash;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::Signer;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Tokens are renewed when they have less than this left before expiring.
+pub const RENEW_BEFORE: Duration = Duration::from_secs(5 * 60);
+
+#[derive(Debug, Serialize, Deserialize)]
+struct CachedToken {
+    token: String,
+}
+
+/// Directory of cached tokens, one file per GenesysGo portal, account ID and signer.
+#[derive(Debug, Clone)]
+pub struct TokenCache {
+    dir: PathBuf,
+    /// The portal which issued the tokens, unless it is the production one.
+    portal_url: Option<String>,
+}
+
+impl TokenCache {
+    /// Tokens issued by the production portal, kept in `dir`.
+    pub fn new(dir: PathBuf) -> Self {
+        Self {
+            dir,
+            portal_url: None,
+        }
+    }
+
+    /// The same directory, for tokens issued by the portal at `portal_url`. Tokens
+    /// of each portal are kept apart, so that one from staging or a mock is never
+    /// sent to production, nor the other way round.
+    pub fn for_portal(&self, portal_url: &str) -> Self {
+        let portal_url = portal_url.trim_end_matches('/');
+        Self {
+            dir: self.dir.clone(),
+            portal_url: (portal_url != DEFAULT_PORTAL_URL).then(|| portal_url.to_string()),
+        }
+    }
+
+    /// `<config dir>/shadow-drive-cli/tokens`, e.g. `~/.config/shadow-drive-cli/tokens` on Linux.
+    pub fn default_dir() -> Option<PathBuf> {
+        dirs_next::config_dir().map(|dir| dir.join("shadow-drive-cli").join("tokens"))
+    }
+
+    fn path(&self, account_id: &str, pubkey: &Pubkey) -> PathBuf {
+        let account_id: String = account_id
+            .chars()
+            .filter(|c| c.is_ascii_alphanumeric() || *c == '-' || *c == '_')
+            .collect();
+        let portal = match &self.portal_url {
+            Some(url) => {
+                let digest = hash(url.as_bytes());
+                let hex: String = digest.as_ref()[..8]
+                    .iter()
+                    .map(|byte| format!("{:02x}", byte))
+                    .collect();
+                format!("-{}", hex)
+            }
+            None => String::new(),
+        };
+        self.dir.join(format!(
+            "genesysgo-{}-{}{}.json",
+            account_id, pubkey, portal
+        ))
+    }
+
+    /// The cached token, whether or not it is still fresh.
+    pub fn load(&self, account_id: &str, pubkey: &Pubkey) -> Option<String> {
+        let bytes = fs::read(self.path(account_id, pubkey)).ok()?;
+        let cached: CachedToken = serde_json::from_slice(&bytes).ok()?;
+        Some(cached.token)
+    }
+
+    /// Write the token readable by the current user only.
+    pub fn store(&self, account_id: &str, pubkey: &Pubkey, token: &str) -> anyhow::Result<()> {
+        create_private_dir(&self.dir)?;
+        let path = self.path(account_id, pubkey);
+        let tmp = path.with_extension("json.tmp");
+        let mut file = private_file(&tmp)?;
+        file.write_all(&serde_json::to_vec(&CachedToken {
+            token: token.to_string(),
+        })?)?;
+        file.sync_all()?;
+        fs::rename(&tmp, &path)?;
+        Ok(())
+    }
+
+    /// Remove the cached token. Returns whether there was one.
+    pub fn clear(&self, account_id: &str, pubkey: &Pubkey) -> anyhow::Result<bool> {
+        match fs::remove_file(self.path(account_id, pubkey)) {
+            Ok(()) => Ok(true),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(false),
+            Err(err) => Err(err.into()),
+        }
+    }
+}
+
+#[cfg(unix)]
+fn create_private_dir(dir: &Path) -> std::io::Result<()> {
+    use std::os::unix::fs::DirBuilderExt;
+    fs::DirBuilder::new().recursive(true).mode(0o700).create(dir)
+}
+
+#[cfg(not(unix))]
+fn create_private_dir(dir: &Path) -> std::io::Result<()> {
+    fs::create_dir_all(dir)
+}
+
+#[cfg(unix)]
+fn private_file(path: &Path) -> std::io::Result<fs::File> {
+    use std::os::unix::fs::OpenOptionsExt;
+    fs::OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .mode(0o600)
+        .open(path)
+}
+
+#[cfg(not(unix))]
+fn private_file(path: &Path) -> std::io::Result<fs::File> {
+    fs::OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open(path)
+}
+
+/// The `exp` claim of a JWT, in seconds since the Unix epoch.
+/// The signature is not verified, this is only used to decide when to renew.
+pub fn jwt_expiry(token: &str) -> Option<u64> {
+    let payload = token.split('.').nth(1)?;
+    let bytes = base64::decode_config(payload, base64::URL_SAFE_NO_PAD).ok()?;
+    let claims: serde_json::Value = serde_json::from_slice(&bytes).ok()?;
+    claims["exp"].as_u64()
+}
+
+/// Whether the token stays valid for at least [RENEW_BEFORE] after `now`.
+/// Tokens without an `exp` claim are never considered fresh.
+pub fn is_fresh(token: &str, now: SystemTime) -> bool {
+    let now = now
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    match jwt_expiry(token) {
+        Some(exp) => exp > now + RENEW_BEFORE.as_secs(),
+        None => false,
+    }
+}
+
+/// Like [sign_in](crate::sign_in), but reuses a fresh token from the cache and
+/// stores newly acquired ones.
+pub async fn cached_sign_in(
+    signer: &dyn Signer,
+    account_id: &str,
+    cache: &TokenCache,
+) -> anyhow::Result<String> {
+    cached_sign_in_with_config(&GenesysGoAuthConfig::default(), signer, account_id, cache).await
+}
+
+/// Like [GenesysGoAuthConfig::sign_in], but reuses a fresh token from the cache
+/// of `config`'s portal and stores newly acquired ones.
+pub async fn cached_sign_in_with_config(
+    config: &GenesysGoAuthConfig,
+    signer: &dyn Signer,
+    account_id: &str,
+    cache: &TokenCache,
+) -> anyhow::Result<String> {
+    let pubkey = signer.pubkey();
+    let token = cache
+        .for_portal(&config.portal_url)
+        .load(account_id, &pubkey);
+    if let Some(token) = token {
+        if is_fresh(&token, SystemTime::now()) {
+            debug!("Using cached GenesysGo token for {}", pubkey);
+            return Ok(token);
+        }
+    }
+    refresh_with_config(config, signer, account_id, cache).await
+}
+
+/// Sign in again and replace the cached token.
+pub async fn refresh(
+    signer: &dyn Signer,
+    account_id: &str,
+    cache: &TokenCache,
+) -> anyhow::Result<String> {
+    refresh_with_config(&GenesysGoAuthConfig::default(), signer, account_id, cache).await
+}
+
+/// Sign in to `config`'s portal again and replace its cached token.
+pub async fn refresh_with_config(
+    config: &GenesysGoAuthConfig,
+    signer: &dyn Signer,
+    account_id: &str,
+    cache: &TokenCache,
+) -> anyhow::Result<String> {
+    let token = config.sign_in(signer, account_id).await?;
+    cache
+        .for_portal(&config.portal_url)
+        .store(account_id, &signer.pubkey(), &token)?;
+    Ok(token)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn jwt(exp: u64) -> String {
+        let payload = serde_json::json!({ "sub": "1", "exp": exp }).to_string();
+        format!(
+            "e30.{}.c2ln",
+            base64::encode_config(payload, base64::URL_SAFE_NO_PAD)
+        )
+    }
+
+    #[test]
+    fn renews_shortly_before_expiry() {
+        let now = UNIX_EPOCH + Duration::from_secs(1_000_000);
+        assert_eq!(jwt_expiry(&jwt(1_003_600)), Some(1_003_600));
+        assert!(is_fresh(&jwt(1_003_600), now));
+        assert!(!is_fresh(&jwt(1_000_060), now));
+        assert!(!is_fresh(&jwt(999_000), now));
+        assert!(!is_fresh("not a jwt", now));
+    }
+
+    #[test]
+    fn stores_private_tokens() {
+        let dir = std::env::temp_dir().join(format!("token-cache-test-{}", std::process::id()));
+        let cache = TokenCache::new(dir.clone());
+        let pubkey = Pubkey::new_unique();
+        assert_eq!(cache.load("account", &pubkey), None);
+        cache.store("account", &pubkey, "token").unwrap();
+        assert_eq!(cache.load("account", &pubkey), Some("token".to_string()));
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = fs::metadata(cache.path("account", &pubkey))
+                .unwrap()
+                .permissions()
+                .mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+        assert!(cache.clear("account", &pubkey).unwrap());
+        assert!(!cache.clear("account", &pubkey).unwrap());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn keeps_portals_apart() {
+        let dir = std::env::temp_dir().join(format!("token-cache-portals-{}", std::process::id()));
+        let cache = TokenCache::new(dir.clone());
+        let pubkey = Pubkey::new_unique();
+        cache.store("account", &pubkey, "production").unwrap();
+        let staging = cache.for_portal("https://staging.example.com/");
+        assert_eq!(staging.load("account", &pubkey), None);
+        staging.store("account", &pubkey, "staging").unwrap();
+        assert_eq!(
+            cache
+                .for_portal("https://staging.example.com")
+                .load("account", &pubkey)
+                .as_deref(),
+            Some("staging")
+        );
+        let production = cache.for_portal(&format!("{}/", DEFAULT_PORTAL_URL));
+        assert_eq!(
+            production.load("account", &pubkey).as_deref(),
+            Some("production")
+        );
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
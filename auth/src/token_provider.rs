@@ -0,0 +1,159 @@
+//! Bearer tokens which can be renewed while a client is in use.
+//!
+//! [HttpSenderWithHeaders](crate::HttpSenderWithHeaders) asks its [TokenProvider]
+//! for a token before each request. When the RPC endpoint answers 401 Unauthorized,
+//! the sender calls [TokenProvider::refresh] and retries the request once.
+use crate::genesysgo_auth::GenesysGoAuthConfig;
+use crate::token_cache::{is_fresh, TokenCache};
+use async_trait::async_trait;
+use log::{debug, warn};
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::Signer;
+use std::sync::Arc;
+use std::time::SystemTime;
+use tokio::sync::{Mutex, RwLock};
+
+#[async_trait]
+pub trait TokenProvider: Send + Sync {
+    /// The token to send with the next request, acquiring one if needed.
+    async fn token(&self) -> anyhow::Result<String>;
+
+    /// Called when the server rejected `stale`. Returns a token to retry with.
+    /// Implementations must tolerate concurrent calls for the same stale token.
+    async fn refresh(&self, stale: &str) -> anyhow::Result<String>;
+}
+
+/// A fixed token, e.g. passed with `--auth <JWT>`. It cannot be refreshed.
+pub struct StaticToken(pub String);
+
+#[async_trait]
+impl TokenProvider for StaticToken {
+    async fn token(&self) -> anyhow::Result<String> {
+        Ok(self.0.clone())
+    }
+
+    async fn refresh(&self, _stale: &str) -> anyhow::Result<String> {
+        Err(anyhow::anyhow!(
+            "the RPC endpoint rejected the supplied auth token, and it cannot be renewed"
+        ))
+    }
+}
+
+/// Resolves the signer again whenever a new sign-in is needed.
+/// Signers such as hardware wallets aren't `Send`, so one can't be held by
+/// a sender shared across threads. It is only used synchronously, to sign.
+pub type SignerLoader = Arc<dyn Fn() -> anyhow::Result<Box<dyn Signer>> + Send + Sync>;
+
+/// Signs in to GenesysGo when a token is first needed and again whenever the
+/// current one is rejected, going through the [TokenCache] if there is one.
+pub struct GenesysGoTokenProvider {
+    account_id: String,
+    pubkey: Pubkey,
+    load_signer: SignerLoader,
+    cache: Option<TokenCache>,
+    config: GenesysGoAuthConfig,
+    current: RwLock<Option<String>>,
+    /// Held while signing in, so concurrent refreshes sign in only once.
+    sign_in_lock: Mutex<()>,
+}
+
+impl GenesysGoTokenProvider {
+    pub fn new(
+        account_id: String,
+        pubkey: Pubkey,
+        load_signer: SignerLoader,
+        cache: Option<TokenCache>,
+    ) -> Self {
+        Self {
+            account_id,
+            pubkey,
+            load_signer,
+            cache,
+            config: GenesysGoAuthConfig::default(),
+            current: RwLock::new(None),
+            sign_in_lock: Mutex::new(()),
+        }
+    }
+
+    /// Sign in through another portal or with another message than the defaults.
+    /// Tokens are cached per portal.
+    pub fn with_config(mut self, config: GenesysGoAuthConfig) -> Self {
+        self.cache = self.cache.map(|cache| cache.for_portal(&config.portal_url));
+        self.config = config;
+        self
+    }
+
+    async fn sign_in(&self) -> anyhow::Result<String> {
+        // Sign before any await, the signer must not be held across one.
+        let signed = {
+            let signer = (self.load_signer)()?;
+            if signer.pubkey() != self.pubkey {
+                return Err(anyhow::anyhow!(
+                    "signer changed from {} to {} during GenesysGo sign-in",
+                    self.pubkey,
+                    signer.pubkey()
+                ));
+            }
+            self.config.sign_message(signer.as_ref())?
+        };
+        let token = self
+            .config
+            .sign_in_with_signature(&self.pubkey, &signed, &self.account_id)
+            .await?;
+        if let Some(cache) = &self.cache {
+            if let Err(err) = cache.store(&self.account_id, &self.pubkey, &token) {
+                warn!("Could not cache GenesysGo token: {}", err);
+            }
+        }
+        *self.current.write().await = Some(token.clone());
+        Ok(token)
+    }
+}
+
+#[async_trait]
+impl TokenProvider for GenesysGoTokenProvider {
+    async fn token(&self) -> anyhow::Result<String> {
+        if let Some(token) = self.current.read().await.clone() {
+            return Ok(token);
+        }
+        let _guard = self.sign_in_lock.lock().await;
+        if let Some(token) = self.current.read().await.clone() {
+            return Ok(token);
+        }
+        let cached = self
+            .cache
+            .as_ref()
+            .and_then(|cache| cache.load(&self.account_id, &self.pubkey))
+            .filter(|token| is_fresh(token, SystemTime::now()));
+        if let Some(token) = cached {
+            debug!("Using cached GenesysGo token for {}", self.pubkey);
+            *self.current.write().await = Some(token.clone());
+            return Ok(token);
+        }
+        self.sign_in().await
+    }
+
+    async fn refresh(&self, stale: &str) -> anyhow::Result<String> {
+        let _guard = self.sign_in_lock.lock().await;
+        if let Some(token) = self.current.read().await.clone() {
+            if token != stale {
+                // Another request already renewed it.
+                return Ok(token);
+            }
+        }
+        debug!("GenesysGo token rejected, signing in again");
+        self.sign_in().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn static_token_cannot_refresh() {
+        let provider = StaticToken("jwt".to_string());
+        assert_eq!(provider.token().await.unwrap(), "jwt");
+        assert!(provider.refresh("jwt").await.is_err());
+    }
+}
@@ -0,0 +1,394 @@
+use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind};
+use crossterm::execute;
+use crossterm::terminal::{
+    disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen,
+};
+use ratatui::backend::{Backend, CrosstermBackend};
+use ratatui::layout::{Constraint, Direction, Layout};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::widgets::{Block, Borders, Gauge, List, ListItem, ListState, Paragraph, Wrap};
+use ratatui::{Frame, Terminal};
+use shadow_drive_cli::client::DriveHttpClient;
+use shadow_drive_cli::migration::download_file;
+use shadow_drive_cli::storage_accounts::{
+    owned_storage_accounts, storage_account_capacity, storage_account_name,
+};
+use shadow_drive_cli::{
+    get_object_data_json, get_text, last_modified, list_objects, local_download_path,
+    process_shadow_api_response, save_new_file,
+};
+use shadow_drive_rust::models::storage_acct::StorageAcct;
+use shadow_drive_rust::ShadowDriveClient;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::Signer;
+use std::collections::BTreeMap;
+use std::io::{stdout, Stdout};
+
+const KEYS: &str = "↑/↓ move  ←/→ switch pane  ENTER open  p preview  \
+d mark delete  s mark download  x apply marks  r refresh  q quit";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Pane {
+    Accounts,
+    Files,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Mark {
+    Delete,
+    Download,
+}
+
+struct App {
+    /// Fetches object metadata, previews and downloads.
+    http_client: DriveHttpClient,
+    accounts: Vec<(Pubkey, StorageAcct)>,
+    account_list: ListState,
+    /// Bytes used by the open storage account, if known.
+    used: Option<u64>,
+    files: Vec<String>,
+    file_list: ListState,
+    marks: BTreeMap<String, Mark>,
+    pane: Pane,
+    /// Object metadata or text preview of the selected file.
+    detail: String,
+    status: String,
+    /// Set while waiting for the user to confirm pending deletions.
+    confirming: bool,
+}
+
+impl App {
+    fn open_account(&self) -> Option<&(Pubkey, StorageAcct)> {
+        self.account_list.selected().and_then(|i| self.accounts.get(i))
+    }
+
+    fn selected_file(&self) -> Option<&String> {
+        self.file_list.selected().and_then(|i| self.files.get(i))
+    }
+
+    fn move_selection(&mut self, down: bool) {
+        let (state, len) = match self.pane {
+            Pane::Accounts => (&mut self.account_list, self.accounts.len()),
+            Pane::Files => (&mut self.file_list, self.files.len()),
+        };
+        if len == 0 {
+            return;
+        }
+        let next = match (state.selected(), down) {
+            (None, _) => 0,
+            (Some(i), true) => (i + 1).min(len - 1),
+            (Some(i), false) => i.saturating_sub(1),
+        };
+        state.select(Some(next));
+    }
+
+    fn toggle_mark(&mut self, mark: Mark) {
+        if let Some(file) = self.selected_file().cloned() {
+            if self.marks.get(&file) == Some(&mark) {
+                self.marks.remove(&file);
+            } else {
+                self.marks.insert(file, mark);
+            }
+        }
+    }
+}
+
+/// Browse the signer's storage accounts and files in a full-screen terminal UI.
+pub async fn run<T: Signer>(
+    client: &ShadowDriveClient<T>,
+    http_client: DriveHttpClient,
+    owner: Pubkey,
+) -> anyhow::Result<()> {
+    let mut app = App {
+        http_client,
+        accounts: owned_storage_accounts(client, &owner, &Default::default()).await?,
+        account_list: ListState::default(),
+        used: None,
+        files: vec![],
+        file_list: ListState::default(),
+        marks: BTreeMap::new(),
+        pane: Pane::Accounts,
+        detail: String::new(),
+        status: KEYS.to_string(),
+        confirming: false,
+    };
+    if !app.accounts.is_empty() {
+        app.account_list.select(Some(0));
+        load_files(client, &mut app).await;
+    }
+
+    enable_raw_mode()?;
+    execute!(stdout(), EnterAlternateScreen)?;
+    let mut terminal = Terminal::new(CrosstermBackend::new(stdout()))?;
+    let result = event_loop(client, owner, &mut terminal, &mut app).await;
+    // Always hand the terminal back in a usable state, even on error.
+    disable_raw_mode()?;
+    execute!(terminal.backend_mut(), LeaveAlternateScreen)?;
+    terminal.show_cursor()?;
+    result
+}
+
+async fn event_loop<T: Signer>(
+    client: &ShadowDriveClient<T>,
+    owner: Pubkey,
+    terminal: &mut Terminal<CrosstermBackend<Stdout>>,
+    app: &mut App,
+) -> anyhow::Result<()> {
+    loop {
+        terminal.draw(|f| draw(f, app))?;
+        let key = match event::read()? {
+            Event::Key(key @ KeyEvent {
+                kind: KeyEventKind::Press,
+                ..
+            }) => key,
+            _ => continue,
+        };
+        if app.confirming {
+            app.confirming = false;
+            if key.code == KeyCode::Char('y') {
+                apply_marks(client, app).await;
+            } else {
+                app.status = "Cancelled".to_string();
+            }
+            continue;
+        }
+        match key.code {
+            KeyCode::Char('q') | KeyCode::Esc => return Ok(()),
+            KeyCode::Up | KeyCode::Char('k') => app.move_selection(false),
+            KeyCode::Down | KeyCode::Char('j') => app.move_selection(true),
+            KeyCode::Left | KeyCode::Right | KeyCode::Tab => {
+                app.pane = match app.pane {
+                    Pane::Accounts => Pane::Files,
+                    Pane::Files => Pane::Accounts,
+                }
+            }
+            KeyCode::Enter => match app.pane {
+                Pane::Accounts => {
+                    load_files(client, app).await;
+                    app.pane = Pane::Files;
+                }
+                Pane::Files => load_object_data(app).await,
+            },
+            KeyCode::Char('p') => preview_text(app).await,
+            KeyCode::Char('d') => app.toggle_mark(Mark::Delete),
+            KeyCode::Char('s') => app.toggle_mark(Mark::Download),
+            KeyCode::Char('x') if !app.marks.is_empty() => {
+                let deletes = app.marks.values().filter(|m| **m == Mark::Delete).count();
+                app.status = format!(
+                    "Delete {} and download {} files? (y/n)",
+                    deletes,
+                    app.marks.len() - deletes
+                );
+                app.confirming = true;
+            }
+            KeyCode::Char('r') => {
+                match owned_storage_accounts(client, &owner, &Default::default()).await {
+                    Ok(accounts) => app.accounts = accounts,
+                    Err(err) => app.status = format!("Error: {:#}", err),
+                }
+                let last = app.accounts.len().checked_sub(1);
+                app.account_list
+                    .select(app.account_list.selected().zip(last).map(|(i, l)| i.min(l)));
+                load_files(client, app).await;
+            }
+            _ => {}
+        }
+    }
+}
+
+async fn load_files<T: Signer>(client: &ShadowDriveClient<T>, app: &mut App) {
+    app.files.clear();
+    app.marks.clear();
+    app.file_list.select(None);
+    app.used = None;
+    let storage_account = match app.open_account() {
+        Some((key, _)) => *key,
+        None => return,
+    };
+    match list_objects(&app.http_client, &storage_account).await {
+        Ok(files) => {
+            app.files = files;
+            if !app.files.is_empty() {
+                app.file_list.select(Some(0));
+            }
+        }
+        Err(err) => app.status = format!("Error: {:#}", err),
+    }
+    let response = client.get_storage_account_size(&storage_account).await;
+    if let Ok(size) = process_shadow_api_response(response) {
+        app.used = Some(size.storage_used);
+    }
+}
+
+async fn load_object_data(app: &mut App) {
+    let location = match selected_location(app) {
+        Some(location) => location,
+        None => return,
+    };
+    app.detail = match get_object_data_json(&app.http_client, &location).await {
+        Ok(data) => serde_json::to_string_pretty(&data).unwrap_or_default(),
+        Err(err) => format!("Error: {:#}", err),
+    };
+}
+
+async fn preview_text(app: &mut App) {
+    let location = match selected_location(app) {
+        Some(location) => location,
+        None => return,
+    };
+    app.detail = match get_text(&app.http_client, &location).await {
+        Ok(resp) => {
+            let modified = last_modified(resp.headers()).unwrap_or_default();
+            match resp.text().await {
+                Ok(text) => format!("Last Modified: {}\n\n{}", modified, text),
+                Err(err) => format!("Error: {:#}", err),
+            }
+        }
+        Err(err) => format!("Error: {:#}", err),
+    };
+}
+
+/// URL of the file selected in the open storage account.
+fn selected_location(app: &App) -> Option<String> {
+    let (storage_account, _) = app.open_account()?;
+    Some(app.http_client.file_url(storage_account, app.selected_file()?))
+}
+
+/// Download, then delete, every marked file in the open storage account.
+async fn apply_marks<T: Signer>(client: &ShadowDriveClient<T>, app: &mut App) {
+    let storage_account = match app.open_account() {
+        Some((key, _)) => *key,
+        None => return,
+    };
+    let mut failed = 0;
+    for (file, mark) in &app.marks {
+        let result = match mark {
+            Mark::Download => {
+                match local_download_path(file) {
+                    Ok(path) => download_file(&app.http_client, &storage_account, file)
+                        .await
+                        .and_then(|bytes| save_new_file(&path, &bytes)),
+                    Err(err) => Err(err),
+                }
+            }
+            Mark::Delete => {
+                let location = app.http_client.file_url(&storage_account, file);
+                let response = client.delete_file(&storage_account, location).await;
+                process_shadow_api_response(response).map(|_| ())
+            }
+        };
+        if result.is_err() {
+            failed += 1;
+        }
+    }
+    app.status = format!("Applied {} marks, {} failed", app.marks.len(), failed);
+    load_files(client, app).await;
+}
+
+fn draw<B: Backend>(f: &mut Frame<B>, app: &mut App) {
+    let rows = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Min(5),
+            Constraint::Length(3),
+            Constraint::Length(1),
+        ])
+        .split(f.size());
+    let columns = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([
+            Constraint::Percentage(30),
+            Constraint::Percentage(30),
+            Constraint::Percentage(40),
+        ])
+        .split(rows[0]);
+
+    let focused = |pane: Pane| {
+        if app.pane == pane {
+            Style::default().fg(Color::Yellow)
+        } else {
+            Style::default()
+        }
+    };
+    let highlight = Style::default().add_modifier(Modifier::REVERSED);
+
+    let accounts: Vec<ListItem> = app
+        .accounts
+        .iter()
+        .map(|(key, account)| {
+            let name = storage_account_name(account);
+            ListItem::new(if name.is_empty() {
+                key.to_string()
+            } else {
+                name.to_string()
+            })
+        })
+        .collect();
+    let accounts = List::new(accounts)
+        .block(
+            Block::default()
+                .title("Storage Accounts")
+                .borders(Borders::ALL)
+                .border_style(focused(Pane::Accounts)),
+        )
+        .highlight_style(highlight);
+    f.render_stateful_widget(accounts, columns[0], &mut app.account_list);
+
+    let files: Vec<ListItem> = app
+        .files
+        .iter()
+        .map(|file| {
+            let mark = match app.marks.get(file) {
+                Some(Mark::Delete) => "D ",
+                Some(Mark::Download) => "S ",
+                None => "  ",
+            };
+            ListItem::new(format!("{}{}", mark, file))
+        })
+        .collect();
+    let files = List::new(files)
+        .block(
+            Block::default()
+                .title("Files")
+                .borders(Borders::ALL)
+                .border_style(focused(Pane::Files)),
+        )
+        .highlight_style(highlight);
+    f.render_stateful_widget(files, columns[1], &mut app.file_list);
+
+    let detail = Paragraph::new(app.detail.as_str())
+        .block(Block::default().title("Details").borders(Borders::ALL))
+        .wrap(Wrap { trim: false });
+    f.render_widget(detail, columns[2]);
+
+    let (ratio, label) = match (app.open_account(), app.used) {
+        (Some((_, account)), used) => {
+            let capacity = storage_account_capacity(account);
+            let total = capacity.get_bytes() as f64;
+            match used {
+                Some(used) => (
+                    if total > 0.0 {
+                        (used as f64 / total).min(1.0)
+                    } else {
+                        0.0
+                    },
+                    format!(
+                        "{} of {} used",
+                        byte_unit::Byte::from_bytes(used as u128).get_appropriate_unit(false),
+                        capacity.get_appropriate_unit(false)
+                    ),
+                ),
+                None => (0.0, format!("{} reserved", capacity.get_appropriate_unit(false))),
+            }
+        }
+        (None, _) => (0.0, "no storage account selected".to_string()),
+    };
+    let gauge = Gauge::default()
+        .block(Block::default().title("Capacity").borders(Borders::ALL))
+        .gauge_style(Style::default().fg(Color::Green))
+        .ratio(ratio)
+        .label(label);
+    f.render_widget(gauge, rows[1]);
+
+    f.render_widget(Paragraph::new(app.status.as_str()), rows[2]);
+}
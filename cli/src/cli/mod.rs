@@ -1,10 +1,19 @@
+pub mod browse;
 pub mod process;
+pub mod shell;
 
 use byte_unit::Byte;
-use clap::Parser;
+use clap::{ArgEnum, Parser};
+use clap_complete::Shell;
+use regex::Regex;
 use shadow_drive_cli::{parse_filesize, pubkey_arg};
+use shadow_drive_cli::client::commitment_arg;
+use shadow_drive_cli::output::OutputFormat;
+use shadow_drive_cli::storage_accounts::StorageAccountFilter;
 use shadow_drive_cli::FILE_UPLOAD_BATCH_SIZE;
+use solana_sdk::commitment_config::CommitmentConfig;
 use solana_sdk::pubkey::Pubkey;
+use std::path::PathBuf;
 
 /// Manually specify a cluster url and/or keypair.
 /// Those values otherwise default to the Solana CLI config file.
@@ -14,8 +23,10 @@ use solana_sdk::pubkey::Pubkey;
 pub struct ConfigOverride {
     /// The target URL for the cluster. See Solana CLI documentation on how to use this.
     /// Default values and usage patterns are identical to Solana CLI.
+    /// Can be repeated: requests fail over to the next URL when one fails.
+    /// Only the first is authenticated with `--auth`.
     #[clap(short, long)]
-    pub url: Option<String>,
+    pub url: Vec<String>,
     /// The target signer for transactions. See Solana CLI documentation on how to use this.
     /// Default values and usage patterns are identical to Solana CLI.
     #[clap(short, long)]
@@ -31,14 +42,135 @@ pub struct ConfigOverride {
     /// alter the state of the storage network.
     #[clap(long)]
     pub skip_confirm: bool,
+    /// Irreversible operations (making a storage account immutable, deleting it)
+    /// ask the user to type the account name even with `--skip-confirm`.
+    /// Pass this together with `--skip-confirm` to bypass that as well.
+    #[clap(long)]
+    pub yes_i_am_sure_immutable: bool,
     /// Supply a JWT to be included as a Bearer auth token to each RPC request.
     /// Use keyword "genesysgo" to automatically
     /// authenticate with a GenesysGo Premium RPC endpoint.
-    /// GenesysGo Account ID is inferred from `-u/--url` path.
+    /// GenesysGo Account ID is inferred from `-u/--url` path,
+    /// unless given with `--auth-account-id`.
     /// See also the `shadow-rpc-auth` subcommand for manually
-    /// acquiring an auth token.
+    /// acquiring an auth token. Other kinds of auth (basic, API key
+    /// headers, keys in the URL) can be configured in a profile.
     #[clap(long)]
     pub auth: Option<String>,
+    /// GenesysGo account ID to sign in with, for `--auth genesysgo` and
+    /// `shadow-rpc-auth`, instead of the one in the `-u/--url` path or the profile.
+    #[clap(long, global = true)]
+    pub auth_account_id: Option<String>,
+    /// Take defaults for `--url`, `--keypair`, `--auth`, `--commitment` and
+    /// `--rpc-timeout` from this profile of the CLI config file,
+    /// instead of the "default" profile.
+    #[clap(long, global = true)]
+    pub profile: Option<String>,
+    /// Commitment level for RPC queries and confirmations: processed, confirmed
+    /// or finalized. Defaults to the Solana CLI config's.
+    #[clap(long, global = true, parse(try_from_str = commitment_arg))]
+    pub commitment: Option<CommitmentConfig>,
+    /// Timeout in seconds of each RPC and Shadow Drive request.
+    #[clap(long, global = true)]
+    pub rpc_timeout: Option<u64>,
+    /// Attempts per JSON-RPC request, including the first, before giving up on
+    /// connection errors, timeouts, 429, 502, 503, 504 and unhealthy nodes.
+    /// 1 disables retries.
+    #[clap(long, global = true)]
+    pub rpc_max_attempts: Option<u32>,
+    /// Seconds after the first attempt at a JSON-RPC request past which
+    /// it isn't retried anymore, 0 for no limit.
+    #[clap(long, global = true)]
+    pub rpc_retry_deadline: Option<u64>,
+    /// Milliseconds after which a read request still unanswered is also sent
+    /// to the next `--url`, taking whichever response comes first.
+    #[clap(long, global = true, value_name = "MS")]
+    pub rpc_hedge: Option<u64>,
+    /// Extra header for every JSON-RPC request, as "Name: value",
+    /// e.g. a gateway key or a tracing ID. Can be repeated.
+    #[clap(long, global = true, value_name = "HEADER")]
+    pub rpc_header: Vec<String>,
+    /// Extra header for the CLI's own requests to the Shadow Drive server and
+    /// file host, as "Name: value". Can be repeated. Rejected for commands whose
+    /// uploads, edits, deletions or storage account changes the SDK sends itself.
+    #[clap(long, global = true, value_name = "HEADER")]
+    pub drive_header: Vec<String>,
+    /// Host of Shadow Drive files, e.g. a local `shadow-drive-emulator`. Used for
+    /// file URLs and the CLI's own downloads. Rejected for commands whose uploads,
+    /// edits, deletions or storage account changes the SDK sends to its own server.
+    #[clap(long, global = true, value_name = "URL")]
+    pub drive_host: Option<String>,
+    /// Shadow Drive server for the CLI's own requests, such as file listings and
+    /// `get-object-data`, e.g. a local `shadow-drive-emulator`. Rejected like `--drive-host`.
+    #[clap(long, global = true, value_name = "URL")]
+    pub storage_server: Option<String>,
+    /// Format of the command result printed to stdout: text, json, yaml or table.
+    /// Progress and prompts always go to stderr.
+    #[clap(short, long, arg_enum, default_value = "text", global = true)]
+    pub output: OutputFormat,
+    /// Build and simulate the transactions of a state-changing command,
+    /// printing the accounts touched, the fee and any simulation logs,
+    /// without sending anything. Refused by `shell` and `browse`.
+    #[clap(long, global = true)]
+    pub dry_run: bool,
+    /// Log more detail to stderr: -v for info, -vv to trace HTTP requests,
+    /// -vvv to include request and response bodies. `RUST_LOG` overrides this.
+    #[clap(short, long, parse(from_occurrences), global = true)]
+    pub verbose: u8,
+    /// Append logs to this file instead of printing them to stderr.
+    #[clap(long, global = true)]
+    pub log_file: Option<PathBuf>,
+    /// Print statistics of the RPC requests and of the CLI's own Shadow Drive HTTP
+    /// requests to stderr when done: requests, errors and retries, p50/p95 latency
+    /// and bytes, by method. Requests the Shadow Drive SDK sends itself aren't counted.
+    #[clap(long, global = true)]
+    pub stats: bool,
+    /// Write request metrics to this file in the Prometheus text format, every
+    /// 15 seconds and when done, e.g. for the node exporter's textfile collector.
+    #[clap(long, global = true, value_name = "PATH")]
+    pub metrics_file: Option<PathBuf>,
+    /// Record the outcome of every JSON-RPC request and of the CLI's own Shadow Drive
+    /// HTTP requests to this cassette file, one JSON object per line. Request headers,
+    /// and so auth tokens, are never recorded. Rejected for commands whose Shadow
+    /// Drive requests the SDK sends itself.
+    #[clap(long, global = true, value_name = "PATH", conflicts_with = "replay")]
+    pub record: Option<PathBuf>,
+    /// Answer requests from a cassette written with `--record` instead of the network,
+    /// failing on any request which wasn't recorded.
+    #[clap(long, global = true, value_name = "PATH")]
+    pub replay: Option<PathBuf>,
+}
+
+/// Select every storage account owned by the signer instead of a single one,
+/// optionally narrowed down by name or mutability.
+#[derive(Debug, Parser)]
+pub struct AccountSelection {
+    /// Apply the command to every storage account owned by the signer.
+    #[clap(long, name = "all_accounts")]
+    pub all_accounts: bool,
+    /// Only include accounts whose name matches this regular expression.
+    #[clap(long, requires = "all_accounts", parse(try_from_str = Regex::new))]
+    pub name_filter: Option<Regex>,
+    /// Only include immutable storage accounts.
+    #[clap(long, requires = "all_accounts", conflicts_with = "mutable")]
+    pub immutable: bool,
+    /// Only include mutable storage accounts.
+    #[clap(long, requires = "all_accounts")]
+    pub mutable: bool,
+}
+
+impl AccountSelection {
+    pub fn filter(&self) -> StorageAccountFilter {
+        let immutable = match (self.immutable, self.mutable) {
+            (true, _) => Some(true),
+            (_, true) => Some(false),
+            _ => None,
+        };
+        StorageAccountFilter {
+            name: self.name_filter.clone(),
+            immutable,
+        }
+    }
 }
 
 /// Perform Shadow Drive operations on the command-line.
@@ -53,9 +185,91 @@ pub struct Opts {
     pub command: Command,
 }
 
+/// What the hidden `__complete` command suggests.
+#[derive(Debug, Clone, Copy, ArgEnum)]
+pub enum CompletionKind {
+    Accounts,
+    Files,
+}
+
+/// Inspect and prepare the signer's SOL and SHDW balances.
+#[derive(Debug, Parser)]
+pub enum WalletCommand {
+    /// Show the SOL and SHDW balances of a wallet.
+    /// If no owner is provided, the configured signer is used.
+    Balance {
+        #[clap(parse(try_from_str = pubkey_arg))]
+        owner: Option<Pubkey>,
+    },
+    /// Create the signer's SHDW associated token account, if it is missing.
+    CreateShdwAccount,
+    /// Check whether the signer can afford to reserve an amount of storage.
+    CanAfford {
+        /// File size string, accepts KB, MB, GB, e.g. "10MB"
+        #[clap(parse(try_from_str = parse_filesize))]
+        size: Byte,
+        /// Include the rent for creating a new storage account.
+        #[clap(long)]
+        new_account: bool,
+    },
+}
+
+/// What to do with the cached GenesysGo RPC token.
+#[derive(Debug, Clone, Copy, Parser)]
+pub enum AuthAction {
+    /// Print the cached token, signing in first if it is missing or about to expire.
+    Print,
+    /// Sign in again and replace the cached token.
+    Refresh,
+    /// Delete the cached token.
+    Clear,
+}
+
 #[derive(Debug, Parser)]
 pub enum Command {
-    ShadowRpcAuth,
+    /// Acquire a GenesysGo RPC auth token for the `-u/--url` endpoint.
+    /// Tokens are cached under the config directory and renewed shortly before they expire.
+    ShadowRpcAuth {
+        /// Defaults to `print`.
+        #[clap(subcommand)]
+        action: Option<AuthAction>,
+    },
+    /// Start an interactive shell which keeps one authenticated client
+    /// alive across commands. Select a storage account with `use <account>`,
+    /// then work with its files through `ls`, `get`, `put` and `rm`.
+    Shell,
+    /// Browse storage accounts and files in a full-screen terminal UI.
+    /// Preview text files, inspect object metadata, and mark files
+    /// for download or deletion.
+    Browse,
+    /// Print a shell completion script to stdout, e.g.
+    /// `shadow-drive-cli completions bash > /etc/bash_completion.d/shadow-drive-cli`.
+    Completions {
+        #[clap(arg_enum)]
+        shell: Shell,
+        /// Also complete storage account addresses and remote filenames
+        /// by querying the network. Supported for bash and fish.
+        #[clap(long)]
+        dynamic: bool,
+    },
+    /// Print completion candidates for the dynamic completion scripts.
+    #[clap(name = "__complete", hide = true)]
+    Complete {
+        #[clap(arg_enum)]
+        kind: CompletionKind,
+        /// Only print candidates starting with this prefix.
+        #[clap(default_value = "")]
+        prefix: String,
+        /// Storage account whose files are completed.
+        #[clap(long, parse(try_from_str = pubkey_arg))]
+        storage_account: Option<Pubkey>,
+    },
+    /// Show SOL and SHDW balances, create the SHDW token account,
+    /// and check whether an operation is affordable.
+    Wallet {
+        #[clap(subcommand)]
+        command: WalletCommand,
+    },
     /// Create an account on which to store data.
     /// Storage accounts can be globally, irreversibly marked immutable
     /// for a one-time fee.
@@ -85,8 +299,14 @@ pub enum Command {
     /// Redeem tokens afforded to a storage account after reducing storage capacity.
     ClaimStake {
         /// The account whose stake to claim.
-        #[clap(parse(try_from_str = pubkey_arg))]
-        storage_account: Pubkey,
+        #[clap(
+            parse(try_from_str = pubkey_arg),
+            required_unless_present = "all_accounts",
+            conflicts_with = "all_accounts"
+        )]
+        storage_account: Option<Pubkey>,
+        #[clap(flatten)]
+        selection: AccountSelection,
     },
     /// Increase the capacity of a storage account.
     AddStorage {
@@ -124,8 +344,26 @@ pub enum Command {
     /// Fetch the metadata pertaining to a storage account.
     GetStorageAccount {
         /// Account whose metadata will be fetched.
-        #[clap(parse(try_from_str = pubkey_arg))]
-        storage_account: Pubkey,
+        #[clap(
+            parse(try_from_str = pubkey_arg),
+            required_unless_present = "all_accounts",
+            conflicts_with = "all_accounts"
+        )]
+        storage_account: Option<Pubkey>,
+        #[clap(flatten)]
+        selection: AccountSelection,
+    },
+    /// Report how much of a storage account's capacity is used and how much is free.
+    StorageUsage {
+        /// Account whose usage to report.
+        #[clap(
+            parse(try_from_str = pubkey_arg),
+            required_unless_present = "all_accounts",
+            conflicts_with = "all_accounts"
+        )]
+        storage_account: Option<Pubkey>,
+        #[clap(flatten)]
+        selection: AccountSelection,
     },
     /// Fetch a list of storage accounts owned by a particular pubkey.
     /// If no owner is provided, the configured signer is used.
@@ -137,8 +375,14 @@ pub enum Command {
     /// List all the files in a storage account.
     ListFiles {
         /// Storage account whose files to list.
-        #[clap(parse(try_from_str = pubkey_arg))]
-        storage_account: Pubkey,
+        #[clap(
+            parse(try_from_str = pubkey_arg),
+            required_unless_present = "all_accounts",
+            conflicts_with = "all_accounts"
+        )]
+        storage_account: Option<Pubkey>,
+        #[clap(flatten)]
+        selection: AccountSelection,
     },
     /// Get a file, assume it's text, and print it.
     GetText {
@@ -173,6 +417,40 @@ pub enum Command {
         /// name as the file you are editing.
         file: String,
     },
+    /// Migrate a v1 storage account to a new v2 account of the same size.
+    /// Every file is copied across and verified. Progress is kept in a state
+    /// file, so an interrupted migration resumes where it left off when the
+    /// same command is run again.
+    MigrateAccount {
+        /// The v1 storage account to migrate.
+        #[clap(parse(try_from_str = pubkey_arg))]
+        v1_account: Pubkey,
+        /// Name of the new v2 account, defaults to the name of the v1 account.
+        #[clap(long)]
+        name: Option<String>,
+        /// Where to keep migration progress,
+        /// defaults to "migrate-<v1_account>.json" in the current directory.
+        #[clap(long)]
+        state_file: Option<PathBuf>,
+        /// Queue the v1 account for deletion once every file is verified.
+        #[clap(long)]
+        delete_source: bool,
+    },
+    /// Compare a storage layout file (TOML or YAML) against on-chain state
+    /// and print the changes needed to bring the two in line.
+    Plan {
+        /// Path to the layout file.
+        layout: PathBuf,
+    },
+    /// Compute the same changes as `plan`, then carry them out
+    /// after a single confirmation.
+    Apply {
+        /// Batch size for file uploads.
+        #[clap(long, default_value_t=FILE_UPLOAD_BATCH_SIZE)]
+        batch_size: usize,
+        /// Path to the layout file.
+        layout: PathBuf,
+    },
     /// Upload one or more files to a storage account.
     StoreFiles {
         /// Batch size for file uploads, default 100, only relevant for large
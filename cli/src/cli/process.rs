@@ -1,262 +1,684 @@
-use super::Command;
+use super::{browse, shell, AuthAction, Command, CompletionKind, WalletCommand};
+use anyhow::anyhow;
 use itertools::Itertools;
-use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
-use shadow_rpc_auth::genesysgo_auth::{parse_account_id_from_url, sign_in};
-use shadow_rpc_auth::HttpSenderWithHeaders;
-use shadow_drive_cli::process_shadow_api_response;
-use shadow_drive_cli::wait_for_user_confirmation;
-use shadow_drive_rust::models::ShadowFile;
-use shadow_drive_rust::{ShadowDriveClient, StorageAccountVersion};
-use solana_client::nonblocking;
-use solana_client::rpc_client::RpcClient;
+use shadow_drive_cli::client::ClientFactory;
+use shadow_drive_cli::completion::{file_candidates, matching, storage_account_candidates};
+use shadow_drive_cli::config::GenesysGoSignIn;
+use shadow_drive_cli::error::CliError;
+use shadow_drive_cli::dry_run::{
+    simulate_message, CapturedMessages, CapturingSigner, DryRunLog, DryRunView,
+};
+use shadow_drive_cli::declarative::{self, AccountRef, Change, StorageLayout};
+use shadow_drive_cli::migration::{default_state_file, Migration, MigrationState};
+use shadow_drive_cli::output::{
+    print_output, AuthTokenView, DeletedFileView, FileListView, JsonView, MessageView,
+    OutputFormat, StorageAccountListView, StorageAccountView, StorageUsageView, TextFileView,
+    TransactionView, UploadView, UploadedFileView,
+};
+use shadow_drive_cli::{list_objects, process_shadow_api_response, storage_used};
+use shadow_drive_cli::storage_accounts::{
+    owned_storage_accounts, storage_account_details, BatchSummary,
+};
+use shadow_drive_cli::wallet::{
+    create_shdw_token_account, ensure_affordable, wallet_balances, OperationCost,
+};
+use shadow_drive_cli::{
+    confirm_irreversible, confirm_typed, wait_for_user_confirmation, ConfirmPolicy,
+};
+use shadow_drive_rust::models::{ShadowDriveResult, ShadowFile};
+use shadow_drive_rust::StorageAccountVersion;
+use shadow_rpc_auth::cassette::redact_url;
+use shadow_rpc_auth::token_cache::{cached_sign_in_with_config, refresh_with_config};
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_sdk::pubkey::Pubkey;
 use solana_sdk::signature::Signer;
-use std::str::FromStr;
 use std::thread::sleep;
 use std::time::Duration;
 
-/// We either create an authenticated client with default auth headers,
-/// or else we simply use the [RpcClient] provided by the normal
-/// [ShadowDriveClient] constructor.
-pub fn shadow_client_factory<T: Signer>(
-    signer: T,
-    url: &str,
-    auth: Option<String>,
-) -> ShadowDriveClient<T> {
-    if let Some(auth) = auth {
-        let mut headers = HeaderMap::new();
-        headers.append(
-            HeaderName::from_str("Authorization").unwrap(),
-            HeaderValue::from_str(&format!("Bearer {}", auth)).unwrap(),
-        );
-        let rpc_client = nonblocking::rpc_client::RpcClient::new_sender(
-            HttpSenderWithHeaders::new(url, Some(headers.clone())),
-            Default::default(),
-        );
-        let client = RpcClient::new_sender(
-            HttpSenderWithHeaders::new(url, Some(headers)),
-            Default::default(),
-        );
-        let balance = client.get_balance(&signer.pubkey());
-        match balance {
-            Ok(balance) => {
-                println!("{}: {} lamports", signer.pubkey().to_string(), balance);
-            }
-            Err(e) => {
-                println!("Failed to fetch balance: {:?}", e);
-            }
-        }
-        ShadowDriveClient::new_with_rpc(signer, rpc_client)
-    } else {
-        ShadowDriveClient::new(signer, url)
-    }
+/// Print the estimated cost of an operation, and fail before prompting
+/// the user if the signer cannot afford it.
+async fn check_cost(rpc: &RpcClient, owner: &Pubkey, cost: OperationCost) -> anyhow::Result<()> {
+    eprintln!("Estimated cost: {}", cost);
+    ensure_affordable(rpc, owner, &cost).await?;
+    Ok(())
+}
+
+/// Commands accepting `--all-accounts` take an optional storage account,
+/// which clap guarantees is present when that flag is absent.
+fn required_account(storage_account: &Option<Pubkey>) -> anyhow::Result<&Pubkey> {
+    storage_account
+        .as_ref()
+        .ok_or_else(|| anyhow!("a storage account or --all-accounts is required"))
 }
 
 impl Command {
     pub async fn process<T: Signer>(
         &self,
         signer: T,
-        url: &str,
-        skip_confirm: bool,
-        auth: Option<String>,
+        clients: &ClientFactory,
+        confirm: ConfirmPolicy,
+        genesysgo: GenesysGoSignIn,
+        output: OutputFormat,
+        dry_run: bool,
     ) -> anyhow::Result<()> {
+        let skip_confirm = confirm.skip_confirm;
         let signer_pubkey = signer.pubkey();
-        println!("Signing with {:?}", signer_pubkey);
-        println!("Sending RPC requests to {}", url);
+        eprintln!("Signing with {:?}", signer_pubkey);
+        eprintln!("Sending RPC requests to {}", redact_url(clients.url()));
+        for fallback in clients.fallback_urls() {
+            eprintln!("Falling back to {}", redact_url(fallback));
+        }
+        if self.uses_sdk_drive_requests() {
+            clients.ensure_reaches_sdk_requests()?;
+        }
+        if dry_run && matches!(self, Command::Shell | Command::Browse) {
+            return Err(CliError::Other(
+                "--dry-run can't be used with `shell` or `browse`, which upload and delete \
+                files as soon as they are asked to"
+                    .to_string(),
+            )
+            .into());
+        }
+        if dry_run && self.is_state_changing() {
+            let view = self.dry_run(signer, clients).await?;
+            return print_output(output, &view);
+        }
         match self {
-            Command::ShadowRpcAuth => {
-                let account_id = parse_account_id_from_url(url.to_string())?;
-                let resp = sign_in(&signer, &account_id).await?;
-                println!("{:#?}", resp);
+            Command::ShadowRpcAuth { action } => {
+                let account_id = genesysgo.account_id(clients.url())?;
+                let config = &genesysgo.config;
+                let cache = shadow_drive_cli::token_cache()?;
+                match action.unwrap_or(AuthAction::Print) {
+                    AuthAction::Print => {
+                        let token =
+                            cached_sign_in_with_config(config, &signer, &account_id, &cache)
+                                .await?;
+                        print_output(output, &AuthTokenView { token })?;
+                    }
+                    AuthAction::Refresh => {
+                        let token =
+                            refresh_with_config(config, &signer, &account_id, &cache).await?;
+                        print_output(output, &AuthTokenView { token })?;
+                    }
+                    AuthAction::Clear => {
+                        let cache = cache.for_portal(&config.portal_url);
+                        let message = if cache.clear(&account_id, &signer_pubkey)? {
+                            "Cleared cached token"
+                        } else {
+                            "No cached token"
+                        };
+                        print_output(output, &MessageView::new(message))?;
+                    }
+                }
+            }
+            Command::Shell => {
+                let client = clients.shadow_client(signer);
+                shell::run(
+                    &client,
+                    clients.http_client(),
+                    signer_pubkey,
+                    confirm,
+                    output,
+                )
+                .await?;
+            }
+            Command::Completions { .. } => {
+                unreachable!("completion scripts are printed before a signer is resolved")
+            }
+            Command::Complete {
+                kind,
+                prefix,
+                storage_account,
+            } => {
+                let candidates = match kind {
+                    CompletionKind::Accounts => {
+                        let client = clients.shadow_client(signer);
+                        storage_account_candidates(&client, &signer_pubkey).await?
+                    }
+                    CompletionKind::Files => {
+                        let http_client = clients.http_client();
+                        file_candidates(&http_client, required_account(storage_account)?).await?
+                    }
+                };
+                for candidate in matching(&candidates, prefix) {
+                    println!("{}", candidate.line());
+                }
+            }
+            Command::Browse => {
+                let client = clients.shadow_client(signer);
+                browse::run(&client, clients.http_client(), signer_pubkey).await?;
             }
             Command::CreateStorageAccount { name, size } => {
-                let client = shadow_client_factory(signer, url, auth);
-                println!("Create Storage Account {}: {}", name, size);
+                let rpc = clients.rpc_client();
+                let client = clients.shadow_client(signer);
+                eprintln!("Create Storage Account {}: {}", name, size);
+                let cost = OperationCost::storage(&rpc, size, true).await?;
+                check_cost(&rpc, &signer_pubkey, cost).await?;
                 wait_for_user_confirmation(skip_confirm)?;
                 let response = client
                     .create_storage_account(name, size.clone(), StorageAccountVersion::v2())
                     .await;
                 let resp = process_shadow_api_response(response)?;
-                println!("{:#?}", resp);
+                let view = TransactionView {
+                    operation: "create-storage-account".to_string(),
+                    storage_account: resp.shdw_bucket,
+                    signature: resp.transaction_signature,
+                    message: None,
+                };
+                print_output(output, &view)?;
             }
             Command::DeleteStorageAccount { storage_account } => {
-                let client = shadow_client_factory(signer, url, auth);
-                println!("Delete Storage Account {}", storage_account.to_string());
-                wait_for_user_confirmation(skip_confirm)?;
+                let rpc = clients.rpc_client();
+                let client = clients.shadow_client(signer);
+                let http_client = clients.http_client();
+                let details =
+                    storage_account_details(&client, &http_client, storage_account).await?;
+                check_cost(&rpc, &signer_pubkey, OperationCost::fee_only()).await?;
+                confirm_irreversible(confirm, "Delete Storage Account", &details)?;
                 let response = client.delete_storage_account(storage_account).await;
 
                 let resp = process_shadow_api_response(response)?;
-                println!("{:#?}", resp);
+                let view = TransactionView::new(
+                    "delete-storage-account",
+                    Some(storage_account),
+                    resp.txid,
+                    None,
+                );
+                print_output(output, &view)?;
             }
             Command::CancelDeleteStorageAccount { storage_account } => {
-                let client = shadow_client_factory(signer, url, auth);
-                println!(
+                let rpc = clients.rpc_client();
+                let client = clients.shadow_client(signer);
+                eprintln!(
                     "Cancellation of Delete Storage Account {}",
                     storage_account.to_string()
                 );
+                check_cost(&rpc, &signer_pubkey, OperationCost::fee_only()).await?;
                 wait_for_user_confirmation(skip_confirm)?;
                 let response = client.cancel_delete_storage_account(storage_account).await;
 
                 let resp = process_shadow_api_response(response)?;
-                println!("{:#?}", resp);
+                let view = TransactionView::new(
+                    "cancel-delete-storage-account",
+                    Some(storage_account),
+                    resp.txid,
+                    None,
+                );
+                print_output(output, &view)?;
             }
-            Command::ClaimStake { storage_account } => {
-                let client = shadow_client_factory(signer, url, auth);
-                println!(
+            Command::ClaimStake {
+                storage_account,
+                selection,
+            } => {
+                let rpc = clients.rpc_client();
+                let client = clients.shadow_client(signer);
+                if selection.all_accounts {
+                    let accounts =
+                        owned_storage_accounts(&client, &signer_pubkey, &selection.filter())
+                            .await?;
+                    eprintln!("Claim Stake on {} Storage Accounts", accounts.len());
+                    for (storage_account, _) in &accounts {
+                        eprintln!("  {}", storage_account.to_string());
+                    }
+                    let cost = OperationCost {
+                        lamports: OperationCost::fee_only().lamports * accounts.len() as u64,
+                        shades: 0,
+                    };
+                    check_cost(&rpc, &signer_pubkey, cost).await?;
+                    wait_for_user_confirmation(skip_confirm)?;
+                    let mut summary = BatchSummary::default();
+                    for (storage_account, _) in accounts {
+                        let response = client.claim_stake(&storage_account).await;
+                        let result = process_shadow_api_response(response).map(|resp| {
+                            TransactionView::new(
+                                "claim-stake",
+                                Some(&storage_account),
+                                resp.txid,
+                                None,
+                            )
+                        });
+                        summary.record(storage_account, result);
+                    }
+                    return summary.finish(output);
+                }
+                let storage_account = required_account(storage_account)?;
+                eprintln!(
                     "Claim Stake on Storage Account {}",
                     storage_account.to_string()
                 );
+                check_cost(&rpc, &signer_pubkey, OperationCost::fee_only()).await?;
                 wait_for_user_confirmation(skip_confirm)?;
                 let response = client.claim_stake(storage_account).await;
 
                 let resp = process_shadow_api_response(response)?;
-                println!("{:#?}", resp);
+                let view =
+                    TransactionView::new("claim-stake", Some(storage_account), resp.txid, None);
+                print_output(output, &view)?;
             }
             Command::ReduceStorage {
                 storage_account,
                 size,
             } => {
-                let client = shadow_client_factory(signer, url, auth);
-                println!(
+                let rpc = clients.rpc_client();
+                let client = clients.shadow_client(signer);
+                eprintln!(
                     "Reduce Storage Capacity {}: {}",
                     storage_account.to_string(),
                     size
                 );
+                check_cost(&rpc, &signer_pubkey, OperationCost::fee_only()).await?;
                 wait_for_user_confirmation(skip_confirm)?;
                 let response = client.reduce_storage(storage_account, size.clone()).await;
 
                 let resp = process_shadow_api_response(response)?;
-                println!("{:#?}", resp);
+                let view = TransactionView::new(
+                    "reduce-storage",
+                    Some(storage_account),
+                    resp.transaction_signature,
+                    Some(resp.message),
+                );
+                print_output(output, &view)?;
             }
             Command::AddStorage {
                 storage_account,
                 size,
             } => {
-                let client = shadow_client_factory(signer, url, auth);
-                println!("Increase Storage {}: {}", storage_account.to_string(), size);
+                let rpc = clients.rpc_client();
+                let client = clients.shadow_client(signer);
+                eprintln!("Increase Storage {}: {}", storage_account.to_string(), size);
+                let cost = OperationCost::storage(&rpc, size, false).await?;
+                check_cost(&rpc, &signer_pubkey, cost).await?;
                 wait_for_user_confirmation(skip_confirm)?;
                 let response = client.add_storage(storage_account, size.clone()).await;
 
                 let resp = process_shadow_api_response(response)?;
-                println!("{:#?}", resp);
+                let view = TransactionView::new(
+                    "add-storage",
+                    Some(storage_account),
+                    resp.transaction_signature,
+                    Some(resp.message),
+                );
+                print_output(output, &view)?;
             }
             Command::AddImmutableStorage {
                 storage_account,
                 size,
             } => {
-                let client = shadow_client_factory(signer, url, auth);
-                println!(
+                let rpc = clients.rpc_client();
+                let client = clients.shadow_client(signer);
+                eprintln!(
                     "Increase Immutable Storage {}: {}",
                     storage_account.to_string(),
                     size
                 );
+                let cost = OperationCost::storage(&rpc, size, false).await?;
+                check_cost(&rpc, &signer_pubkey, cost).await?;
                 wait_for_user_confirmation(skip_confirm)?;
                 let response = client
                     .add_immutable_storage(storage_account, size.clone())
                     .await;
 
                 let resp = process_shadow_api_response(response)?;
-                println!("{:#?}", resp);
+                let view = TransactionView::new(
+                    "add-immutable-storage",
+                    Some(storage_account),
+                    resp.transaction_signature,
+                    Some(resp.message),
+                );
+                print_output(output, &view)?;
             }
             Command::MakeStorageImmutable { storage_account } => {
-                let client = shadow_client_factory(signer, url, auth);
-                println!("Make Storage Immutable {}", storage_account.to_string());
-                wait_for_user_confirmation(skip_confirm)?;
+                let rpc = clients.rpc_client();
+                let client = clients.shadow_client(signer);
+                let http_client = clients.http_client();
+                let details =
+                    storage_account_details(&client, &http_client, storage_account).await?;
+                let cost = OperationCost::storage(&rpc, &details.capacity, false).await?;
+                check_cost(&rpc, &signer_pubkey, cost).await?;
+                confirm_irreversible(confirm, "Make Storage Immutable", &details)?;
                 let response = client.make_storage_immutable(storage_account).await;
 
                 let resp = process_shadow_api_response(response)?;
-                println!("{:#?}", resp);
+                let view = TransactionView::new(
+                    "make-storage-immutable",
+                    Some(storage_account),
+                    resp.transaction_signature,
+                    Some(resp.message),
+                );
+                print_output(output, &view)?;
             }
-            Command::GetStorageAccount { storage_account } => {
-                let client = ShadowDriveClient::new(signer, url);
-                println!("Get Storage Account {}", storage_account.to_string());
+            Command::GetStorageAccount {
+                storage_account,
+                selection,
+            } => {
+                let client = clients.shadow_client(signer);
+                if selection.all_accounts {
+                    eprintln!(
+                        "Get Storage Accounts Owned By {}",
+                        signer_pubkey.to_string()
+                    );
+                    let accounts =
+                        owned_storage_accounts(&client, &signer_pubkey, &selection.filter())
+                            .await?;
+                    let mut summary = BatchSummary::default();
+                    for (storage_account, account) in accounts {
+                        let view = StorageAccountView::new(storage_account, &account);
+                        summary.record(storage_account, Ok(view));
+                    }
+                    return summary.finish(output);
+                }
+                let storage_account = required_account(storage_account)?;
+                eprintln!("Get Storage Account {}", storage_account.to_string());
                 let response = client.get_storage_account(storage_account).await;
 
                 let act = process_shadow_api_response(response)?;
-                println!("{:#?}", act);
+                print_output(output, &StorageAccountView::new(*storage_account, &act))?;
+            }
+            Command::StorageUsage {
+                storage_account,
+                selection,
+            } => {
+                let client = clients.shadow_client(signer);
+                let http_client = clients.http_client();
+                if selection.all_accounts {
+                    let accounts =
+                        owned_storage_accounts(&client, &signer_pubkey, &selection.filter())
+                            .await?;
+                    eprintln!("Storage Usage of {} Storage Accounts", accounts.len());
+                    let mut summary = BatchSummary::default();
+                    for (storage_account, account) in accounts {
+                        let result = storage_used(&http_client, &storage_account)
+                            .await
+                            .map(|used| StorageUsageView::new(storage_account, &account, used));
+                        summary.record(storage_account, result);
+                    }
+                    return summary.finish(output);
+                }
+                let storage_account = required_account(storage_account)?;
+                eprintln!("Storage Usage of {}", storage_account.to_string());
+                let response = client.get_storage_account(storage_account).await;
+                let account = process_shadow_api_response(response)?;
+                let used = storage_used(&http_client, storage_account).await?;
+                let view = StorageUsageView::new(*storage_account, &account, used);
+                print_output(output, &view)?;
             }
             Command::GetStorageAccounts { owner } => {
-                let client = shadow_client_factory(signer, url, auth.clone());
+                let client = clients.shadow_client(signer);
                 let owner = owner.as_ref().unwrap_or(&signer_pubkey);
-                println!("Get Storage Accounts Owned By {}", owner.to_string());
-                let response = client.get_storage_accounts(owner).await;
-                let accounts = process_shadow_api_response(response)?;
-                println!("{:#?}", accounts);
-            }
-            Command::ListFiles { storage_account } => {
-                let client = ShadowDriveClient::new(signer, url);
-                println!(
+                eprintln!("Get Storage Accounts Owned By {}", owner.to_string());
+                let accounts =
+                    owned_storage_accounts(&client, owner, &Default::default()).await?;
+                let view = StorageAccountListView(
+                    accounts
+                        .iter()
+                        .map(|(key, account)| StorageAccountView::new(*key, account))
+                        .collect(),
+                );
+                print_output(output, &view)?;
+            }
+            Command::ListFiles {
+                storage_account,
+                selection,
+            } => {
+                let client = clients.shadow_client(signer);
+                let http_client = clients.http_client();
+                if selection.all_accounts {
+                    let accounts =
+                        owned_storage_accounts(&client, &signer_pubkey, &selection.filter())
+                            .await?;
+                    eprintln!("List Files for {} Storage Accounts", accounts.len());
+                    let mut summary = BatchSummary::default();
+                    for (storage_account, _) in accounts {
+                        let result =
+                            list_objects(&http_client, &storage_account)
+                                .await
+                                .map(|files| FileListView {
+                                    storage_account,
+                                    drive_host: http_client.drive_host().to_string(),
+                                    files,
+                                });
+                        summary.record(storage_account, result);
+                    }
+                    return summary.finish(output);
+                }
+                let storage_account = required_account(storage_account)?;
+                eprintln!(
                     "List Files for Storage Account {}",
                     storage_account.to_string()
                 );
-                let response = client.list_objects(storage_account).await;
-                let files = process_shadow_api_response(response)?;
-                println!("{:#?}", files);
+                let files = list_objects(&http_client, storage_account).await?;
+                let view = FileListView {
+                    storage_account: *storage_account,
+                    drive_host: http_client.drive_host().to_string(),
+                    files,
+                };
+                print_output(output, &view)?;
             }
             Command::GetText {
                 storage_account,
                 file,
             } => {
-                let location = shadow_drive_cli::drive_url(storage_account, file);
-                let resp = shadow_drive_cli::get_text(&location).await?;
+                let http_client = clients.http_client();
+                let location = http_client.file_url(storage_account, file);
+                let resp = shadow_drive_cli::get_text(&http_client, &location).await?;
                 let last_modified = shadow_drive_cli::last_modified(resp.headers())?;
-                println!("Get Text at {}", &location);
-                println!("Last Modified: {}", last_modified);
-                println!("");
-                println!("{}", resp.text().await?);
+                eprintln!("Get Text at {}", &location);
+                eprintln!("Last Modified: {}", last_modified);
+                let view = TextFileView {
+                    url: location,
+                    last_modified,
+                    text: resp.text().await?,
+                };
+                print_output(output, &view)?;
             }
             Command::DeleteFile {
                 storage_account,
                 file,
             } => {
-                let client = ShadowDriveClient::new(signer, url);
-                let location = shadow_drive_cli::drive_url(storage_account, file);
-                println!("Delete file {}", &location);
+                let client = clients.shadow_client(signer);
+                let location = clients.http_client().file_url(storage_account, file);
+                eprintln!("Delete file {}", &location);
                 wait_for_user_confirmation(skip_confirm)?;
                 let response = client.delete_file(storage_account, location.clone()).await;
                 let resp = process_shadow_api_response(response)?;
-                println!("{:#?}", resp);
+                let view = DeletedFileView {
+                    storage_account: *storage_account,
+                    url: location,
+                    message: resp.message,
+                };
+                print_output(output, &view)?;
             }
             Command::EditFile {
                 storage_account,
                 file,
             } => {
-                let client = ShadowDriveClient::new(signer, url);
+                let client = clients.shadow_client(signer);
                 let basename = shadow_drive_cli::acquire_basename(file);
-                let shdw_file = ShadowFile::file(basename, file.clone());
-                println!("Edit file {} {}", storage_account.to_string(), file);
+                let shdw_file = ShadowFile::file(basename.clone(), file.clone());
+                eprintln!("Edit file {} {}", storage_account.to_string(), file);
                 wait_for_user_confirmation(skip_confirm)?;
                 let response = client.edit_file(storage_account, shdw_file).await;
-                let resp = process_shadow_api_response(response)?;
-                println!("{:#?}", resp);
+                process_shadow_api_response(response)?;
+                let view = UploadView {
+                    storage_account: *storage_account,
+                    files: vec![UploadedFileView {
+                        file: file.clone(),
+                        url: clients.http_client().file_url(storage_account, &basename),
+                    }],
+                    failed: vec![],
+                };
+                print_output(output, &view)?;
             }
             Command::GetObjectData {
                 storage_account,
                 file,
             } => {
-                let client = ShadowDriveClient::new(signer, url);
-                let location = shadow_drive_cli::drive_url(storage_account, file);
-                println!("Get object data {} {}", storage_account.to_string(), file);
-                let response = client.get_object_data(&location).await;
-                let data = process_shadow_api_response(response)?;
-                println!("{:#?}", data);
+                let http_client = clients.http_client();
+                let location = http_client.file_url(storage_account, file);
+                eprintln!("Get object data {} {}", storage_account.to_string(), file);
+                let data = shadow_drive_cli::get_object_data_json(&http_client, &location).await?;
+                print_output(output, &JsonView(data))?;
+            }
+            Command::MigrateAccount {
+                v1_account,
+                name,
+                state_file,
+                delete_source,
+            } => {
+                let rpc = clients.rpc_client();
+                let client = clients.shadow_client(signer);
+                let http_client = clients.http_client();
+                let state_file = state_file
+                    .clone()
+                    .unwrap_or_else(|| default_state_file(v1_account));
+                let mut migration = Migration::new(
+                    &client,
+                    http_client.clone(),
+                    *v1_account,
+                    state_file.clone(),
+                )?;
+                match migration.state.destination()? {
+                    Some(destination) => eprintln!(
+                        "Resume Migration of {} to {}",
+                        v1_account.to_string(),
+                        destination.to_string()
+                    ),
+                    None => eprintln!(
+                        "Migrate Storage Account {} to a new v2 account",
+                        v1_account.to_string()
+                    ),
+                }
+                eprintln!("Progress is saved to {}", state_file.display());
+                if migration.state.destination.is_none() {
+                    let details =
+                        storage_account_details(&client, &http_client, v1_account).await?;
+                    let cost = OperationCost::storage(&rpc, &details.capacity, true).await?;
+                    check_cost(&rpc, &signer_pubkey, cost).await?;
+                }
+                wait_for_user_confirmation(skip_confirm)?;
+                if *delete_source {
+                    eprintln!(
+                        "The v1 account will be queued for deletion once every file is verified."
+                    );
+                    let details =
+                        storage_account_details(&client, &http_client, v1_account).await?;
+                    confirm_irreversible(confirm, "Delete Storage Account", &details)?;
+                }
+                let destination = migration.create_destination(name.as_deref()).await?;
+                eprintln!("Destination Storage Account {}", destination.to_string());
+                migration.copy_files(&destination).await?;
+                let mismatched = migration.verify(&destination).await?;
+                if !mismatched.is_empty() {
+                    return Err(anyhow!(
+                        "{} files failed verification, run the command again to retry: {:#?}",
+                        mismatched.len(),
+                        mismatched
+                    ));
+                }
+                if *delete_source {
+                    migration.delete_source().await?;
+                }
+                print_output(output, &migration.state)?;
+            }
+            Command::Wallet { command } => {
+                let rpc = clients.rpc_client();
+                match command {
+                    WalletCommand::Balance { owner } => {
+                        let owner = owner.as_ref().unwrap_or(&signer_pubkey);
+                        print_output(output, &wallet_balances(&rpc, owner).await?)?;
+                    }
+                    WalletCommand::CreateShdwAccount => {
+                        eprintln!(
+                            "Create SHDW token account for {}",
+                            signer_pubkey.to_string()
+                        );
+                        check_cost(&rpc, &signer_pubkey, OperationCost::fee_only()).await?;
+                        wait_for_user_confirmation(skip_confirm)?;
+                        match create_shdw_token_account(&rpc, &signer).await? {
+                            Some(signature) => {
+                                let view = TransactionView::new(
+                                    "create-shdw-account",
+                                    None,
+                                    signature,
+                                    None,
+                                );
+                                print_output(output, &view)?;
+                            }
+                            None => print_output(
+                                output,
+                                &MessageView::new("SHDW token account already exists"),
+                            )?,
+                        }
+                    }
+                    WalletCommand::CanAfford { size, new_account } => {
+                        let cost = OperationCost::storage(&rpc, size, *new_account).await?;
+                        check_cost(&rpc, &signer_pubkey, cost).await?;
+                        eprintln!("{} can afford this operation", signer_pubkey.to_string());
+                        print_output(output, &cost)?;
+                    }
+                }
+            }
+            Command::Plan { layout } => {
+                let client = clients.shadow_client(signer);
+                let layout = StorageLayout::load(layout)?;
+                let http_client = clients.http_client();
+                let plan =
+                    declarative::plan(&client, &http_client, &signer_pubkey, &layout).await?;
+                print_output(output, &plan)?;
+            }
+            Command::Apply { batch_size, layout } => {
+                let rpc = clients.rpc_client();
+                let client = clients.shadow_client(signer);
+                let layout = StorageLayout::load(layout)?;
+                let http_client = clients.http_client();
+                let plan =
+                    declarative::plan(&client, &http_client, &signer_pubkey, &layout).await?;
+                eprintln!("{}", plan);
+                if plan.is_empty() {
+                    return print_output(output, &plan);
+                }
+                let cost = declarative::estimated_cost(&rpc, &plan).await?;
+                check_cost(&rpc, &signer_pubkey, cost).await?;
+                wait_for_user_confirmation(skip_confirm)?;
+                for change in plan.changes.iter().filter(|c| c.is_irreversible()) {
+                    if let Change::MakeImmutable { account } = change {
+                        match account {
+                            AccountRef::Existing(storage_account) => {
+                                let details =
+                                    storage_account_details(&client, &http_client, storage_account)
+                                        .await?;
+                                confirm_irreversible(confirm, "Make Storage Immutable", &details)?;
+                            }
+                            AccountRef::New(name) => {
+                                eprintln!("Make Storage Immutable {} (IRREVERSIBLE)", name);
+                                confirm_typed(confirm, "Make Storage Immutable", name)?;
+                            }
+                        }
+                    }
+                }
+                declarative::apply(&client, &http_client, &plan, *batch_size).await?;
+                print_output(output, &plan)?;
             }
             Command::StoreFiles {
                 batch_size,
                 storage_account,
                 files,
             } => {
-                let client = ShadowDriveClient::new(signer, url);
-                println!("Store Files {} {:#?}", storage_account.to_string(), files);
-                println!(
+                let client = clients.shadow_client(signer);
+                eprintln!("Store Files {} {:#?}", storage_account.to_string(), files);
+                eprintln!(
                     "WARNING: This CLI does not add any encryption on its own. \
                 The files in their current state become public as soon as they're uploaded."
                 );
                 wait_for_user_confirmation(skip_confirm)?;
-                for chunk in &files.into_iter().chunks(*batch_size) {
+                let mut view = UploadView::new(*storage_account);
+                for chunk in &files.iter().chunks(*batch_size) {
+                    let chunk: Vec<String> = chunk.cloned().collect();
                     let response = client
                         .store_files(
-                            &storage_account,
+                            storage_account,
                             chunk
+                                .iter()
                                 .map(|s| {
                                     let basename = shadow_drive_cli::acquire_basename(s);
                                     ShadowFile::file(basename, s.clone())
@@ -265,11 +687,593 @@ impl Command {
                         )
                         .await;
                     let resp = process_shadow_api_response(response)?;
-                    println!("{:#?}", resp);
+                    view.record_response(&chunk, &resp);
                     sleep(Duration::from_millis(150));
                 }
+                print_output(output, &view)?;
+                if let Some(err) = view.failure() {
+                    return Err(err.into());
+                }
             }
         }
         Ok(())
     }
+
+    /// Whether the Shadow Drive SDK sends some of the command's Shadow Drive
+    /// requests itself, rather than the CLI's [ClientFactory::http_client].
+    pub fn uses_sdk_drive_requests(&self) -> bool {
+        match self {
+            Command::Shell
+            | Command::Browse
+            | Command::CreateStorageAccount { .. }
+            | Command::ReduceStorage { .. }
+            | Command::AddStorage { .. }
+            | Command::AddImmutableStorage { .. }
+            | Command::MakeStorageImmutable { .. }
+            | Command::DeleteFile { .. }
+            | Command::EditFile { .. }
+            | Command::MigrateAccount { .. }
+            | Command::Apply { .. }
+            | Command::StoreFiles { .. } => true,
+            _ => false,
+        }
+    }
+
+    /// Whether the command can alter on-chain or Shadow Drive state.
+    fn is_state_changing(&self) -> bool {
+        match self {
+            Command::Wallet { command } => matches!(command, WalletCommand::CreateShdwAccount),
+            Command::CreateStorageAccount { .. }
+            | Command::DeleteStorageAccount { .. }
+            | Command::CancelDeleteStorageAccount { .. }
+            | Command::ClaimStake { .. }
+            | Command::ReduceStorage { .. }
+            | Command::AddStorage { .. }
+            | Command::AddImmutableStorage { .. }
+            | Command::MakeStorageImmutable { .. }
+            | Command::DeleteFile { .. }
+            | Command::EditFile { .. }
+            | Command::MigrateAccount { .. }
+            | Command::Apply { .. }
+            | Command::StoreFiles { .. } => true,
+            _ => false,
+        }
+    }
+
+    /// Build and simulate the transactions of a state-changing command without
+    /// sending them. Transactions which the Shadow Drive server co-signs and submits
+    /// are built and partially signed as usual, then simulated instead of posted.
+    async fn dry_run<T: Signer>(
+        &self,
+        signer: T,
+        clients: &ClientFactory,
+    ) -> anyhow::Result<DryRunView> {
+        let signer_pubkey = signer.pubkey();
+        let rpc = clients.rpc_client();
+        let log = DryRunLog::default();
+        let dry_rpc = clients.dry_run_rpc_client(log.clone());
+        let http_client = clients.http_client();
+        let file_changes = Some(
+            "File uploads, edits and deletions are authorized by a message signed for the \
+            Shadow Drive server rather than by a transaction, so there is nothing to simulate."
+                .to_string(),
+        );
+
+        if let Command::Wallet { .. } = self {
+            let result = create_shdw_token_account(&dry_rpc, &signer).await;
+            if log.is_empty() {
+                result?;
+                return Ok(DryRunView {
+                    operation: "create-shdw-account".to_string(),
+                    estimated_cost: None,
+                    simulations: vec![],
+                    note: Some("SHDW token account already exists".to_string()),
+                });
+            }
+            return Ok(DryRunView {
+                operation: "create-shdw-account".to_string(),
+                estimated_cost: Some(OperationCost::fee_only()),
+                simulations: log.take(),
+                note: None,
+            });
+        }
+
+        if let Command::DeleteStorageAccount { .. }
+        | Command::CancelDeleteStorageAccount { .. }
+        | Command::ClaimStake { .. } = self
+        {
+            let client = clients.shadow_client_with_rpc(signer, dry_rpc);
+            let (operation, estimated_cost) = match self {
+                Command::DeleteStorageAccount { storage_account } => {
+                    let response = client.delete_storage_account(storage_account).await;
+                    expect_simulated(response, log.len(), 0)?;
+                    ("delete-storage-account", OperationCost::fee_only())
+                }
+                Command::CancelDeleteStorageAccount { storage_account } => {
+                    let response = client.cancel_delete_storage_account(storage_account).await;
+                    expect_simulated(response, log.len(), 0)?;
+                    ("cancel-delete-storage-account", OperationCost::fee_only())
+                }
+                Command::ClaimStake {
+                    storage_account,
+                    selection,
+                } => {
+                    let accounts = if selection.all_accounts {
+                        owned_storage_accounts(&client, &signer_pubkey, &selection.filter())
+                            .await?
+                            .into_iter()
+                            .map(|(key, _)| key)
+                            .collect()
+                    } else {
+                        vec![*required_account(storage_account)?]
+                    };
+                    for (i, storage_account) in accounts.iter().enumerate() {
+                        let response = client.claim_stake(storage_account).await;
+                        expect_simulated(response, log.len(), i)?;
+                    }
+                    let cost = OperationCost {
+                        lamports: OperationCost::fee_only().lamports * accounts.len() as u64,
+                        shades: 0,
+                    };
+                    ("claim-stake", cost)
+                }
+                _ => unreachable!("not sent straight to the cluster"),
+            };
+            return Ok(DryRunView {
+                operation: operation.to_string(),
+                estimated_cost: Some(estimated_cost),
+                simulations: log.take(),
+                note: None,
+            });
+        }
+
+        let captured = CapturedMessages::default();
+        let client =
+            clients.shadow_client_with_rpc(CapturingSigner::new(signer, captured.clone()), dry_rpc);
+        let (operation, estimated_cost, note) = match self {
+            Command::CreateStorageAccount { name, size } => {
+                let response = client
+                    .create_storage_account(name, size.clone(), StorageAccountVersion::v2())
+                    .await;
+                expect_simulated(response, captured.len(), 0)?;
+                (
+                    "create-storage-account",
+                    Some(OperationCost::storage(&rpc, size, true).await?),
+                    None,
+                )
+            }
+            Command::AddStorage {
+                storage_account,
+                size,
+            } => {
+                let response = client.add_storage(storage_account, size.clone()).await;
+                expect_simulated(response, captured.len(), 0)?;
+                (
+                    "add-storage",
+                    Some(OperationCost::storage(&rpc, size, false).await?),
+                    None,
+                )
+            }
+            Command::AddImmutableStorage {
+                storage_account,
+                size,
+            } => {
+                let response = client
+                    .add_immutable_storage(storage_account, size.clone())
+                    .await;
+                expect_simulated(response, captured.len(), 0)?;
+                (
+                    "add-immutable-storage",
+                    Some(OperationCost::storage(&rpc, size, false).await?),
+                    None,
+                )
+            }
+            Command::ReduceStorage {
+                storage_account,
+                size,
+            } => {
+                let response = client.reduce_storage(storage_account, size.clone()).await;
+                expect_simulated(response, captured.len(), 0)?;
+                ("reduce-storage", Some(OperationCost::fee_only()), None)
+            }
+            Command::MakeStorageImmutable { storage_account } => {
+                let details =
+                    storage_account_details(&client, &http_client, storage_account).await?;
+                let response = client.make_storage_immutable(storage_account).await;
+                expect_simulated(response, captured.len(), 0)?;
+                (
+                    "make-storage-immutable",
+                    Some(OperationCost::storage(&rpc, &details.capacity, false).await?),
+                    None,
+                )
+            }
+            Command::MigrateAccount {
+                v1_account,
+                name,
+                state_file,
+                ..
+            } => {
+                let state_file = state_file
+                    .clone()
+                    .unwrap_or_else(|| default_state_file(v1_account));
+                let state = MigrationState::load_or_new(&state_file, v1_account)?;
+                if state.destination.is_some() || state.creating.is_some() {
+                    (
+                        "migrate-account",
+                        None,
+                        Some(format!(
+                            "The destination account was already created by the migration \
+                            saved in {}. {}",
+                            state_file.display(),
+                            file_changes.unwrap_or_default()
+                        )),
+                    )
+                } else {
+                    let details =
+                        storage_account_details(&client, &http_client, v1_account).await?;
+                    let name = name.as_deref().unwrap_or(&details.name);
+                    let response = client
+                        .create_storage_account(
+                            name,
+                            details.capacity.clone(),
+                            StorageAccountVersion::v2(),
+                        )
+                        .await;
+                    expect_simulated(response, captured.len(), 0)?;
+                    (
+                        "migrate-account",
+                        Some(OperationCost::storage(&rpc, &details.capacity, true).await?),
+                        file_changes,
+                    )
+                }
+            }
+            Command::Apply { layout, .. } => {
+                let layout = StorageLayout::load(layout)?;
+                let http_client = clients.http_client();
+                let plan =
+                    declarative::plan(&client, &http_client, &signer_pubkey, &layout).await?;
+                eprintln!("{}", plan);
+                let mut not_simulated = false;
+                for change in &plan.changes {
+                    let before = captured.len();
+                    let response = match change {
+                        Change::CreateAccount { name, size } => client
+                            .create_storage_account(name, size.clone(), StorageAccountVersion::v2())
+                            .await
+                            .map(|_| ()),
+                        Change::AddStorage { account, size } => {
+                            client.add_storage(account, size.clone()).await.map(|_| ())
+                        }
+                        Change::AddImmutableStorage { account, size } => client
+                            .add_immutable_storage(account, size.clone())
+                            .await
+                            .map(|_| ()),
+                        Change::ReduceStorage { account, size } => {
+                            client.reduce_storage(account, size.clone()).await.map(|_| ())
+                        }
+                        Change::MakeImmutable {
+                            account: AccountRef::Existing(account),
+                        } => client.make_storage_immutable(account).await.map(|_| ()),
+                        _ => {
+                            not_simulated = true;
+                            continue;
+                        }
+                    };
+                    expect_simulated(response, captured.len(), before)?;
+                }
+                (
+                    "apply",
+                    Some(declarative::estimated_cost(&rpc, &plan).await?),
+                    not_simulated.then(|| {
+                        format!(
+                            "Only storage account changes were simulated. Making an account \
+                            created by the same plan immutable can't be built before the \
+                            account exists. {}",
+                            file_changes.unwrap_or_default()
+                        )
+                    }),
+                )
+            }
+            Command::StoreFiles { .. } => ("store-files", None, file_changes),
+            Command::EditFile { .. } => ("edit-file", None, file_changes),
+            Command::DeleteFile { .. } => ("delete-file", None, file_changes),
+            _ => unreachable!("not a state-changing command"),
+        };
+        let mut simulations = vec![];
+        for message in captured.take() {
+            simulations.push(simulate_message(&rpc, &message).await?);
+        }
+        Ok(DryRunView {
+            operation: operation.to_string(),
+            estimated_cost,
+            simulations,
+            note,
+        })
+    }
+}
+
+/// In a dry run, an SDK call fails once its transaction has been simulated or
+/// captured. If nothing was, past the `before` transactions already handled,
+/// it failed earlier, for a reason worth reporting.
+fn expect_simulated<R>(
+    response: ShadowDriveResult<R>,
+    simulated: usize,
+    before: usize,
+) -> anyhow::Result<()> {
+    if simulated == before {
+        process_shadow_api_response(response)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cli::Opts;
+    use clap::Parser;
+    use shadow_drive_cli::config::parse_headers;
+    use shadow_drive_test_support::{MockResponse, MockServer};
+    use serde_json::json;
+    use shadow_rpc_auth::{BearerAuth, Cassette, RetryPolicy, StaticToken};
+    use solana_sdk::signature::Keypair;
+    use std::collections::HashSet;
+    use std::sync::Arc;
+
+    /// Every command reaching the network must build its clients through the factory,
+    /// rather than from the bare URL, or it would drop `--auth` and the other settings.
+    /// So every command must send requests to the mocks, and each request must carry
+    /// the factory's auth and headers. Commands whose requests the SDK sends itself
+    /// refuse the drive settings, and those requests go to the SDK's own server.
+    ///
+    /// Not run here:
+    /// - `shadow-rpc-auth`, which only talks to the sign-in portal of `GenesysGoSignIn`
+    ///   and builds no client, and would write to the user's token cache.
+    /// - `shell` and `browse`, which read their commands from the terminal.
+    /// - `store-files`, `edit-file` and `delete-file`, whose uploads, edits and
+    ///   deletions the SDK sends to the real Shadow Drive server, see
+    ///   `runs_read_commands_against_the_emulator`.
+    #[tokio::test]
+    async fn every_command_uses_client_factory() {
+        let rpc = MockServer::rpc(|_, _| Err((-32601, "not served by this mock".to_string())))
+            .unwrap();
+        let drive = MockServer::always(MockResponse::new(404, "not found")).unwrap();
+        let account = Pubkey::new_unique().to_string();
+        let layout = std::env::temp_dir().join(format!("layout-{}.toml", std::process::id()));
+        std::fs::write(&layout, "[[accounts]]\nname = \"site\"\nsize = \"1MB\"\n").unwrap();
+        let layout = layout.to_str().unwrap();
+        let state_file =
+            std::env::temp_dir().join(format!("migration-{}.json", std::process::id()));
+        let state_file = state_file.to_str().unwrap();
+        let commands: Vec<Vec<&str>> = vec![
+            vec!["__complete", "accounts"],
+            vec!["__complete", "files", "--storage-account", &account],
+            vec!["wallet", "balance"],
+            vec!["wallet", "create-shdw-account"],
+            vec!["wallet", "can-afford", "1MB"],
+            vec!["create-storage-account", "name", "1MB"],
+            vec!["delete-storage-account", &account],
+            vec!["cancel-delete-storage-account", &account],
+            vec!["claim-stake", &account],
+            vec!["add-storage", &account, "1MB"],
+            vec!["add-immutable-storage", &account, "1MB"],
+            vec!["reduce-storage", &account, "1MB"],
+            vec!["make-storage-immutable", &account],
+            vec!["get-storage-account", &account],
+            vec!["get-storage-accounts"],
+            vec!["storage-usage", &account],
+            vec!["list-files", &account],
+            vec!["get-text", &account, "file.txt"],
+            vec!["get-object-data", &account, "file.txt"],
+            vec!["migrate-account", &account, "--state-file", state_file],
+            vec!["plan", layout],
+            vec!["apply", layout],
+        ];
+        for args in commands {
+            let opts = Opts::try_parse_from(
+                std::iter::once("shadow-drive-cli").chain(args.iter().copied()),
+            )
+            .unwrap();
+            let mut clients = ClientFactory::new(rpc.url())
+                .with_auth(Some(Arc::new(BearerAuth::new(StaticToken(
+                    "jwt".to_string(),
+                )))))
+                .with_rpc_headers(parse_headers(&["x-tenant: acme"]).unwrap())
+                .with_timeout(Duration::from_millis(100))
+                .with_retry_policy(RetryPolicy::none());
+            if !opts.command.uses_sdk_drive_requests() {
+                clients = clients
+                    .with_drive_host(Some(drive.url().to_string()))
+                    .with_storage_server(Some(drive.url().to_string()))
+                    .with_drive_headers(parse_headers(&["x-drive-key: secret"]).unwrap());
+            }
+            let (rpc_before, drive_before) = (rpc.received().len(), drive.received().len());
+            let confirm = ConfirmPolicy {
+                skip_confirm: true,
+                yes_i_am_sure_immutable: true,
+            };
+            let result = tokio::time::timeout(
+                Duration::from_secs(10),
+                opts.command.process(
+                    Keypair::new(),
+                    &clients,
+                    confirm,
+                    GenesysGoSignIn::default(),
+                    OutputFormat::Json,
+                    false,
+                ),
+            )
+            .await
+            .unwrap_or_else(|_| panic!("{:?} did not finish", args));
+            let rpc_requests = &rpc.received()[rpc_before..];
+            let drive_requests = &drive.received()[drive_before..];
+            assert!(
+                !rpc_requests.is_empty() || !drive_requests.is_empty(),
+                "{:?} sent no request to the mocks: {:?}",
+                args,
+                result
+            );
+            for request in rpc_requests {
+                assert_eq!(
+                    (request.header("authorization"), request.header("x-tenant")),
+                    (Some("Bearer jwt"), Some("acme")),
+                    "{:?} sent {} without the factory's auth and headers",
+                    args,
+                    request.rpc_method()
+                );
+            }
+            for request in drive_requests {
+                assert_eq!(
+                    request.header("x-drive-key"),
+                    Some("secret"),
+                    "{:?} sent {} without the drive headers",
+                    args,
+                    request.path
+                );
+            }
+        }
+        let paths: HashSet<String> = drive.received().into_iter().map(|r| r.path).collect();
+        for path in ["/list-objects", "/get-object-data"] {
+            assert!(paths.contains(path), "nothing reached {}", path);
+        }
+        std::fs::remove_file(layout).unwrap();
+    }
+
+    async fn run(args: &[&str], clients: &ClientFactory) -> anyhow::Result<()> {
+        let opts = Opts::try_parse_from(
+            std::iter::once("shadow-drive-cli").chain(args.iter().copied()),
+        )?;
+        let confirm = ConfirmPolicy {
+            skip_confirm: true,
+            yes_i_am_sure_immutable: true,
+        };
+        opts.command
+            .process(
+                Keypair::new(),
+                clients,
+                confirm,
+                GenesysGoSignIn::default(),
+                OutputFormat::Json,
+                false,
+            )
+            .await
+    }
+
+    /// The shell and terminal UI change files as they go, so a dry run of them
+    /// must be refused rather than run for real.
+    #[tokio::test]
+    async fn refuses_dry_runs_of_interactive_commands() {
+        let clients = ClientFactory::new("http://127.0.0.1:1");
+        for args in [["shell"], ["browse"]] {
+            let opts = Opts::try_parse_from(["shadow-drive-cli", args[0], "--dry-run"]).unwrap();
+            assert!(opts.cfg_override.dry_run);
+            let err = opts
+                .command
+                .process(
+                    Keypair::new(),
+                    &clients,
+                    ConfirmPolicy::default(),
+                    GenesysGoSignIn::default(),
+                    OutputFormat::Json,
+                    opts.cfg_override.dry_run,
+                )
+                .await
+                .unwrap_err();
+            assert!(err.to_string().contains("--dry-run"), "{:?}: {:#}", args, err);
+        }
+        assert_eq!(clients.clients_built(), 0);
+    }
+
+    /// `--drive-host` and `--storage-server` take the read-only commands to the
+    /// emulator, and are refused by those whose requests the SDK sends itself.
+    /// Those commands can't be run against the emulator end to end, as the SDK only
+    /// sends their uploads, edits and deletions to the real Shadow Drive server,
+    /// so here they only have to refuse it before sending anything.
+    #[tokio::test]
+    async fn runs_read_commands_against_the_emulator() {
+        let account = Pubkey::new_unique().to_string();
+        let store = shadow_drive_emulator::Store::in_memory();
+        store.put(&account, "notes.txt", b"hello".to_vec()).unwrap();
+        let host = shadow_drive_emulator::spawn("127.0.0.1:0".parse().unwrap(), store).unwrap();
+        let clients = ClientFactory::new("http://127.0.0.1:1")
+            .with_retry_policy(RetryPolicy::none())
+            .with_drive_host(Some(host.clone()))
+            .with_storage_server(Some(host));
+        for args in [
+            vec!["list-files", &account],
+            vec!["get-text", &account, "notes.txt"],
+            vec!["get-object-data", &account, "notes.txt"],
+        ] {
+            if let Err(err) = run(&args, &clients).await {
+                panic!("{:?} failed against the emulator: {:#}", args, err);
+            }
+        }
+        let stats = clients.metrics().snapshot();
+        let methods: Vec<&str> = stats.iter().map(|stat| stat.method.as_str()).collect();
+        assert_eq!(
+            methods,
+            ["GET file", "HEAD file", "get-object-data", "list-objects"]
+        );
+        assert!(stats.iter().all(|stat| stat.errors == 0));
+
+        for args in [
+            vec!["store-files", &account, "notes.txt"],
+            vec!["edit-file", &account, "notes.txt"],
+            vec!["delete-file", &account, "notes.txt"],
+        ] {
+            let err = run(&args, &clients).await.unwrap_err();
+            assert!(
+                err.to_string().contains("--drive-host"),
+                "{:?} did not refuse the emulator: {:#}",
+                args,
+                err
+            );
+        }
+        assert_eq!(clients.metrics().snapshot(), stats);
+    }
+
+    /// Commands recorded with `--record` replay from the cassette with no server
+    /// answering, both their RPC and their Shadow Drive requests.
+    #[tokio::test]
+    async fn replays_recorded_commands() {
+        let rpc = MockServer::rpc(|method, _| match method {
+            "getProgramAccounts" => Ok(json!([])),
+            _ => Err((-32601, "not served by this mock".to_string())),
+        })
+        .unwrap();
+        let drive =
+            MockServer::always(MockResponse::json(json!({ "keys": ["notes.txt"] }))).unwrap();
+        let owner = Pubkey::new_unique().to_string();
+        let account = Pubkey::new_unique().to_string();
+        let commands = [
+            vec!["get-storage-accounts", &owner],
+            vec!["list-files", &account],
+        ];
+        let path = std::env::temp_dir().join(format!("commands-{}.jsonl", std::process::id()));
+        let factory = |url: &str, cassette: Cassette| {
+            ClientFactory::new(url)
+                .with_retry_policy(RetryPolicy::none())
+                .with_drive_host(Some(drive.url().to_string()))
+                .with_storage_server(Some(drive.url().to_string()))
+                .with_cassette(Some(Arc::new(cassette)))
+        };
+
+        let recording = factory(rpc.url(), Cassette::record(&path).unwrap());
+        for args in &commands {
+            if let Err(err) = run(args, &recording).await {
+                panic!("{:?} failed while recording: {:#}", args, err);
+            }
+        }
+        let (rpc_requests, drive_requests) = (rpc.received().len(), drive.received().len());
+        assert!(rpc_requests > 0 && drive_requests > 0);
+
+        let replaying = factory("http://127.0.0.1:1", Cassette::replay(&path).unwrap());
+        for args in &commands {
+            if let Err(err) = run(args, &replaying).await {
+                panic!("{:?} failed to replay: {:#}", args, err);
+            }
+        }
+        assert_eq!(rpc.received().len(), rpc_requests);
+        assert_eq!(drive.received().len(), drive_requests);
+        std::fs::remove_file(path).unwrap();
+    }
 }
@@ -0,0 +1,345 @@
+use rustyline::completion::{Completer, FilenameCompleter, Pair};
+use rustyline::error::ReadlineError;
+use rustyline::highlight::Highlighter;
+use rustyline::hint::Hinter;
+use rustyline::validate::Validator;
+use rustyline::{Context, Editor, Helper};
+use shadow_drive_cli::client::DriveHttpClient;
+use shadow_drive_cli::error::{report, CliError};
+use shadow_drive_cli::migration::download_file;
+use shadow_drive_cli::output::{
+    print_output, DeletedFileView, FileListView, JsonView, OutputFormat, StorageAccountListView,
+    StorageAccountView, TextFileView, UploadView,
+};
+use shadow_drive_cli::storage_accounts::{owned_storage_accounts, storage_account_name};
+use shadow_drive_cli::{
+    acquire_basename, get_object_data_json, get_text, last_modified, list_objects,
+    local_download_path, process_shadow_api_response, save_new_file, wait_for_user_confirmation,
+    ConfirmPolicy,
+};
+use shadow_drive_rust::models::ShadowFile;
+use shadow_drive_rust::ShadowDriveClient;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::Signer;
+use std::cell::RefCell;
+use std::path::PathBuf;
+use std::rc::Rc;
+use std::str::FromStr;
+
+const COMMANDS: [&str; 11] = [
+    "accounts", "use", "ls", "info", "cat", "get", "put", "rm", "refresh", "help", "exit",
+];
+
+const HELP: &str = "\
+accounts              list storage accounts owned by the signer
+use <account>         select a storage account by name or address
+ls                    list files in the selected storage account
+info [file]           show the selected storage account, or a file's object data
+cat <file>            print a text file
+get <file> [path]     download a file to a new local file, its own name by default
+put <path>...         upload one or more local files
+rm <file>...          delete files from the selected storage account
+refresh               reload accounts and files used for tab completion
+help                  show this message
+exit                  leave the shell (also CTRL+D)";
+
+/// Names offered by tab completion, refreshed as the session changes.
+#[derive(Debug, Default)]
+struct Completions {
+    accounts: Vec<(String, Pubkey)>,
+    files: Vec<String>,
+}
+
+struct ShellHelper {
+    completions: Rc<RefCell<Completions>>,
+    filenames: FilenameCompleter,
+}
+
+impl Completer for ShellHelper {
+    type Candidate = Pair;
+
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        ctx: &Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<Pair>)> {
+        let start = line[..pos].rfind(' ').map(|i| i + 1).unwrap_or(0);
+        let word = &line[start..pos];
+        let command = line[..start].split_whitespace().next();
+        let completions = self.completions.borrow();
+        let candidates: Vec<String> = match command {
+            None => COMMANDS.iter().map(|c| c.to_string()).collect(),
+            Some("use") => completions
+                .accounts
+                .iter()
+                .flat_map(|(name, key)| [name.clone(), key.to_string()])
+                .collect(),
+            Some("info") | Some("cat") | Some("get") | Some("rm") => completions.files.clone(),
+            Some("put") => return self.filenames.complete(line, pos, ctx),
+            Some(_) => vec![],
+        };
+        let pairs = candidates
+            .into_iter()
+            .filter(|candidate| !candidate.is_empty() && candidate.starts_with(word))
+            .map(|candidate| Pair {
+                display: candidate.clone(),
+                replacement: candidate,
+            })
+            .collect();
+        Ok((start, pairs))
+    }
+}
+
+impl Hinter for ShellHelper {
+    type Hint = String;
+}
+
+impl Highlighter for ShellHelper {}
+
+impl Validator for ShellHelper {}
+
+impl Helper for ShellHelper {}
+
+/// One authenticated client kept alive across commands,
+/// plus the storage account selected with `use`.
+struct Session<'a, T: Signer> {
+    client: &'a ShadowDriveClient<T>,
+    http_client: DriveHttpClient,
+    owner: Pubkey,
+    confirm: ConfirmPolicy,
+    output: OutputFormat,
+    current: Option<(Pubkey, String)>,
+    completions: Rc<RefCell<Completions>>,
+}
+
+/// Where shell history is kept between sessions.
+fn history_file() -> Option<PathBuf> {
+    dirs_next::config_dir().map(|dir| dir.join("shadow-drive-cli").join("shell_history"))
+}
+
+/// Run the interactive shell until the user exits.
+pub async fn run<T: Signer>(
+    client: &ShadowDriveClient<T>,
+    http_client: DriveHttpClient,
+    owner: Pubkey,
+    confirm: ConfirmPolicy,
+    output: OutputFormat,
+) -> anyhow::Result<()> {
+    let completions = Rc::new(RefCell::new(Completions::default()));
+    let mut editor = Editor::<ShellHelper>::new()?;
+    editor.set_helper(Some(ShellHelper {
+        completions: completions.clone(),
+        filenames: FilenameCompleter::new(),
+    }));
+    let history = history_file();
+    if let Some(history) = &history {
+        // There is no history yet on first use.
+        let _ = editor.load_history(history);
+    }
+    let mut session = Session {
+        client,
+        http_client,
+        owner,
+        confirm,
+        output,
+        current: None,
+        completions,
+    };
+    if let Err(err) = session.refresh().await {
+        report(&err);
+    }
+    eprintln!("Shadow Drive shell for {}, type `help` for commands", owner.to_string());
+    loop {
+        let prompt = match &session.current {
+            Some((_, name)) => format!("shdw:{}> ", name),
+            None => "shdw> ".to_string(),
+        };
+        let line = match editor.readline(&prompt) {
+            Ok(line) => line,
+            Err(ReadlineError::Interrupted) => continue,
+            Err(ReadlineError::Eof) => break,
+            Err(err) => return Err(err.into()),
+        };
+        let words: Vec<&str> = line.split_whitespace().collect();
+        if words.is_empty() {
+            continue;
+        }
+        editor.add_history_entry(line.as_str());
+        if matches!(words[0], "exit" | "quit") {
+            break;
+        }
+        if let Err(err) = session.execute(words[0], &words[1..]).await {
+            report(&err);
+        }
+    }
+    if let Some(history) = &history {
+        if let Some(dir) = history.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        editor.save_history(history)?;
+    }
+    Ok(())
+}
+
+impl<'a, T: Signer> Session<'a, T> {
+    async fn execute(&mut self, command: &str, args: &[&str]) -> anyhow::Result<()> {
+        match (command, args) {
+            ("help", _) => println!("{}", HELP),
+            ("refresh", []) => self.refresh().await?,
+            ("accounts", []) => {
+                let accounts =
+                    owned_storage_accounts(self.client, &self.owner, &Default::default()).await?;
+                let view = StorageAccountListView(
+                    accounts
+                        .iter()
+                        .map(|(key, account)| StorageAccountView::new(*key, account))
+                        .collect(),
+                );
+                print_output(self.output, &view)?;
+            }
+            ("use", [account]) => {
+                let (key, name) = self.resolve_account(account)?;
+                self.current = Some((key, name));
+                self.refresh_files().await?;
+            }
+            ("ls", []) => {
+                let storage_account = self.current_account()?;
+                let files = list_objects(&self.http_client, &storage_account).await?;
+                self.completions.borrow_mut().files = files.clone();
+                print_output(
+                    self.output,
+                    &FileListView {
+                        storage_account,
+                        files,
+                        drive_host: self.http_client.drive_host().to_string(),
+                    },
+                )?;
+            }
+            ("info", []) => {
+                let storage_account = self.current_account()?;
+                let response = self.client.get_storage_account(&storage_account).await;
+                let account = process_shadow_api_response(response)?;
+                print_output(
+                    self.output,
+                    &StorageAccountView::new(storage_account, &account),
+                )?;
+            }
+            ("info", [file]) => {
+                let location = self.http_client.file_url(&self.current_account()?, file);
+                let data = get_object_data_json(&self.http_client, &location).await?;
+                print_output(self.output, &JsonView(data))?;
+            }
+            ("cat", [file]) => {
+                let location = self.http_client.file_url(&self.current_account()?, file);
+                let resp = get_text(&self.http_client, &location).await?;
+                let last_modified = last_modified(resp.headers())?;
+                let view = TextFileView {
+                    url: location,
+                    last_modified,
+                    text: resp.text().await?,
+                };
+                print_output(self.output, &view)?;
+            }
+            ("get", [file]) | ("get", [file, _]) => {
+                let storage_account = self.current_account()?;
+                let path = match args.get(1) {
+                    Some(path) => PathBuf::from(path),
+                    None => local_download_path(file)?,
+                };
+                let bytes = download_file(&self.http_client, &storage_account, file).await?;
+                save_new_file(&path, &bytes)?;
+                eprintln!("Saved {} ({} bytes)", path.display(), bytes.len());
+            }
+            ("put", files) if !files.is_empty() => {
+                let storage_account = self.current_account()?;
+                let paths: Vec<String> = files.iter().map(|file| file.to_string()).collect();
+                let uploads = paths
+                    .iter()
+                    .map(|file| ShadowFile::file(acquire_basename(file), file.clone()))
+                    .collect();
+                let response = self.client.store_files(&storage_account, uploads).await;
+                let resp = process_shadow_api_response(response)?;
+                let mut view = UploadView::new(storage_account);
+                view.record_response(&paths, &resp);
+                print_output(self.output, &view)?;
+                self.refresh_files().await?;
+                if let Some(err) = view.failure() {
+                    return Err(err.into());
+                }
+            }
+            ("rm", files) if !files.is_empty() => {
+                let storage_account = self.current_account()?;
+                for file in files {
+                    let location = self.http_client.file_url(&storage_account, file);
+                    eprintln!("Delete file {}", &location);
+                    wait_for_user_confirmation(self.confirm.skip_confirm)?;
+                    let response = self
+                        .client
+                        .delete_file(&storage_account, location.clone())
+                        .await;
+                    let resp = process_shadow_api_response(response)?;
+                    let view = DeletedFileView {
+                        storage_account,
+                        url: location,
+                        message: resp.message,
+                    };
+                    print_output(self.output, &view)?;
+                }
+                self.refresh_files().await?;
+            }
+            _ => {
+                return Err(CliError::Other(format!(
+                    "unknown command or wrong arguments: {} {}, type `help` for usage",
+                    command,
+                    args.join(" ")
+                ))
+                .into())
+            }
+        }
+        Ok(())
+    }
+
+    fn current_account(&self) -> anyhow::Result<Pubkey> {
+        self.current.as_ref().map(|(key, _)| *key).ok_or_else(|| {
+            CliError::Other("no storage account selected, run `use <account>` first".to_string())
+                .into()
+        })
+    }
+
+    /// Look up an account among those owned by the signer by name,
+    /// or accept any address as-is.
+    fn resolve_account(&self, account: &str) -> anyhow::Result<(Pubkey, String)> {
+        let completions = self.completions.borrow();
+        if let Some((name, key)) = completions
+            .accounts
+            .iter()
+            .find(|(name, key)| name == account || key.to_string() == account)
+        {
+            return Ok((*key, name.clone()));
+        }
+        let key = Pubkey::from_str(account).map_err(|_| {
+            CliError::NotFound(format!("no storage account named {}", account))
+        })?;
+        Ok((key, account.to_string()))
+    }
+
+    async fn refresh(&mut self) -> anyhow::Result<()> {
+        let accounts =
+            owned_storage_accounts(self.client, &self.owner, &Default::default()).await?;
+        self.completions.borrow_mut().accounts = accounts
+            .iter()
+            .map(|(key, account)| (storage_account_name(account).to_string(), *key))
+            .collect();
+        self.refresh_files().await
+    }
+
+    async fn refresh_files(&mut self) -> anyhow::Result<()> {
+        let files = match &self.current {
+            Some((storage_account, _)) => list_objects(&self.http_client, storage_account).await?,
+            None => vec![],
+        };
+        self.completions.borrow_mut().files = files;
+        Ok(())
+    }
+}
@@ -0,0 +1,648 @@
+//! The one place RPC, Shadow Drive and HTTP clients are built, so that `--auth`,
+//! profiles and transport settings apply to every command alike.
+use log::{debug, trace, warn};
+use reqwest::header::HeaderMap;
+use reqwest::{Method, RequestBuilder, Response};
+use serde_json::Value;
+use shadow_drive_rust::ShadowDriveClient;
+use shadow_rpc_auth::cassette::{describe_failure, strip_query, Interaction, RECORDED_HEADERS};
+use shadow_rpc_auth::http_sender::redact_headers;
+use shadow_rpc_auth::metrics::{RequestRecord, DRIVE};
+use shadow_rpc_auth::{
+    AuthProvider, Cassette, FailoverSender, HttpSenderWithHeaders, RetryPolicy, TransportMetrics,
+};
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_client::rpc_client::RpcClientConfig;
+use solana_sdk::commitment_config::CommitmentConfig;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::Signer;
+use std::collections::BTreeMap;
+use std::ops::Deref;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::dry_run::{DryRunLog, DryRunSender};
+use crate::error::CliError;
+use crate::{drive_url, GENESYSGO_DRIVE, SHDW_STORAGE_SERVER};
+
+/// Request timeout when none is configured, the same as the Solana RPC client's.
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Clap value parser for `processed`, `confirmed` or `finalized`.
+pub fn commitment_arg(commitment: &str) -> anyhow::Result<CommitmentConfig> {
+    CommitmentConfig::from_str(commitment)
+        .map_err(|_| anyhow::anyhow!("invalid commitment level: {}", commitment))
+}
+
+/// Builds every client a command needs from the same settings.
+/// Cloning is cheap, clones share the [ClientFactory::clients_built] count
+/// and the [ClientFactory::metrics] of every request made by their clients.
+#[derive(Clone)]
+pub struct ClientFactory {
+    url: String,
+    auth: Option<Arc<dyn AuthProvider>>,
+    fallbacks: Vec<(String, Option<Arc<dyn AuthProvider>>, HeaderMap)>,
+    hedge: Option<Duration>,
+    rpc_headers: HeaderMap,
+    drive_headers: HeaderMap,
+    retry: RetryPolicy,
+    timeout: Duration,
+    commitment: CommitmentConfig,
+    drive_host: String,
+    storage_server: String,
+    built: Arc<AtomicUsize>,
+    metrics: Arc<TransportMetrics>,
+    cassette: Option<Arc<Cassette>>,
+}
+
+impl ClientFactory {
+    /// Clients for the RPC endpoint at `url`, unauthenticated, with the
+    /// [DEFAULT_TIMEOUT] and `confirmed` commitment.
+    pub fn new<U: ToString>(url: U) -> Self {
+        Self {
+            url: url.to_string(),
+            auth: None,
+            fallbacks: Vec::new(),
+            hedge: None,
+            rpc_headers: HeaderMap::new(),
+            drive_headers: HeaderMap::new(),
+            retry: RetryPolicy::default(),
+            timeout: DEFAULT_TIMEOUT,
+            commitment: CommitmentConfig::confirmed(),
+            drive_host: GENESYSGO_DRIVE.to_string(),
+            storage_server: SHDW_STORAGE_SERVER.to_string(),
+            built: Arc::new(AtomicUsize::new(0)),
+            metrics: Arc::new(TransportMetrics::new()),
+            cassette: None,
+        }
+    }
+
+    /// Authenticate RPC requests with `auth`, if there is a provider.
+    pub fn with_auth(mut self, auth: Option<Arc<dyn AuthProvider>>) -> Self {
+        self.auth = auth;
+        self
+    }
+
+    /// Fail over to the RPC endpoint at `url`, authenticated with `auth` and sent
+    /// `headers`, when the endpoints before it fail. Fallbacks are tried in the
+    /// order they are added. The primary's auth and headers never reach them.
+    pub fn with_fallback<U: ToString>(
+        mut self,
+        url: U,
+        auth: Option<Arc<dyn AuthProvider>>,
+        headers: HeaderMap,
+    ) -> Self {
+        self.fallbacks.push((url.to_string(), auth, headers));
+        self
+    }
+
+    /// Also send read requests to the next endpoint when they take longer than `delay`.
+    pub fn with_hedging(mut self, delay: Option<Duration>) -> Self {
+        self.hedge = delay;
+        self
+    }
+
+    /// Send `headers` with every JSON-RPC request to the primary endpoint,
+    /// e.g. gateway keys or tracing IDs.
+    pub fn with_rpc_headers(mut self, headers: HeaderMap) -> Self {
+        self.rpc_headers = headers;
+        self
+    }
+
+    /// Send `headers` with every request from [ClientFactory::http_client].
+    /// The SDK's own requests can't carry them, see [ClientFactory::ensure_reaches_sdk_requests].
+    pub fn with_drive_headers(mut self, headers: HeaderMap) -> Self {
+        self.drive_headers = headers;
+        self
+    }
+
+    /// Generate file URLs on, and download files from, `host` rather than
+    /// [GENESYSGO_DRIVE], e.g. `shadow-drive-emulator`.
+    pub fn with_drive_host(mut self, host: Option<String>) -> Self {
+        if let Some(host) = host {
+            self.drive_host = host.trim_end_matches('/').to_string();
+        }
+        self
+    }
+
+    /// Send the CLI's own Shadow Drive server requests, such as file listings and
+    /// object metadata, to `server` rather than [SHDW_STORAGE_SERVER].
+    pub fn with_storage_server(mut self, server: Option<String>) -> Self {
+        if let Some(server) = server {
+            self.storage_server = server.trim_end_matches('/').to_string();
+        }
+        self
+    }
+
+    /// Retry failed JSON-RPC requests as `retry` says.
+    pub fn with_retry_policy(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    /// Record the responses to every JSON-RPC and Shadow Drive HTTP request to
+    /// `cassette`, or replay them from it, see [shadow_rpc_auth::cassette].
+    pub fn with_cassette(mut self, cassette: Option<Arc<Cassette>>) -> Self {
+        self.cassette = cassette;
+        self
+    }
+
+    /// Timeout of each RPC and Shadow Drive HTTP request.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    pub fn with_commitment(mut self, commitment: CommitmentConfig) -> Self {
+        self.commitment = commitment;
+        self
+    }
+
+    /// The primary RPC endpoint.
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    pub fn fallback_urls(&self) -> impl Iterator<Item = &str> {
+        self.fallbacks.iter().map(|(url, _, _)| url.as_str())
+    }
+
+    pub fn timeout(&self) -> Duration {
+        self.timeout
+    }
+
+    pub fn commitment(&self) -> CommitmentConfig {
+        self.commitment
+    }
+
+    pub fn is_authenticated(&self) -> bool {
+        self.auth.is_some()
+    }
+
+    /// Requests made so far by every client this factory (or a clone of it) has built.
+    /// The uploads, edits, deletions and storage account changes the Shadow Drive SDK
+    /// sends over HTTP itself aren't included.
+    pub fn metrics(&self) -> Arc<TransportMetrics> {
+        self.metrics.clone()
+    }
+
+    /// Fail if a setting which only reaches the CLI's own Shadow Drive requests
+    /// is in use, for a command whose Shadow Drive requests the SDK makes itself.
+    pub fn ensure_reaches_sdk_requests(&self) -> anyhow::Result<()> {
+        let mut unreachable = vec![];
+        if self.cassette.is_some() {
+            unreachable.push("--record and --replay");
+        }
+        if !self.drive_headers.is_empty() {
+            unreachable.push("--drive-header");
+        }
+        if self.drive_host != GENESYSGO_DRIVE || self.storage_server != SHDW_STORAGE_SERVER {
+            unreachable.push("--drive-host and --storage-server");
+        }
+        if unreachable.is_empty() {
+            return Ok(());
+        }
+        Err(CliError::Other(format!(
+            "{} can't be used with this command: the Shadow Drive SDK sends its \
+            Shadow Drive requests itself, and they would not be covered",
+            unreachable.join(", ")
+        ))
+        .into())
+    }
+
+    /// How many clients this factory (or a clone of it) has built.
+    pub fn clients_built(&self) -> usize {
+        self.built.load(Ordering::Relaxed)
+    }
+
+    fn endpoint(
+        &self,
+        url: &str,
+        auth: &Option<Arc<dyn AuthProvider>>,
+        headers: &HeaderMap,
+    ) -> HttpSenderWithHeaders {
+        let sender =
+            HttpSenderWithHeaders::new_with_timeout(url, self.timeout, Some(headers.clone()))
+                .with_retry_policy(self.retry.clone())
+                .with_metrics(self.metrics.clone());
+        let sender = match &self.cassette {
+            Some(cassette) => sender.with_cassette(cassette.clone()),
+            None => sender,
+        };
+        match auth {
+            Some(provider) => sender.with_auth_provider(provider.clone()),
+            None => sender,
+        }
+    }
+
+    fn sender(&self) -> FailoverSender {
+        let endpoints = std::iter::once(self.endpoint(&self.url, &self.auth, &self.rpc_headers))
+            .chain(
+                self.fallbacks
+                    .iter()
+                    .map(|(url, auth, headers)| self.endpoint(url, auth, headers)),
+            )
+            .collect();
+        FailoverSender::new(endpoints)
+            .expect("the primary endpoint")
+            .with_hedging(self.hedge)
+    }
+
+    fn rpc_config(&self) -> RpcClientConfig {
+        self.built.fetch_add(1, Ordering::Relaxed);
+        RpcClientConfig::with_commitment(self.commitment)
+    }
+
+    /// An [RpcClient] for wallet queries, cost estimates and transactions sent directly.
+    pub fn rpc_client(&self) -> RpcClient {
+        RpcClient::new_sender(self.sender(), self.rpc_config())
+    }
+
+    /// An [RpcClient] which simulates transactions into `log` instead of sending them.
+    pub fn dry_run_rpc_client(&self, log: DryRunLog) -> RpcClient {
+        RpcClient::new_sender(DryRunSender::new(self.sender(), log), self.rpc_config())
+    }
+
+    /// A [ShadowDriveClient] signing with `signer`, over a new [ClientFactory::rpc_client].
+    pub fn shadow_client<T: Signer>(&self, signer: T) -> ShadowDriveClient<T> {
+        ShadowDriveClient::new_with_rpc(signer, self.rpc_client())
+    }
+
+    /// A [ShadowDriveClient] over an RPC client built by this factory, e.g. for dry runs.
+    pub fn shadow_client_with_rpc<T: Signer>(
+        &self,
+        signer: T,
+        rpc: RpcClient,
+    ) -> ShadowDriveClient<T> {
+        ShadowDriveClient::new_with_rpc(signer, rpc)
+    }
+
+    /// An HTTP client for requests the CLI makes to the Shadow Drive server
+    /// and file host itself, such as downloads, file listings and object metadata.
+    /// Commands whose requests the SDK makes internally refuse the drive headers.
+    pub fn http_client(&self) -> DriveHttpClient {
+        self.built.fetch_add(1, Ordering::Relaxed);
+        if !self.drive_headers.is_empty() {
+            debug!(
+                "Shadow Drive HTTP client with headers {:?}",
+                redact_headers(&self.drive_headers)
+            );
+        }
+        let client = reqwest::Client::builder()
+            .default_headers(self.drive_headers.clone())
+            .timeout(self.timeout)
+            .build()
+            .expect("build http client");
+        DriveHttpClient {
+            client,
+            drive_host: self.drive_host.clone(),
+            storage_server: self.storage_server.clone(),
+            headers: self.drive_headers.clone(),
+            metrics: self.metrics.clone(),
+            cassette: self.cassette.clone(),
+        }
+    }
+}
+
+/// The CLI's own HTTP client for the Shadow Drive server and file host.
+/// Requests are built through the [reqwest::Client] it dereferences to,
+/// and sent with [DriveHttpClient::send] so that they count in the metrics.
+#[derive(Clone)]
+pub struct DriveHttpClient {
+    client: reqwest::Client,
+    drive_host: String,
+    storage_server: String,
+    /// The client's default headers, for tracing.
+    headers: HeaderMap,
+    metrics: Arc<TransportMetrics>,
+    cassette: Option<Arc<Cassette>>,
+}
+
+impl Deref for DriveHttpClient {
+    type Target = reqwest::Client;
+
+    fn deref(&self) -> &Self::Target {
+        &self.client
+    }
+}
+
+impl DriveHttpClient {
+    /// Where Shadow Drive files are hosted, see [ClientFactory::with_drive_host].
+    pub fn drive_host(&self) -> &str {
+        &self.drive_host
+    }
+
+    /// The Shadow Drive server, see [ClientFactory::with_storage_server].
+    pub fn storage_server(&self) -> &str {
+        &self.storage_server
+    }
+
+    /// The URL of `file` in `storage_account`.
+    pub fn file_url(&self, storage_account: &Pubkey, file: &str) -> String {
+        drive_url(&self.drive_host, storage_account, file)
+    }
+
+    /// Send `request`, recording it as `method`, e.g. `get-object-data`.
+    /// Latency is to the response headers, and bytes received are as
+    /// given by `Content-Length`. With a cassette, the whole response is
+    /// recorded before it is returned, or replayed without sending anything.
+    /// Requests are traced with their headers redacted and without query strings.
+    pub async fn send(&self, method: &str, request: RequestBuilder) -> anyhow::Result<Response> {
+        let request = request.build()?;
+        debug!(
+            "Shadow Drive {}: {} {}",
+            method,
+            request.method(),
+            strip_query(request.url().as_str())
+        );
+        if log::log_enabled!(log::Level::Trace) {
+            let mut headers = self.headers.clone();
+            headers.extend(request.headers().clone());
+            trace!(
+                "Shadow Drive {} headers: {:?}",
+                method,
+                redact_headers(&headers)
+            );
+        }
+        let head = request.method() == Method::HEAD;
+        let bytes_sent = request
+            .body()
+            .and_then(|body| body.as_bytes())
+            .map_or(0, |body| body.len() as u64);
+        let recorded = Interaction {
+            transport: DRIVE.to_string(),
+            method: request.method().to_string(),
+            url: strip_query(request.url().as_str()).to_string(),
+            request: request
+                .body()
+                .and_then(|body| body.as_bytes())
+                .map_or(Value::Null, |body| {
+                    Value::String(String::from_utf8_lossy(body).into_owned())
+                }),
+            status: 0,
+            headers: BTreeMap::new(),
+            response: Value::Null,
+            base64: false,
+            error: None,
+        };
+        let started = Instant::now();
+        let response = match self.cassette.as_deref() {
+            Some(cassette) if cassette.is_replaying() => cassette
+                .replay_interaction(DRIVE, &recorded.method, &recorded.url, &recorded.request)
+                .and_then(|interaction| interaction_response(&interaction)),
+            Some(cassette) => match self.client.execute(request).await {
+                Ok(response) => record(cassette, recorded, response).await,
+                Err(err) => {
+                    let failed = Interaction {
+                        error: Some(describe_failure(&err)),
+                        ..recorded
+                    };
+                    if let Err(err) = cassette.record_interaction(&failed) {
+                        warn!("Unable to record {} {}: {}", failed.method, failed.url, err);
+                    }
+                    Err(err.into())
+                }
+            },
+            None => self.client.execute(request).await.map_err(Into::into),
+        };
+        match &response {
+            Ok(response) => debug!("Shadow Drive {}: {}", method, response.status()),
+            Err(err) => debug!("Shadow Drive {} failed: {:#}", method, err),
+        }
+        let bytes_received = match &response {
+            Ok(response) if !head => response.content_length().unwrap_or(0),
+            _ => 0,
+        };
+        self.metrics.record(
+            DRIVE,
+            method,
+            RequestRecord {
+                elapsed: started.elapsed(),
+                bytes_sent,
+                bytes_received,
+                failed: !matches!(&response, Ok(response) if response.status().is_success()),
+                ..Default::default()
+            },
+        );
+        response
+    }
+}
+
+/// Read `response` into `interaction` and record it, then return it unread again.
+async fn record(
+    cassette: &Cassette,
+    mut interaction: Interaction,
+    response: Response,
+) -> anyhow::Result<Response> {
+    interaction.status = response.status().as_u16();
+    interaction.headers = RECORDED_HEADERS
+        .iter()
+        .filter_map(|name| {
+            let value = response.headers().get(*name)?.to_str().ok()?;
+            Some((name.to_string(), value.to_string()))
+        })
+        .collect();
+    let body = response.bytes().await?;
+    (interaction.response, interaction.base64) = match std::str::from_utf8(&body) {
+        Ok(text) => (Value::String(text.to_string()), false),
+        Err(_) => (Value::String(base64::encode(&body)), true),
+    };
+    if let Err(err) = cassette.record_interaction(&interaction) {
+        warn!(
+            "Unable to record {} {}: {}",
+            interaction.method, interaction.url, err
+        );
+    }
+    interaction_response(&interaction)
+}
+
+/// The response recorded in `interaction`, or the error if there was none.
+fn interaction_response(interaction: &Interaction) -> anyhow::Result<Response> {
+    if let (0, Some(error)) = (interaction.status, &interaction.error) {
+        return Err(anyhow::anyhow!(
+            "{} {}: {} (replayed)",
+            interaction.method,
+            interaction.url,
+            error
+        ));
+    }
+    let body = match &interaction.response {
+        Value::String(body) if interaction.base64 => base64::decode(body)?,
+        Value::String(body) => body.clone().into_bytes(),
+        body => body.to_string().into_bytes(),
+    };
+    let mut response = http::Response::builder().status(interaction.status);
+    for (name, value) in &interaction.headers {
+        response = response.header(name, value);
+    }
+    Ok(Response::from(response.body(body)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::parse_headers;
+    use shadow_drive_test_support::{MockResponse, MockServer};
+    use shadow_rpc_auth::{BearerAuth, StaticToken};
+    use std::str::FromStr;
+
+    /// An RPC node answering `getVersion`, but only to requests with `token`
+    /// and an `x-tenant: acme` header.
+    fn mock_rpc(token: &'static str) -> MockServer {
+        MockServer::spawn(move |request| {
+            let bearer = format!("Bearer {}", token);
+            if request.header("authorization") == Some(bearer.as_str())
+                && request.header("x-tenant") == Some("acme")
+            {
+                MockResponse::rpc_result(serde_json::json!({ "solana-core": "1.14.0" }))
+            } else {
+                MockResponse::new(401, "")
+            }
+        })
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn carries_settings_into_clients() {
+        let node = mock_rpc("jwt");
+        let url = node.url();
+        let factory = ClientFactory::new(url)
+            .with_auth(Some(Arc::new(BearerAuth::new(StaticToken(
+                "jwt".to_string(),
+            )))))
+            .with_rpc_headers(parse_headers(&["x-tenant: acme"]).unwrap())
+            .with_timeout(Duration::from_secs(5))
+            .with_commitment(CommitmentConfig::finalized());
+
+        let rpc = factory.rpc_client();
+        assert_eq!(rpc.url(), url);
+        assert_eq!(rpc.commitment(), CommitmentConfig::finalized());
+        assert!(rpc.get_version().await.is_ok());
+        assert!(ClientFactory::new(url)
+            .rpc_client()
+            .get_version()
+            .await
+            .is_err());
+
+        // The fallback answers, with its own auth and headers, when the primary is down.
+        let auth: Arc<dyn AuthProvider> = Arc::new(BearerAuth::new(StaticToken("jwt".to_string())));
+        let failover = ClientFactory::new("http://127.0.0.1:1")
+            .with_fallback(url, Some(auth), parse_headers(&["x-tenant: acme"]).unwrap())
+            .with_retry_policy(RetryPolicy::none());
+        assert!(failover.rpc_client().get_version().await.is_ok());
+        assert_eq!(failover.fallback_urls().collect::<Vec<_>>(), [url]);
+
+        let clone = factory.clone();
+        clone.http_client();
+        clone.dry_run_rpc_client(DryRunLog::default());
+        assert_eq!(factory.clients_built(), 3);
+    }
+
+    /// Drive headers reach the CLI's own requests, and commands whose
+    /// requests the SDK sends itself refuse them.
+    #[tokio::test]
+    async fn sends_drive_headers_where_they_reach() {
+        let server =
+            MockServer::always(MockResponse::json(serde_json::json!({ "keys": ["a.txt"] })))
+                .unwrap();
+        let factory = ClientFactory::new("http://127.0.0.1:1")
+            .with_storage_server(Some(server.url().to_string()))
+            .with_drive_headers(parse_headers(&["x-tenant: acme"]).unwrap());
+        let files = crate::list_objects(&factory.http_client(), &Pubkey::new_unique())
+            .await
+            .unwrap();
+        assert_eq!(files, ["a.txt"]);
+        assert_eq!(server.received()[0].header("x-tenant"), Some("acme"));
+        let err = factory.ensure_reaches_sdk_requests().unwrap_err();
+        assert!(err.to_string().contains("--drive-header"));
+    }
+
+    #[tokio::test]
+    async fn keeps_primary_credentials_from_fallbacks() {
+        let public = MockServer::always(MockResponse::rpc_result(
+            serde_json::json!({ "solana-core": "1.14.0" }),
+        ))
+        .unwrap();
+        let factory = ClientFactory::new("http://127.0.0.1:1")
+            .with_auth(Some(Arc::new(BearerAuth::new(StaticToken(
+                "jwt".to_string(),
+            )))))
+            .with_rpc_headers(parse_headers(&["x-api-key: primary-key"]).unwrap())
+            .with_fallback(public.url(), None, HeaderMap::new())
+            .with_retry_policy(RetryPolicy::none());
+        assert!(factory.rpc_client().get_version().await.is_ok());
+        let received = public.received();
+        assert_eq!(received.len(), 1);
+        assert_eq!(received[0].header("x-api-key"), None);
+        assert_eq!(received[0].header("authorization"), None);
+    }
+
+    #[tokio::test]
+    async fn replays_recorded_requests() {
+        let node = mock_rpc("jwt");
+        let url = node.url();
+        let path = std::env::temp_dir().join(format!("cli-{}.jsonl", std::process::id()));
+        let recording = ClientFactory::new(url)
+            .with_auth(Some(Arc::new(BearerAuth::new(StaticToken(
+                "jwt".to_string(),
+            )))))
+            .with_rpc_headers(parse_headers(&["x-tenant: acme"]).unwrap())
+            .with_cassette(Some(Arc::new(Cassette::record(&path).unwrap())));
+        let version = recording.rpc_client().get_version().await.unwrap();
+        assert!(!std::fs::read_to_string(&path).unwrap().contains("jwt"));
+
+        let replaying = ClientFactory::new("http://127.0.0.1:1")
+            .with_cassette(Some(Arc::new(Cassette::replay(&path).unwrap())));
+        let replayed = replaying.rpc_client().get_version().await.unwrap();
+        assert_eq!(replayed.solana_core, version.solana_core);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn reads_files_from_the_emulator() {
+        let account = "11111111111111111111111111111111";
+        let store = shadow_drive_emulator::Store::in_memory();
+        store.put(account, "notes.txt", b"hello".to_vec()).unwrap();
+        store.put(account, "image.png", vec![0x89, 0x50]).unwrap();
+        let host = shadow_drive_emulator::spawn("127.0.0.1:0".parse().unwrap(), store).unwrap();
+
+        let factory = ClientFactory::new("http://127.0.0.1:1")
+            .with_drive_host(Some(format!("{}/", host)))
+            .with_storage_server(Some(host.clone()));
+        let http_client = factory.http_client();
+        let account = Pubkey::from_str(account).unwrap();
+        let url = http_client.file_url(&account, "notes.txt");
+        assert_eq!(url, format!("{}/{}/notes.txt", host, account));
+        let response = crate::get_text(&http_client, &url).await.unwrap();
+        assert!(crate::last_modified(response.headers()).is_ok());
+        assert_eq!(response.text().await.unwrap(), "hello");
+        let image = http_client.file_url(&account, "image.png");
+        assert!(crate::get_text(&http_client, &image).await.is_err());
+        let mut files = crate::list_objects(&http_client, &account).await.unwrap();
+        files.sort();
+        assert_eq!(files, ["image.png", "notes.txt"]);
+        assert_eq!(crate::storage_used(&http_client, &account).await.unwrap(), 7);
+        assert!(factory.ensure_reaches_sdk_requests().is_err());
+        assert!(ClientFactory::new("http://127.0.0.1:1")
+            .ensure_reaches_sdk_requests()
+            .is_ok());
+        let requests: u64 = factory
+            .metrics()
+            .snapshot()
+            .iter()
+            .map(|stat| stat.requests)
+            .sum();
+        assert_eq!(requests, 5);
+    }
+
+    #[test]
+    fn parses_commitment() {
+        assert_eq!(
+            commitment_arg("finalized").unwrap(),
+            CommitmentConfig::finalized()
+        );
+        assert!(commitment_arg("eventually").is_err());
+    }
+}
@@ -0,0 +1,173 @@
+//! Dynamic shell completion of storage accounts and remote filenames.
+//!
+//! Completing a word means an RPC or Shadow Drive round trip, so results are
+//! cached on disk for [CACHE_TTL] and reused by the next few key presses.
+use serde::{Deserialize, Serialize};
+use shadow_drive_rust::ShadowDriveClient;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::Signer;
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::client::DriveHttpClient;
+use crate::list_objects;
+use crate::storage_accounts::{owned_storage_accounts, storage_account_name};
+
+/// How long completion candidates are reused before being fetched again.
+pub const CACHE_TTL: Duration = Duration::from_secs(60);
+
+/// A completion candidate with an optional description, printed as
+/// `value<TAB>description` for shells which can display it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Candidate {
+    pub value: String,
+    pub description: Option<String>,
+}
+
+impl Candidate {
+    pub fn line(&self) -> String {
+        match &self.description {
+            Some(description) => format!("{}\t{}", self.value, description),
+            None => self.value.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct CacheEntry {
+    fetched_at: u64,
+    candidates: Vec<Candidate>,
+}
+
+fn cache_path(key: &str) -> Option<PathBuf> {
+    dirs_next::cache_dir().map(|dir| {
+        dir.join("shadow-drive-cli")
+            .join(format!("completions-{}.json", key))
+    })
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+/// Candidates stored under `key`, if they are younger than [CACHE_TTL].
+fn read_cache(key: &str) -> Option<Vec<Candidate>> {
+    let bytes = std::fs::read(cache_path(key)?).ok()?;
+    let entry: CacheEntry = serde_json::from_slice(&bytes).ok()?;
+    if now().saturating_sub(entry.fetched_at) > CACHE_TTL.as_secs() {
+        return None;
+    }
+    Some(entry.candidates)
+}
+
+/// Best effort: completion still works, just slower, if the cache can't be written.
+fn write_cache(key: &str, candidates: &[Candidate]) {
+    let path = match cache_path(key) {
+        Some(path) => path,
+        None => return,
+    };
+    let entry = CacheEntry {
+        fetched_at: now(),
+        candidates: candidates.to_vec(),
+    };
+    if let (Some(dir), Ok(json)) = (path.parent(), serde_json::to_vec(&entry)) {
+        let _ = std::fs::create_dir_all(dir).and_then(|_| std::fs::write(&path, json));
+    }
+}
+
+/// Addresses of storage accounts owned by `owner`, described by their names.
+pub async fn storage_account_candidates<T: Signer>(
+    client: &ShadowDriveClient<T>,
+    owner: &Pubkey,
+) -> anyhow::Result<Vec<Candidate>> {
+    let key = format!("accounts-{}", owner.to_string());
+    if let Some(candidates) = read_cache(&key) {
+        return Ok(candidates);
+    }
+    let candidates: Vec<Candidate> = owned_storage_accounts(client, owner, &Default::default())
+        .await?
+        .iter()
+        .map(|(key, account)| Candidate {
+            value: key.to_string(),
+            description: Some(storage_account_name(account).to_string()),
+        })
+        .collect();
+    write_cache(&key, &candidates);
+    Ok(candidates)
+}
+
+/// Names of the files held by `storage_account`.
+pub async fn file_candidates(
+    http_client: &DriveHttpClient,
+    storage_account: &Pubkey,
+) -> anyhow::Result<Vec<Candidate>> {
+    let key = format!("files-{}", storage_account.to_string());
+    if let Some(candidates) = read_cache(&key) {
+        return Ok(candidates);
+    }
+    let candidates: Vec<Candidate> = list_objects(http_client, storage_account)
+        .await?
+        .into_iter()
+        .map(|file| Candidate {
+            value: file,
+            description: None,
+        })
+        .collect();
+    write_cache(&key, &candidates);
+    Ok(candidates)
+}
+
+/// Candidates matching `prefix`. Storage accounts also match on their name.
+pub fn matching<'a>(
+    candidates: &'a [Candidate],
+    prefix: &'a str,
+) -> impl Iterator<Item = &'a Candidate> {
+    candidates.iter().filter(move |candidate| {
+        candidate.value.starts_with(prefix)
+            || candidate
+                .description
+                .as_ref()
+                .map_or(false, |description| description.starts_with(prefix))
+    })
+}
+
+/// Bash completion wrapping the static script generated by `clap_complete`,
+/// which asks the CLI for storage accounts and files where they are expected.
+pub const BASH_DYNAMIC: &str = r#"
+_shadow_drive_cli_dynamic() {
+    local cur="${COMP_WORDS[COMP_CWORD]}"
+    if [[ "$cur" != -* ]]; then
+        case "${COMP_WORDS[1]}" in
+            get-storage-account|delete-storage-account|cancel-delete-storage-account|\
+            claim-stake|reduce-storage|add-storage|add-immutable-storage|\
+            make-storage-immutable|storage-usage|list-files|migrate-account|store-files|edit-file)
+                if [[ $COMP_CWORD -eq 2 ]]; then
+                    COMPREPLY=( $(shadow-drive-cli __complete accounts "$cur" 2>/dev/null | cut -f1) )
+                    return
+                fi
+                ;;
+            get-text|delete-file|get-object-data)
+                if [[ $COMP_CWORD -eq 2 ]]; then
+                    COMPREPLY=( $(shadow-drive-cli __complete accounts "$cur" 2>/dev/null | cut -f1) )
+                    return
+                fi
+                if [[ $COMP_CWORD -eq 3 ]]; then
+                    COMPREPLY=( $(shadow-drive-cli __complete files --storage-account "${COMP_WORDS[2]}" "$cur" 2>/dev/null) )
+                    return
+                fi
+                ;;
+        esac
+    fi
+    _shadow-drive-cli "$@"
+}
+complete -F _shadow_drive_cli_dynamic -o bashdefault -o default shadow-drive-cli
+"#;
+
+/// Fish completion added on top of the static script generated by `clap_complete`.
+pub const FISH_DYNAMIC: &str = r#"
+complete -c shadow-drive-cli -n "__fish_seen_subcommand_from get-storage-account delete-storage-account cancel-delete-storage-account claim-stake reduce-storage add-storage add-immutable-storage make-storage-immutable storage-usage list-files migrate-account store-files edit-file get-text delete-file get-object-data; and test (count (commandline -opc)) -eq 2" -f -a "(shadow-drive-cli __complete accounts (commandline -ct) 2>/dev/null)"
+complete -c shadow-drive-cli -n "__fish_seen_subcommand_from get-text delete-file get-object-data; and test (count (commandline -opc)) -eq 3" -f -a "(shadow-drive-cli __complete files --storage-account (commandline -opc)[3] (commandline -ct) 2>/dev/null)"
+"#;
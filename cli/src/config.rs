@@ -0,0 +1,459 @@
+//! Named profiles, selected with `--profile`, kept in
+//! `<config dir>/shadow-drive-cli/config.toml`:
+//!
+//! ```toml
+//! [profiles.default]
+//! url = "https://ssc-dao.genesysgo.net"
+//!
+//! [profiles.helius]
+//! url = "https://mainnet.helius-rpc.com/"
+//! keypair = "~/.config/solana/deploy.json"
+//! auth = { type = "url-key", query = "api-key", key = "env:HELIUS_API_KEY" }
+//!
+//! [profiles.genesysgo]
+//! url = "https://us-west-1.genesysgo.net/<account id>"
+//! auth = { type = "genesysgo" }
+//!
+//! [profiles.mock]
+//! url = "http://localhost:8899"
+//! auth = { type = "genesysgo", account_id = "test", portal_url = "http://localhost:3000", format = "siws" }
+//! ```
+//!
+//! Endpoints to fail over to, in order, each with its own auth, and the delay
+//! in milliseconds after which read requests are also sent to the next endpoint:
+//!
+//! ```toml
+//! [profiles.premium]
+//! url = "https://mainnet.helius-rpc.com/"
+//! auth = { type = "url-key", query = "api-key", key = "env:HELIUS_API_KEY" }
+//! fallbacks = [{ url = "https://api.mainnet-beta.solana.com" }]
+//! hedge_ms = 800
+//! ```
+//!
+//! A local `shadow-drive-emulator` and `solana-test-validator`, for the read-only
+//! commands; those whose requests the SDK sends itself reject the emulator hosts:
+//!
+//! ```toml
+//! [profiles.local]
+//! url = "http://localhost:8899"
+//! drive_host = "http://127.0.0.1:8910"
+//! storage_server = "http://127.0.0.1:8910"
+//! ```
+//!
+//! Extra headers for RPC requests and for the CLI's own Shadow Drive requests are
+//! given as `"Name: value"`, e.g. `rpc_headers = ["x-tenant: acme"]`. The profile's
+//! RPC headers go to `url` only, fallbacks are sent just their own `headers`:
+//!
+//! ```toml
+//! fallbacks = [{ url = "https://backup.example.com/", headers = ["x-api-key: env:BACKUP_KEY"] }]
+//! ```
+//!
+//! Secrets (`token`, `password`, `value`, `key` and header values) written as
+//! `env:NAME` are read from the environment variable `NAME` instead.
+use anyhow::anyhow;
+use reqwest::header::HeaderMap;
+use reqwest::StatusCode;
+use serde::Deserialize;
+use shadow_rpc_auth::http_sender::parse_header;
+use shadow_rpc_auth::token_provider::SignerLoader;
+use shadow_rpc_auth::{
+    parse_account_id_from_url, AuthProvider, BasicAuth, BearerAuth, GenesysGoAuthConfig,
+    GenesysGoTokenProvider, HeaderAuth, RetryPolicy, SignInFormat, StaticToken, UrlKeyAuth,
+    UrlKeyPlacement,
+};
+use solana_sdk::pubkey::Pubkey;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::token_cache;
+
+/// `--auth` keyword selecting [AuthConfig::Genesysgo].
+pub const GENESYSGO_AUTH_KEYWORD: &str = "genesysgo";
+
+/// Profile used when `--profile` is not given, if the config file has one.
+pub const DEFAULT_PROFILE: &str = "default";
+
+#[derive(Debug, Default, Deserialize)]
+pub struct CliConfig {
+    #[serde(default)]
+    pub profiles: BTreeMap<String, Profile>,
+}
+
+/// Defaults for `--url`, `--keypair`, `--auth`, `--commitment` and `--rpc-timeout`.
+/// Command-line flags take precedence over the profile, which takes precedence
+/// over the Solana CLI config. Headers from `--rpc-header` and `--drive-header`
+/// are added to the profile's, replacing those of the same name. `--url` given on
+/// the command line replaces both `url` and `fallbacks`, and RPC headers are only
+/// ever sent to the first URL.
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Profile {
+    pub url: Option<String>,
+    pub keypair: Option<String>,
+    pub auth: Option<AuthConfig>,
+    /// `processed`, `confirmed` or `finalized`.
+    pub commitment: Option<String>,
+    /// RPC and Shadow Drive request timeout, in seconds.
+    pub rpc_timeout: Option<u64>,
+    /// Endpoints to fail over to when `url` fails, in order.
+    #[serde(default)]
+    pub fallbacks: Vec<EndpointConfig>,
+    /// Milliseconds after which a read request is also sent to the next endpoint.
+    pub hedge_ms: Option<u64>,
+    /// How failed JSON-RPC requests are retried.
+    pub retry: Option<RetryConfig>,
+    /// Headers sent with every JSON-RPC request to `url`, but not to the fallbacks.
+    #[serde(default)]
+    pub rpc_headers: Vec<String>,
+    /// Headers sent with the CLI's own requests to the Shadow Drive server and file host.
+    #[serde(default)]
+    pub drive_headers: Vec<String>,
+    /// Host of Shadow Drive files, e.g. a local emulator.
+    pub drive_host: Option<String>,
+    /// Shadow Drive server for the CLI's own requests, such as file listings,
+    /// e.g. a local emulator.
+    pub storage_server: Option<String>,
+}
+
+/// An RPC endpoint to fail over to. Its auth and headers are separate from the profile's.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct EndpointConfig {
+    pub url: String,
+    pub auth: Option<AuthConfig>,
+    /// Headers sent with every JSON-RPC request to this endpoint.
+    #[serde(default)]
+    pub headers: Vec<String>,
+}
+
+/// Changes to the default [RetryPolicy], e.g. `retry = { max_attempts = 8, deadline = 120 }`.
+#[derive(Debug, Default, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RetryConfig {
+    /// Attempts per request, including the first. 1 disables retries.
+    pub max_attempts: Option<u32>,
+    /// Delay before the first retry in milliseconds, doubled for each one after.
+    pub base_backoff_ms: Option<u64>,
+    /// Longest delay between attempts in milliseconds.
+    pub max_backoff_ms: Option<u64>,
+    /// Fraction of each delay which is randomized, from 0.0 to 1.0.
+    pub jitter: Option<f64>,
+    /// HTTP statuses which are retried.
+    pub statuses: Option<Vec<u16>>,
+    /// Seconds after the first attempt past which no retry is started, 0 for no limit.
+    pub deadline: Option<u64>,
+}
+
+impl RetryConfig {
+    /// The default [RetryPolicy] with this config's changes.
+    pub fn policy(&self) -> anyhow::Result<RetryPolicy> {
+        let mut policy = RetryPolicy::default();
+        if let Some(max_attempts) = self.max_attempts {
+            if max_attempts == 0 {
+                return Err(anyhow!("retry max_attempts must be at least 1"));
+            }
+            policy.max_attempts = max_attempts;
+        }
+        if let Some(base_backoff) = self.base_backoff_ms {
+            policy.base_backoff = Duration::from_millis(base_backoff);
+        }
+        if let Some(max_backoff) = self.max_backoff_ms {
+            policy.max_backoff = Duration::from_millis(max_backoff);
+        }
+        if let Some(jitter) = self.jitter {
+            if !(0.0..=1.0).contains(&jitter) {
+                return Err(anyhow!("retry jitter must be between 0.0 and 1.0"));
+            }
+            policy.jitter = jitter;
+        }
+        if let Some(statuses) = &self.statuses {
+            policy.retryable_statuses = statuses
+                .iter()
+                .map(|status| {
+                    StatusCode::from_u16(*status)
+                        .map_err(|_| anyhow!("invalid HTTP status {} to retry", status))
+                })
+                .collect::<anyhow::Result<_>>()?;
+        }
+        match self.deadline {
+            Some(0) => policy.deadline = None,
+            Some(deadline) => policy.deadline = Some(Duration::from_secs(deadline)),
+            None => {}
+        }
+        Ok(policy)
+    }
+}
+
+/// How to authenticate with the RPC endpoint.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(tag = "type", rename_all = "kebab-case", deny_unknown_fields)]
+pub enum AuthConfig {
+    /// `Authorization: Bearer <token>`.
+    Bearer { token: String },
+    /// HTTP basic auth.
+    Basic {
+        username: String,
+        password: Option<String>,
+    },
+    /// An API key in a custom header, e.g. `name = "x-api-key"`.
+    Header { name: String, value: String },
+    /// An API key in the URL: as the query parameter `query` if given,
+    /// otherwise as the last path segment.
+    UrlKey { key: String, query: Option<String> },
+    /// Sign in to GenesysGo with the signer. The account ID defaults
+    /// to the one in the endpoint URL, the portal and the signed message
+    /// to those of production GenesysGo. `format` is `raw` (the default),
+    /// `offchain` or `siws`, see [SignInFormat].
+    Genesysgo {
+        account_id: Option<String>,
+        portal_url: Option<String>,
+        message: Option<String>,
+        format: Option<SignInFormat>,
+    },
+}
+
+/// Account and portal to sign in to GenesysGo with, both for `--auth genesysgo`
+/// and for the `shadow-rpc-auth` command.
+#[derive(Debug, Clone, Default)]
+pub struct GenesysGoSignIn {
+    pub account_id: Option<String>,
+    pub config: GenesysGoAuthConfig,
+}
+
+impl GenesysGoSignIn {
+    /// The configured account ID, or else the one in the endpoint `url`.
+    pub fn account_id(&self, url: &str) -> anyhow::Result<String> {
+        match &self.account_id {
+            Some(account_id) => Ok(account_id.clone()),
+            None => parse_account_id_from_url(url.to_string()),
+        }
+    }
+}
+
+impl CliConfig {
+    /// `<config dir>/shadow-drive-cli/config.toml`, e.g. `~/.config/shadow-drive-cli/config.toml` on Linux.
+    pub fn default_path() -> Option<PathBuf> {
+        dirs_next::config_dir().map(|dir| dir.join("shadow-drive-cli").join("config.toml"))
+    }
+
+    /// Load the config file, or an empty config if there is none.
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        match fs::read_to_string(path) {
+            Ok(contents) => toml::from_str(&contents)
+                .map_err(|e| anyhow!("invalid config file {}: {}", path.display(), e)),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    /// The profile called `name`, or the [DEFAULT_PROFILE] (if any) when no name is given.
+    pub fn profile(&self, name: Option<&str>) -> anyhow::Result<Profile> {
+        match name {
+            Some(name) => self
+                .profiles
+                .get(name)
+                .cloned()
+                .ok_or_else(|| anyhow!("no profile named {:?} in the config file", name)),
+            None => Ok(self
+                .profiles
+                .get(DEFAULT_PROFILE)
+                .cloned()
+                .unwrap_or_default()),
+        }
+    }
+}
+
+/// Resolve `env:NAME` secrets.
+fn secret(value: &str) -> anyhow::Result<String> {
+    match value.strip_prefix("env:") {
+        Some(name) => std::env::var(name)
+            .map_err(|_| anyhow!("environment variable {} is not set", name)),
+        None => Ok(value.to_string()),
+    }
+}
+
+/// Parse `"Name: value"` headers, resolving `env:NAME` values.
+/// Later headers replace earlier ones of the same name.
+pub fn parse_headers<S: AsRef<str>>(headers: &[S]) -> anyhow::Result<HeaderMap> {
+    let mut map = HeaderMap::new();
+    for header in headers {
+        let header = header.as_ref();
+        let resolved = match header.split_once(':') {
+            Some((name, value)) => format!("{}: {}", name, secret(value.trim())?),
+            None => header.to_string(),
+        };
+        let (name, value) = parse_header(&resolved)?;
+        map.insert(name, value);
+    }
+    Ok(map)
+}
+
+impl AuthConfig {
+    /// Interpret `--auth`: the [GENESYSGO_AUTH_KEYWORD], or else a bearer token.
+    pub fn from_flag(auth: &str) -> Self {
+        if auth == GENESYSGO_AUTH_KEYWORD {
+            AuthConfig::Genesysgo {
+                account_id: None,
+                portal_url: None,
+                message: None,
+                format: None,
+            }
+        } else {
+            AuthConfig::Bearer {
+                token: auth.to_string(),
+            }
+        }
+    }
+
+    /// Build the provider authenticating requests to `url`. GenesysGo sign-in
+    /// uses `load_signer`, which must resolve to `pubkey`, and the token cache.
+    pub fn provider(
+        &self,
+        url: &str,
+        pubkey: Pubkey,
+        load_signer: SignerLoader,
+    ) -> anyhow::Result<Arc<dyn AuthProvider>> {
+        Ok(match self {
+            AuthConfig::Bearer { token } => {
+                Arc::new(BearerAuth::new(StaticToken(secret(token)?)))
+            }
+            AuthConfig::Basic { username, password } => Arc::new(BasicAuth {
+                username: username.clone(),
+                password: password.as_deref().map(secret).transpose()?,
+            }),
+            AuthConfig::Header { name, value } => Arc::new(HeaderAuth::new(name, secret(value)?)?),
+            AuthConfig::UrlKey { key, query } => Arc::new(UrlKeyAuth {
+                key: secret(key)?,
+                placement: match query {
+                    Some(name) => UrlKeyPlacement::Query(name.clone()),
+                    None => UrlKeyPlacement::Path,
+                },
+            }),
+            AuthConfig::Genesysgo { .. } => {
+                let sign_in = self.genesysgo_sign_in();
+                Arc::new(BearerAuth::new(
+                    GenesysGoTokenProvider::new(
+                        sign_in.account_id(url)?,
+                        pubkey,
+                        load_signer,
+                        Some(token_cache()?),
+                    )
+                    .with_config(sign_in.config),
+                ))
+            }
+        })
+    }
+
+    /// Sign in to GenesysGo as `account_id`, e.g. from `--auth-account-id`,
+    /// rather than the configured account or the one in the URL.
+    /// Other kinds of auth are left alone.
+    pub fn set_genesysgo_account_id(&mut self, account_id: &str) {
+        if let AuthConfig::Genesysgo {
+            account_id: configured,
+            ..
+        } = self
+        {
+            *configured = Some(account_id.to_string());
+        }
+    }
+
+    /// GenesysGo settings of a `genesysgo` auth config, defaults for any other.
+    pub fn genesysgo_sign_in(&self) -> GenesysGoSignIn {
+        match self {
+            AuthConfig::Genesysgo {
+                account_id,
+                portal_url,
+                message,
+                format,
+            } => {
+                let defaults = GenesysGoAuthConfig::default();
+                GenesysGoSignIn {
+                    account_id: account_id.clone(),
+                    config: GenesysGoAuthConfig {
+                        portal_url: portal_url.clone().unwrap_or(defaults.portal_url),
+                        message: message.clone().unwrap_or(defaults.message),
+                        format: format.unwrap_or(defaults.format),
+                    },
+                }
+            }
+            _ => GenesysGoSignIn::default(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_profiles() {
+        let config: CliConfig = toml::from_str(
+            r#"
+            [profiles.default]
+            url = "http://localhost:8899"
+            drive_host = "http://127.0.0.1:8910"
+
+            [profiles.vendor]
+            url = "https://rpc.example.com/"
+            auth = { type = "url-key", query = "api-key", key = "env:VENDOR_KEY" }
+            rpc_headers = ["x-tenant: acme"]
+            retry = { max_attempts = 8, statuses = [429, 503], deadline = 0 }
+            fallbacks = [
+                { url = "https://backup.example.com/", auth = { type = "bearer", token = "env:BACKUP" } },
+                { url = "https://public.example.com/", headers = ["x-client: cli"] },
+            ]
+            hedge_ms = 800
+
+            [profiles.mock]
+            auth = { type = "genesysgo", portal_url = "http://localhost:3000", format = "siws" }
+            "#,
+        )
+        .unwrap();
+        assert_eq!(
+            config.profile(None).unwrap().url.as_deref(),
+            Some("http://localhost:8899")
+        );
+        assert_eq!(
+            config.profile(Some("vendor")).unwrap().auth,
+            Some(AuthConfig::UrlKey {
+                key: "env:VENDOR_KEY".to_string(),
+                query: Some("api-key".to_string()),
+            })
+        );
+        assert_eq!(
+            config.profile(None).unwrap().drive_host.as_deref(),
+            Some("http://127.0.0.1:8910")
+        );
+        assert!(config.profile(Some("missing")).is_err());
+        let vendor = config.profile(Some("vendor")).unwrap();
+        let retry = vendor.retry.unwrap().policy().unwrap();
+        assert_eq!(retry.max_attempts, 8);
+        assert_eq!(retry.retryable_statuses.len(), 2);
+        assert_eq!(retry.deadline, None);
+        assert_eq!(vendor.fallbacks.len(), 2);
+        assert_eq!(vendor.fallbacks[1].auth, None);
+        assert_eq!(vendor.fallbacks[1].headers, ["x-client: cli"]);
+        assert_eq!(vendor.hedge_ms, Some(800));
+        let headers = parse_headers(&vendor.rpc_headers).unwrap();
+        assert_eq!(headers["x-tenant"], "acme");
+        assert!(parse_headers(&["x-tenant: env:SHADOW_DRIVE_CLI_UNSET_VAR"]).is_err());
+        assert_eq!(
+            AuthConfig::from_flag("genesysgo").genesysgo_sign_in().config,
+            GenesysGoAuthConfig::default()
+        );
+        let mock = config.profile(Some("mock")).unwrap().auth.unwrap();
+        assert_eq!(mock.genesysgo_sign_in().config.format, SignInFormat::Siws);
+        let mut genesysgo = AuthConfig::from_flag("genesysgo");
+        genesysgo.set_genesysgo_account_id("abc-123");
+        assert_eq!(
+            genesysgo
+                .genesysgo_sign_in()
+                .account_id("https://rpc.genesysgo.net/other")
+                .unwrap(),
+            "abc-123"
+        );
+    }
+}
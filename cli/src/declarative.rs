@@ -0,0 +1,738 @@
+use anyhow::anyhow;
+use byte_unit::Byte;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use shadow_drive_rust::models::ShadowFile;
+use shadow_drive_rust::{ShadowDriveClient, StorageAccountVersion};
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::Signer;
+use std::collections::{BTreeSet, HashMap};
+use std::fmt::{Display, Formatter};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use crate::client::DriveHttpClient;
+use crate::migration::{content_hash, download_file};
+use crate::storage_accounts::{
+    owned_storage_accounts, storage_account_capacity, storage_account_is_immutable,
+    storage_account_name, StorageAccountFilter,
+};
+use crate::output::{bytes, display, Render, UploadView};
+use crate::wallet::OperationCost;
+use crate::{list_objects, parse_filesize, process_shadow_api_response};
+
+/// The desired storage layout, as kept in a TOML or YAML file:
+///
+/// ```toml
+/// [[accounts]]
+/// name = "website"
+/// size = "100MB"
+/// immutable = false
+/// directory = "./public"
+/// delete_untracked = true
+/// ```
+#[derive(Debug, Deserialize)]
+pub struct StorageLayout {
+    #[serde(default)]
+    pub accounts: Vec<DesiredAccount>,
+}
+
+/// One storage account in a [StorageLayout], identified by its name.
+#[derive(Debug, Deserialize)]
+pub struct DesiredAccount {
+    pub name: String,
+    /// File size string, accepts KB, MB, GB, e.g. "10MB"
+    #[serde(deserialize_with = "deserialize_filesize")]
+    pub size: Byte,
+    #[serde(default)]
+    pub immutable: bool,
+    /// Local directory whose files are mirrored into the account.
+    /// Relative paths are resolved against the layout file's directory.
+    pub directory: Option<PathBuf>,
+    /// Delete remote files which are not present in `directory`.
+    #[serde(default)]
+    pub delete_untracked: bool,
+}
+
+fn deserialize_filesize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Byte, D::Error> {
+    let size = String::deserialize(deserializer)?;
+    parse_filesize(&size).map_err(serde::de::Error::custom)
+}
+
+impl StorageLayout {
+    /// Load a layout from a `.toml`, `.yaml` or `.yml` file.
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let contents = fs::read_to_string(path)
+            .map_err(|e| anyhow!("unable to read {}: {}", path.display(), e))?;
+        let mut layout: Self = match path.extension().and_then(|ext| ext.to_str()) {
+            Some("toml") => toml::from_str(&contents)?,
+            Some("yaml") | Some("yml") => serde_yaml::from_str(&contents)?,
+            _ => {
+                return Err(anyhow!(
+                    "unrecognized layout file extension, expected .toml, .yaml or .yml: {}",
+                    path.display()
+                ))
+            }
+        };
+        let base = path.parent().unwrap_or_else(|| Path::new("."));
+        for account in layout.accounts.iter_mut() {
+            if let Some(directory) = account.directory.as_mut() {
+                if directory.is_relative() {
+                    *directory = base.join(&directory);
+                }
+            }
+        }
+        let mut names = BTreeSet::new();
+        for account in &layout.accounts {
+            if !names.insert(&account.name) {
+                return Err(anyhow!("storage account {} is listed twice", account.name));
+            }
+        }
+        Ok(layout)
+    }
+}
+
+/// On-chain state of an existing storage account, along with its files.
+#[derive(Debug, Clone)]
+pub struct ObservedAccount {
+    pub key: Pubkey,
+    pub name: String,
+    pub capacity: Byte,
+    pub immutable: bool,
+    pub files: Vec<String>,
+    /// Content hashes of the stored files which also exist locally, by name.
+    pub hashes: HashMap<String, String>,
+}
+
+/// A file in an account's `directory`, and the hash of its contents.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LocalFile {
+    /// The name it is stored under.
+    pub name: String,
+    pub path: PathBuf,
+    pub hash: String,
+}
+
+/// A storage account targeted by a [Change], which may not exist until
+/// an earlier change in the same plan creates it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AccountRef {
+    Existing(Pubkey),
+    New(String),
+}
+
+impl AccountRef {
+    /// The address of the account, looking up accounts created earlier in the plan.
+    fn resolve(&self, created: &HashMap<String, Pubkey>) -> anyhow::Result<Pubkey> {
+        match self {
+            AccountRef::Existing(key) => Ok(*key),
+            AccountRef::New(name) => created
+                .get(name)
+                .copied()
+                .ok_or_else(|| anyhow!("storage account {} was not created", name)),
+        }
+    }
+}
+
+impl Serialize for AccountRef {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl Display for AccountRef {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AccountRef::Existing(key) => write!(f, "{}", key.to_string()),
+            AccountRef::New(name) => write!(f, "<new account {}>", name),
+        }
+    }
+}
+
+/// One step of a [Plan]. Serialized with an `action` tag, e.g.
+/// `{"action": "add_storage", "account": "<base58>", "size": 1000000}`,
+/// where sizes are in bytes.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum Change {
+    CreateAccount {
+        name: String,
+        #[serde(serialize_with = "bytes")]
+        size: Byte,
+    },
+    AddStorage {
+        #[serde(serialize_with = "display")]
+        account: Pubkey,
+        #[serde(serialize_with = "bytes")]
+        size: Byte,
+    },
+    AddImmutableStorage {
+        #[serde(serialize_with = "display")]
+        account: Pubkey,
+        #[serde(serialize_with = "bytes")]
+        size: Byte,
+    },
+    ReduceStorage {
+        #[serde(serialize_with = "display")]
+        account: Pubkey,
+        #[serde(serialize_with = "bytes")]
+        size: Byte,
+    },
+    UploadFiles {
+        account: AccountRef,
+        files: Vec<PathBuf>,
+    },
+    /// Replace stored files whose contents differ from the local ones.
+    EditFiles {
+        #[serde(serialize_with = "display")]
+        account: Pubkey,
+        files: Vec<PathBuf>,
+    },
+    DeleteFiles {
+        #[serde(serialize_with = "display")]
+        account: Pubkey,
+        files: Vec<String>,
+    },
+    MakeImmutable {
+        account: AccountRef,
+    },
+}
+
+impl Change {
+    /// Whether applying the change can never be undone.
+    pub fn is_irreversible(&self) -> bool {
+        matches!(self, Change::MakeImmutable { .. })
+    }
+}
+
+impl Display for Change {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Change::CreateAccount { name, size } => {
+                write!(f, "+ create storage account {} ({})", name, size)
+            }
+            Change::AddStorage { account, size } => {
+                write!(f, "~ add {} of storage to {}", size, account.to_string())
+            }
+            Change::AddImmutableStorage { account, size } => write!(
+                f,
+                "~ add {} of immutable storage to {}",
+                size,
+                account.to_string()
+            ),
+            Change::ReduceStorage { account, size } => {
+                write!(f, "~ reduce storage of {} by {}", account.to_string(), size)
+            }
+            Change::UploadFiles { account, files } => {
+                write!(f, "+ upload {} files to {}", files.len(), account)?;
+                for file in files {
+                    write!(f, "\n    {}", file.display())?;
+                }
+                Ok(())
+            }
+            Change::EditFiles { account, files } => {
+                write!(f, "~ edit {} files in {}", files.len(), account.to_string())?;
+                for file in files {
+                    write!(f, "\n    {}", file.display())?;
+                }
+                Ok(())
+            }
+            Change::DeleteFiles { account, files } => {
+                write!(
+                    f,
+                    "- delete {} files from {}",
+                    files.len(),
+                    account.to_string()
+                )?;
+                for file in files {
+                    write!(f, "\n    {}", file)?;
+                }
+                Ok(())
+            }
+            Change::MakeImmutable { account } => {
+                write!(f, "! make {} immutable (irreversible)", account)
+            }
+        }
+    }
+}
+
+/// The changes needed to bring on-chain state in line with a [StorageLayout].
+/// Output of `plan` and `apply` as `{"changes": [<Change>, ...]}`.
+#[derive(Debug, Default, Serialize)]
+pub struct Plan {
+    pub changes: Vec<Change>,
+}
+
+impl Plan {
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+}
+
+impl Render for Plan {
+    fn text(&self) -> String {
+        self.to_string()
+    }
+}
+
+impl Display for Plan {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        if self.changes.is_empty() {
+            return write!(f, "No changes. On-chain state matches the layout.");
+        }
+        for change in &self.changes {
+            writeln!(f, "{}", change)?;
+        }
+        write!(f, "{} changes planned", self.changes.len())
+    }
+}
+
+/// Files directly inside `directory`, with the name they are stored under
+/// and the hash of their contents.
+pub fn local_files(directory: &Path) -> anyhow::Result<Vec<LocalFile>> {
+    let mut files = vec![];
+    for entry in fs::read_dir(directory)
+        .map_err(|e| anyhow!("unable to read {}: {}", directory.display(), e))?
+    {
+        let path = entry?.path();
+        if !path.is_file() {
+            continue;
+        }
+        if let Some(name) = path.file_name().and_then(|name| name.to_str()) {
+            let contents = fs::read(&path)
+                .map_err(|e| anyhow!("unable to read {}: {}", path.display(), e))?;
+            files.push(LocalFile {
+                name: name.to_string(),
+                path: path.clone(),
+                hash: content_hash(&contents),
+            });
+        }
+    }
+    files.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(files)
+}
+
+/// Diff a layout against observed state. `local` holds the files found in
+/// each account's `directory`, keyed by account name. Stored files are edited
+/// when their contents differ from the local ones.
+/// Accounts which exist on-chain but are absent from the layout are left alone.
+pub fn diff(
+    layout: &StorageLayout,
+    observed: &[ObservedAccount],
+    local: &HashMap<String, Vec<LocalFile>>,
+) -> anyhow::Result<Plan> {
+    let mut plan = Plan::default();
+    let mut immutables = vec![];
+    for desired in &layout.accounts {
+        let matching: Vec<&ObservedAccount> = observed
+            .iter()
+            .filter(|account| account.name == desired.name)
+            .collect();
+        if matching.len() > 1 {
+            return Err(anyhow!(
+                "{} storage accounts are named {}, cannot tell which one the layout refers to",
+                matching.len(),
+                desired.name
+            ));
+        }
+        let local_files = local.get(&desired.name).cloned().unwrap_or_default();
+        let existing = match matching.first() {
+            Some(existing) => existing,
+            None => {
+                plan.changes.push(Change::CreateAccount {
+                    name: desired.name.clone(),
+                    size: desired.size.clone(),
+                });
+                let account = AccountRef::New(desired.name.clone());
+                if !local_files.is_empty() {
+                    plan.changes.push(Change::UploadFiles {
+                        account: account.clone(),
+                        files: local_files.into_iter().map(|file| file.path).collect(),
+                    });
+                }
+                if desired.immutable {
+                    immutables.push(Change::MakeImmutable { account });
+                }
+                continue;
+            }
+        };
+
+        if existing.immutable && !desired.immutable {
+            return Err(anyhow!(
+                "storage account {} ({}) is immutable and cannot be made mutable again",
+                desired.name,
+                existing.key.to_string()
+            ));
+        }
+        let current = existing.capacity.get_bytes();
+        let wanted = desired.size.get_bytes();
+        if wanted > current {
+            let size = Byte::from_bytes(wanted - current);
+            plan.changes.push(if existing.immutable {
+                Change::AddImmutableStorage {
+                    account: existing.key,
+                    size,
+                }
+            } else {
+                Change::AddStorage {
+                    account: existing.key,
+                    size,
+                }
+            });
+        }
+
+        let uploads: Vec<PathBuf> = local_files
+            .iter()
+            .filter(|file| !existing.files.contains(&file.name))
+            .map(|file| file.path.clone())
+            .collect();
+        if !uploads.is_empty() {
+            plan.changes.push(Change::UploadFiles {
+                account: AccountRef::Existing(existing.key),
+                files: uploads,
+            });
+        }
+        let edits: Vec<PathBuf> = local_files
+            .iter()
+            .filter(|file| {
+                existing.files.contains(&file.name)
+                    && existing.hashes.get(&file.name) != Some(&file.hash)
+            })
+            .map(|file| file.path.clone())
+            .collect();
+        if !edits.is_empty() {
+            if existing.immutable {
+                return Err(anyhow!(
+                    "cannot edit files of immutable storage account {}",
+                    desired.name
+                ));
+            }
+            plan.changes.push(Change::EditFiles {
+                account: existing.key,
+                files: edits,
+            });
+        }
+        if desired.delete_untracked && desired.directory.is_some() {
+            let deletes: Vec<String> = existing
+                .files
+                .iter()
+                .filter(|file| !local_files.iter().any(|local| &local.name == *file))
+                .cloned()
+                .collect();
+            if !deletes.is_empty() {
+                if existing.immutable {
+                    return Err(anyhow!(
+                        "cannot delete files from immutable storage account {}",
+                        desired.name
+                    ));
+                }
+                plan.changes.push(Change::DeleteFiles {
+                    account: existing.key,
+                    files: deletes,
+                });
+            }
+        }
+
+        if wanted < current {
+            if existing.immutable {
+                return Err(anyhow!(
+                    "cannot reduce the size of immutable storage account {}",
+                    desired.name
+                ));
+            }
+            plan.changes.push(Change::ReduceStorage {
+                account: existing.key,
+                size: Byte::from_bytes(current - wanted),
+            });
+        }
+        if desired.immutable && !existing.immutable {
+            immutables.push(Change::MakeImmutable {
+                account: AccountRef::Existing(existing.key),
+            });
+        }
+    }
+    // Nothing can be changed after an account is made immutable,
+    // so these always go last.
+    plan.changes.extend(immutables);
+    Ok(plan)
+}
+
+/// Fetch the on-chain state relevant to `layout` and diff against it.
+/// Stored files which also exist locally are downloaded to compare their contents.
+pub async fn plan<T: Signer>(
+    client: &ShadowDriveClient<T>,
+    http_client: &DriveHttpClient,
+    owner: &Pubkey,
+    layout: &StorageLayout,
+) -> anyhow::Result<Plan> {
+    let mut local = HashMap::new();
+    for account in &layout.accounts {
+        if let Some(directory) = &account.directory {
+            local.insert(account.name.clone(), local_files(directory)?);
+        }
+    }
+    let wanted: BTreeSet<&str> = layout.accounts.iter().map(|a| a.name.as_str()).collect();
+    let mut observed = vec![];
+    for (key, account) in
+        owned_storage_accounts(client, owner, &StorageAccountFilter::default()).await?
+    {
+        let name = storage_account_name(&account);
+        if !wanted.contains(name) {
+            continue;
+        }
+        let files = list_objects(http_client, &key).await?;
+        let mut hashes = HashMap::new();
+        for file in local.get(name).into_iter().flatten() {
+            if files.contains(&file.name) {
+                let stored = download_file(http_client, &key, &file.name).await?;
+                hashes.insert(file.name.clone(), content_hash(&stored));
+            }
+        }
+        observed.push(ObservedAccount {
+            key,
+            name: name.to_string(),
+            capacity: storage_account_capacity(&account),
+            immutable: storage_account_is_immutable(&account),
+            files,
+            hashes,
+        });
+    }
+    diff(layout, &observed, &local)
+}
+
+/// Rough total cost of applying `plan`: network fees for every change,
+/// plus SHDW for any storage it reserves.
+pub async fn estimated_cost(rpc: &RpcClient, plan: &Plan) -> anyhow::Result<OperationCost> {
+    let mut total = OperationCost::default();
+    for change in &plan.changes {
+        let cost = match change {
+            Change::CreateAccount { size, .. } => OperationCost::storage(rpc, size, true).await?,
+            Change::AddStorage { size, .. } | Change::AddImmutableStorage { size, .. } => {
+                OperationCost::storage(rpc, size, false).await?
+            }
+            _ => OperationCost::fee_only(),
+        };
+        total.lamports += cost.lamports;
+        total.shades += cost.shades;
+    }
+    Ok(total)
+}
+
+/// Carry out every change in `plan`, in order.
+pub async fn apply<T: Signer>(
+    client: &ShadowDriveClient<T>,
+    http_client: &DriveHttpClient,
+    plan: &Plan,
+    batch_size: usize,
+) -> anyhow::Result<()> {
+    let mut created: HashMap<String, Pubkey> = HashMap::new();
+    for change in &plan.changes {
+        eprintln!("{}", change);
+        match change {
+            Change::CreateAccount { name, size } => {
+                let response = client
+                    .create_storage_account(name, size.clone(), StorageAccountVersion::v2())
+                    .await;
+                let resp = process_shadow_api_response(response)?;
+                let key = resp
+                    .shdw_bucket
+                    .ok_or_else(|| anyhow!("storage account creation did not return an address"))?;
+                let key = Pubkey::from_str(&key)?;
+                eprintln!("Created {}", key.to_string());
+                created.insert(name.clone(), key);
+            }
+            Change::AddStorage { account, size } => {
+                let response = client.add_storage(account, size.clone()).await;
+                process_shadow_api_response(response)?;
+            }
+            Change::AddImmutableStorage { account, size } => {
+                let response = client.add_immutable_storage(account, size.clone()).await;
+                process_shadow_api_response(response)?;
+            }
+            Change::ReduceStorage { account, size } => {
+                let response = client.reduce_storage(account, size.clone()).await;
+                process_shadow_api_response(response)?;
+            }
+            Change::UploadFiles { account, files } => {
+                let account = account.resolve(&created)?;
+                let mut view = UploadView::new(account);
+                for chunk in files.chunks(batch_size) {
+                    let paths: Vec<String> = chunk
+                        .iter()
+                        .map(|path| path.to_string_lossy().to_string())
+                        .collect();
+                    let shdw_files = chunk
+                        .iter()
+                        .zip(&paths)
+                        .map(|(path, name)| {
+                            ShadowFile::file(crate::acquire_basename(name), path.clone())
+                        })
+                        .collect();
+                    let response = client.store_files(&account, shdw_files).await;
+                    let resp = process_shadow_api_response(response)?;
+                    view.record_response(&paths, &resp);
+                }
+                if let Some(err) = view.failure() {
+                    return Err(err.into());
+                }
+            }
+            Change::EditFiles { account, files } => {
+                for path in files {
+                    let basename = crate::acquire_basename(&path.to_string_lossy());
+                    let response = client
+                        .edit_file(account, ShadowFile::file(basename, path.clone()))
+                        .await;
+                    process_shadow_api_response(response)?;
+                }
+            }
+            Change::DeleteFiles { account, files } => {
+                for file in files {
+                    let location = http_client.file_url(account, file);
+                    let response = client.delete_file(account, location).await;
+                    process_shadow_api_response(response)?;
+                }
+            }
+            Change::MakeImmutable { account } => {
+                let account = account.resolve(&created)?;
+                let response = client.make_storage_immutable(&account).await;
+                process_shadow_api_response(response)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn desired(name: &str, size: &str) -> DesiredAccount {
+        DesiredAccount {
+            name: name.to_string(),
+            size: parse_filesize(size).unwrap(),
+            immutable: false,
+            directory: Some(PathBuf::from(name)),
+            delete_untracked: false,
+        }
+    }
+
+    fn observed(name: &str, size: &str, files: &[&str]) -> ObservedAccount {
+        ObservedAccount {
+            key: Pubkey::new_unique(),
+            name: name.to_string(),
+            capacity: parse_filesize(size).unwrap(),
+            immutable: false,
+            files: files.iter().map(|f| f.to_string()).collect(),
+            hashes: files
+                .iter()
+                .map(|f| (f.to_string(), content_hash(f.as_bytes())))
+                .collect(),
+        }
+    }
+
+    /// Local files whose contents are their names, as in [observed].
+    fn local(name: &str, files: &[&str]) -> HashMap<String, Vec<LocalFile>> {
+        let files = files
+            .iter()
+            .map(|f| LocalFile {
+                name: f.to_string(),
+                path: PathBuf::from(name).join(f),
+                hash: content_hash(f.as_bytes()),
+            })
+            .collect();
+        HashMap::from([(name.to_string(), files)])
+    }
+
+    #[test]
+    fn creates_missing_account_and_uploads() {
+        let layout = StorageLayout {
+            accounts: vec![desired("site", "10MB")],
+        };
+        let plan = diff(&layout, &[], &local("site", &["index.html"])).unwrap();
+        assert_eq!(
+            plan.changes,
+            vec![
+                Change::CreateAccount {
+                    name: "site".to_string(),
+                    size: parse_filesize("10MB").unwrap(),
+                },
+                Change::UploadFiles {
+                    account: AccountRef::New("site".to_string()),
+                    files: vec![PathBuf::from("site/index.html")],
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn matching_state_plans_nothing() {
+        let layout = StorageLayout {
+            accounts: vec![desired("site", "10MB")],
+        };
+        let existing = observed("site", "10MB", &["index.html"]);
+        let plan = diff(&layout, &[existing], &local("site", &["index.html"])).unwrap();
+        assert!(plan.is_empty());
+    }
+
+    #[test]
+    fn edits_changed_files() {
+        let layout = StorageLayout {
+            accounts: vec![desired("site", "10MB")],
+        };
+        let mut existing = observed("site", "10MB", &["index.html", "about.html"]);
+        existing
+            .hashes
+            .insert("index.html".to_string(), content_hash(b"old contents"));
+        let key = existing.key;
+        let local = local("site", &["about.html", "index.html"]);
+        let plan = diff(&layout, &[existing.clone()], &local).unwrap();
+        assert_eq!(
+            plan.changes,
+            vec![Change::EditFiles {
+                account: key,
+                files: vec![PathBuf::from("site/index.html")],
+            }]
+        );
+
+        existing.immutable = true;
+        let mut layout = layout;
+        layout.accounts[0].immutable = true;
+        assert!(diff(&layout, &[existing], &local).is_err());
+    }
+
+    #[test]
+    fn resizes_and_prunes_existing_account() {
+        let mut account = desired("site", "5MB");
+        account.delete_untracked = true;
+        let layout = StorageLayout {
+            accounts: vec![account],
+        };
+        let existing = observed("site", "10MB", &["old.html"]);
+        let key = existing.key;
+        let plan = diff(&layout, &[existing], &local("site", &[])).unwrap();
+        assert_eq!(
+            plan.changes,
+            vec![
+                Change::DeleteFiles {
+                    account: key,
+                    files: vec!["old.html".to_string()],
+                },
+                Change::ReduceStorage {
+                    account: key,
+                    size: parse_filesize("5MB").unwrap(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn immutable_accounts_cannot_become_mutable() {
+        let layout = StorageLayout {
+            accounts: vec![desired("site", "10MB")],
+        };
+        let mut existing = observed("site", "10MB", &[]);
+        existing.immutable = true;
+        assert!(diff(&layout, &[existing], &HashMap::new()).is_err());
+    }
+}
@@ -0,0 +1,362 @@
+//! Simulate transactions instead of sending them.
+//!
+//! [DryRunSender] wraps a real [RpcSender] and passes every request through,
+//! except `sendTransaction`: that transaction is simulated and priced instead,
+//! the result recorded in a [DryRunLog], and an error returned so the caller
+//! stops before waiting for a confirmation that will never come.
+//!
+//! Transactions which the Shadow Drive server co-signs and submits are built
+//! and partially signed by the SDK, then posted to the server rather than sent
+//! over RPC. For those, a [CapturingSigner] records the transaction message it
+//! is asked to sign and refuses, so nothing is posted, and [simulate_message]
+//! simulates it instead.
+use async_trait::async_trait;
+use serde::Serialize;
+use serde_json::{json, Value};
+use solana_client::client_error::Result as ClientResult;
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_client::rpc_request::{RpcError, RpcRequest};
+use solana_client::rpc_sender::{RpcSender, RpcTransportStats};
+use solana_sdk::message::Message;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::{Signature, Signer, SignerError};
+use solana_sdk::transaction::Transaction;
+use std::sync::{Arc, Mutex};
+
+use crate::output::{display, Render};
+use crate::wallet::OperationCost;
+
+/// An account referenced by a simulated transaction.
+#[derive(Debug, Clone, Serialize)]
+pub struct TouchedAccount {
+    #[serde(serialize_with = "display")]
+    pub address: Pubkey,
+    pub signer: bool,
+    pub writable: bool,
+}
+
+/// Outcome of simulating one transaction.
+///
+/// ```json
+/// {"accounts": [{"address": "<base58>", "signer": true, "writable": true}],
+///  "fee_lamports": 5000, "units_consumed": 12345, "error": null, "logs": ["..."]}
+/// ```
+#[derive(Debug, Clone, Serialize)]
+pub struct SimulationReport {
+    pub accounts: Vec<TouchedAccount>,
+    /// `None` if the RPC node could not price the message.
+    pub fee_lamports: Option<u64>,
+    pub units_consumed: Option<u64>,
+    /// The error the transaction would fail with, if any.
+    pub error: Option<String>,
+    pub logs: Vec<String>,
+}
+
+impl Render for SimulationReport {
+    fn text(&self) -> String {
+        let mut lines = vec!["Accounts:".to_string()];
+        for account in &self.accounts {
+            let mut flags = vec![];
+            if account.signer {
+                flags.push("signer");
+            }
+            if account.writable {
+                flags.push("writable");
+            }
+            lines.push(format!(
+                "  {} {}",
+                account.address.to_string(),
+                flags.join(", ")
+            ));
+        }
+        lines.push(format!(
+            "Fee: {}",
+            self.fee_lamports
+                .map(|fee| format!("{} lamports", fee))
+                .unwrap_or_else(|| "unknown".to_string())
+        ));
+        if let Some(units) = self.units_consumed {
+            lines.push(format!("Compute Units: {}", units));
+        }
+        lines.push(format!(
+            "Result: {}",
+            self.error.as_deref().unwrap_or("success")
+        ));
+        if !self.logs.is_empty() {
+            lines.push("Logs:".to_string());
+            lines.extend(self.logs.iter().map(|log| format!("  {}", log)));
+        }
+        lines.join("\n")
+    }
+}
+
+/// Everything a `--dry-run` found out about an operation.
+///
+/// ```json
+/// {"operation": "claim-stake", "estimated_cost": {"lamports": 10000, "shades": 0},
+///  "simulations": [...], "note": null}
+/// ```
+/// `note` explains why an operation could not be simulated, if it wasn't.
+#[derive(Debug, Clone, Serialize)]
+pub struct DryRunView {
+    pub operation: String,
+    pub estimated_cost: Option<OperationCost>,
+    pub simulations: Vec<SimulationReport>,
+    pub note: Option<String>,
+}
+
+impl Render for DryRunView {
+    fn text(&self) -> String {
+        let mut sections = vec![format!("Dry run of {}, nothing was sent", self.operation)];
+        if let Some(cost) = &self.estimated_cost {
+            sections.push(format!("Estimated cost: {}", cost));
+        }
+        if let Some(note) = &self.note {
+            sections.push(note.clone());
+        }
+        sections.extend(self.simulations.iter().map(|report| report.text()));
+        sections.join("\n")
+    }
+}
+
+/// Simulation reports collected by a [DryRunSender], shared with the caller.
+#[derive(Debug, Clone, Default)]
+pub struct DryRunLog(Arc<Mutex<Vec<SimulationReport>>>);
+
+impl DryRunLog {
+    pub fn len(&self) -> usize {
+        self.0.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn take(&self) -> Vec<SimulationReport> {
+        std::mem::take(&mut *self.0.lock().unwrap())
+    }
+
+    fn push(&self, report: SimulationReport) {
+        self.0.lock().unwrap().push(report);
+    }
+}
+
+pub struct DryRunSender<S: RpcSender> {
+    inner: S,
+    log: DryRunLog,
+}
+
+impl<S: RpcSender> DryRunSender<S> {
+    pub fn new(inner: S, log: DryRunLog) -> Self {
+        Self { inner, log }
+    }
+
+    async fn simulate(&self, params: &Value) -> ClientResult<SimulationReport> {
+        let transaction = decode_transaction(params)?;
+        let simulation = self
+            .inner
+            .send(
+                RpcRequest::SimulateTransaction,
+                simulate_params(&transaction)?,
+            )
+            .await?;
+        let fee = self
+            .inner
+            .send(RpcRequest::GetFeeForMessage, fee_params(&transaction))
+            .await?;
+        Ok(report(&transaction, &simulation, &fee))
+    }
+}
+
+/// Simulate a transaction message recorded by a [CapturingSigner], without
+/// its signatures.
+pub async fn simulate_message(rpc: &RpcClient, message: &[u8]) -> anyhow::Result<SimulationReport> {
+    let message: Message = bincode::deserialize(message)?;
+    let transaction = Transaction::new_unsigned(message);
+    let simulation: Value = rpc
+        .send(
+            RpcRequest::SimulateTransaction,
+            simulate_params(&transaction)?,
+        )
+        .await?;
+    let fee: Value = rpc
+        .send(RpcRequest::GetFeeForMessage, fee_params(&transaction))
+        .await?;
+    Ok(report(&transaction, &simulation, &fee))
+}
+
+/// Signatures are not checked, so unsigned and partially signed transactions
+/// simulate like fully signed ones.
+fn simulate_params(transaction: &Transaction) -> ClientResult<Value> {
+    let encoded = base64::encode(bincode::serialize(transaction).map_err(invalid)?);
+    Ok(json!([encoded, {
+        "encoding": "base64",
+        "sigVerify": false,
+        "replaceRecentBlockhash": true,
+        "commitment": "confirmed",
+    }]))
+}
+
+fn fee_params(transaction: &Transaction) -> Value {
+    let message = base64::encode(transaction.message_data());
+    json!([message, {"commitment": "confirmed"}])
+}
+
+fn report(transaction: &Transaction, simulation: &Value, fee: &Value) -> SimulationReport {
+    let value = &simulation["value"];
+    let accounts = transaction
+        .message
+        .account_keys
+        .iter()
+        .enumerate()
+        .map(|(i, address)| TouchedAccount {
+            address: *address,
+            signer: transaction.message.is_signer(i),
+            writable: transaction.message.is_writable(i),
+        })
+        .collect();
+    SimulationReport {
+        accounts,
+        fee_lamports: fee["value"].as_u64(),
+        units_consumed: value["unitsConsumed"].as_u64(),
+        error: match &value["err"] {
+            Value::Null => None,
+            err => Some(err.to_string()),
+        },
+        logs: serde_json::from_value(value["logs"].clone()).unwrap_or_default(),
+    }
+}
+
+fn invalid<E: ToString>(err: E) -> RpcError {
+    RpcError::ForUser(format!("dry run could not decode transaction: {}", err.to_string()))
+}
+
+/// Decode the transaction from `sendTransaction` params, in either
+/// of the encodings [solana_client] may use.
+fn decode_transaction(params: &Value) -> ClientResult<Transaction> {
+    let encoded = params[0].as_str().ok_or_else(|| invalid("missing"))?;
+    let bytes = match params[1]["encoding"].as_str() {
+        Some("base64") => base64::decode(encoded).map_err(invalid)?,
+        _ => bs58::decode(encoded).into_vec().map_err(invalid)?,
+    };
+    bincode::deserialize(&bytes).map_err(|err| invalid(err).into())
+}
+
+#[async_trait]
+impl<S: RpcSender + Send + Sync> RpcSender for DryRunSender<S> {
+    async fn send(&self, request: RpcRequest, params: Value) -> ClientResult<Value> {
+        if request != RpcRequest::SendTransaction {
+            return self.inner.send(request, params).await;
+        }
+        self.log.push(self.simulate(&params).await?);
+        Err(RpcError::ForUser("dry run: transaction simulated, not sent".to_string()).into())
+    }
+
+    fn get_transport_stats(&self) -> RpcTransportStats {
+        self.inner.get_transport_stats()
+    }
+
+    fn url(&self) -> String {
+        self.inner.url()
+    }
+}
+
+/// Transaction messages recorded by a [CapturingSigner], shared with the caller.
+#[derive(Debug, Clone, Default)]
+pub struct CapturedMessages(Arc<Mutex<Vec<Vec<u8>>>>);
+
+impl CapturedMessages {
+    pub fn len(&self) -> usize {
+        self.0.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn take(&self) -> Vec<Vec<u8>> {
+        std::mem::take(&mut *self.0.lock().unwrap())
+    }
+}
+
+/// A [Signer] which records every message it is asked to sign, then fails,
+/// so the SDK stops before posting a transaction to the Shadow Drive server.
+pub struct CapturingSigner<T: Signer> {
+    inner: T,
+    captured: CapturedMessages,
+}
+
+impl<T: Signer> CapturingSigner<T> {
+    pub fn new(inner: T, captured: CapturedMessages) -> Self {
+        Self { inner, captured }
+    }
+}
+
+impl<T: Signer> Signer for CapturingSigner<T> {
+    fn try_pubkey(&self) -> Result<Pubkey, SignerError> {
+        self.inner.try_pubkey()
+    }
+
+    fn try_sign_message(&self, message: &[u8]) -> Result<Signature, SignerError> {
+        self.captured.0.lock().unwrap().push(message.to_vec());
+        Err(SignerError::Custom(
+            "dry run: transaction captured, not signed".to_string(),
+        ))
+    }
+
+    fn is_interactive(&self) -> bool {
+        self.inner.is_interactive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use shadow_drive_test_support::MockServer;
+    use solana_sdk::hash::Hash;
+    use solana_sdk::instruction::{AccountMeta, Instruction};
+    use solana_sdk::signature::Keypair;
+
+    #[tokio::test]
+    async fn simulates_captured_partially_signed_transactions() {
+        let captured = CapturedMessages::default();
+        let signer = CapturingSigner::new(Keypair::new(), captured.clone());
+        let co_signer = Pubkey::new_unique();
+        let instruction = Instruction::new_with_bytes(
+            Pubkey::new_unique(),
+            &[1, 2, 3],
+            vec![
+                AccountMeta::new(signer.pubkey(), true),
+                AccountMeta::new_readonly(co_signer, true),
+            ],
+        );
+        let mut transaction = Transaction::new_with_payer(&[instruction], Some(&signer.pubkey()));
+        assert!(transaction
+            .try_partial_sign(&[&signer], Hash::default())
+            .is_err());
+        assert_eq!(captured.len(), 1);
+
+        let rpc_node = MockServer::rpc(|method, params| match method {
+            "simulateTransaction" => {
+                assert_eq!(params[1]["sigVerify"], json!(false));
+                Ok(json!({ "context": { "slot": 1 }, "value": {
+                    "err": null, "logs": ["Program log: ok"], "unitsConsumed": 1200,
+                }}))
+            }
+            "getFeeForMessage" => Ok(json!({ "context": { "slot": 1 }, "value": 10000 })),
+            _ => Err((-32601, "Method not found".to_string())),
+        })
+        .unwrap();
+        let rpc = RpcClient::new(rpc_node.url().to_string());
+        let report = simulate_message(&rpc, &captured.take()[0]).await.unwrap();
+        assert_eq!(report.fee_lamports, Some(10000));
+        assert_eq!(report.units_consumed, Some(1200));
+        assert_eq!(report.error, None);
+        let co_signer = report
+            .accounts
+            .iter()
+            .find(|account| account.address == co_signer)
+            .unwrap();
+        assert!(co_signer.signer && !co_signer.writable);
+    }
+}
@@ -0,0 +1,263 @@
+//! Typed errors with stable exit codes.
+//!
+//! Every failure the CLI knows how to classify is a [CliError]. Each variant
+//! maps to a documented process exit code, so scripts can tell a duplicate
+//! upload from an empty wallet from a network outage without parsing text.
+//!
+//! | Code | Variant |
+//! |---|---|
+//! | 0 | success |
+//! | 1 | [CliError::Other], or any unclassified error |
+//! | 2 | invalid command-line usage (reported by `clap`) |
+//! | 3 | [CliError::Cancelled] |
+//! | 4 | [CliError::Network] |
+//! | 5 | [CliError::InsufficientFunds] |
+//! | 6 | [CliError::InsufficientStorage] |
+//! | 7 | [CliError::FileAlreadyExists] |
+//! | 8 | [CliError::NotFound] |
+//! | 9 | [CliError::Immutable] |
+//! | 10 | [CliError::InvalidFile] |
+//! | 11 | [CliError::Server] |
+//! | 12 | [CliError::BatchFailed] |
+//! | 13 | GenesysGo sign-in failed, see [AuthError] |
+use shadow_drive_rust::error::{Error, FileError};
+use shadow_rpc_auth::AuthError;
+use solana_client::client_error::{ClientError, ClientErrorKind};
+use solana_sdk::transaction::TransactionError;
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum CliError {
+    /// The user declined a confirmation prompt.
+    #[error("aborted: {0}")]
+    Cancelled(String),
+    /// The RPC node or Shadow Drive server could not be reached.
+    #[error("network error: {0}")]
+    Network(String),
+    /// The wallet can't pay the SOL fees or SHDW price of an operation.
+    #[error("insufficient funds: {0}")]
+    InsufficientFunds(String),
+    /// The storage account has no room left for the upload.
+    #[error("insufficient storage: {0}")]
+    InsufficientStorage(String),
+    /// A file with the same name is already stored.
+    #[error("file already exists: {0}")]
+    FileAlreadyExists(String),
+    /// The storage account or file does not exist.
+    #[error("not found: {0}")]
+    NotFound(String),
+    /// The storage account is immutable and can't be changed this way.
+    #[error("storage account is immutable: {0}")]
+    Immutable(String),
+    /// A local file could not be read or failed validation.
+    #[error("invalid file: {0}")]
+    InvalidFile(String),
+    /// The Shadow Drive server rejected the request for another reason.
+    #[error("Shadow Drive Server Error {status}: {message}")]
+    Server { status: u16, message: String },
+    /// Some accounts of an `--all-accounts` operation failed.
+    #[error("{failed} of {total} storage accounts failed")]
+    BatchFailed { failed: usize, total: usize },
+    #[error("{0}")]
+    Other(String),
+}
+
+impl CliError {
+    /// Process exit code for this error, see the module docs.
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            CliError::Other(_) => 1,
+            CliError::Cancelled(_) => 3,
+            CliError::Network(_) => 4,
+            CliError::InsufficientFunds(_) => 5,
+            CliError::InsufficientStorage(_) => 6,
+            CliError::FileAlreadyExists(_) => 7,
+            CliError::NotFound(_) => 8,
+            CliError::Immutable(_) => 9,
+            CliError::InvalidFile(_) => 10,
+            CliError::Server { .. } => 11,
+            CliError::BatchFailed { .. } => 12,
+        }
+    }
+
+    /// What the user can do about it, if there's a usual fix.
+    pub fn hint(&self) -> Option<&'static str> {
+        match self {
+            CliError::Network(_) => {
+                Some("check your connection and the `-u/--url` endpoint, then retry")
+            }
+            CliError::InsufficientFunds(_) => Some(
+                "check SOL and SHDW balances with `wallet balance`, \
+                or create a SHDW token account with `wallet create-shdw-account`",
+            ),
+            CliError::InsufficientStorage(_) => {
+                Some("run `add-storage` to increase the storage account's capacity")
+            }
+            CliError::FileAlreadyExists(_) => {
+                Some("use `edit-file` to replace an existing file, or rename the upload")
+            }
+            CliError::NotFound(_) => Some(
+                "check the storage account address and file name, \
+                `get-storage-accounts` lists your accounts",
+            ),
+            CliError::Immutable(_) => {
+                Some("immutable storage accounts only accept new files and `add-immutable-storage`")
+            }
+            CliError::BatchFailed { .. } => {
+                Some("rerun the command to retry the failed storage accounts")
+            }
+            _ => None,
+        }
+    }
+
+    /// Classify a rejection from the Shadow Drive server by its status and message.
+    ///
+    /// The server has no error codes, so this is best-effort: it matches
+    /// substrings of the messages it is known to send, and anything it doesn't
+    /// recognize, including reworded messages, falls back to [CliError::Server].
+    pub fn from_server(status: u16, message: String) -> Self {
+        let lower = message.to_lowercase();
+        if lower.contains("already exist") {
+            CliError::FileAlreadyExists(message)
+        } else if lower.contains("not enough storage") || lower.contains("insufficient storage") {
+            CliError::InsufficientStorage(message)
+        } else if lower.contains("insufficient funds") || lower.contains("insufficient balance") {
+            CliError::InsufficientFunds(message)
+        } else if lower.contains("immutable") {
+            CliError::Immutable(message)
+        } else if status == 404 || lower.contains("not found") || lower.contains("does not exist") {
+            CliError::NotFound(message)
+        } else {
+            CliError::Server { status, message }
+        }
+    }
+}
+
+impl From<Error> for CliError {
+    fn from(err: Error) -> Self {
+        match err {
+            Error::ShadowDriveServerError { status, message } => {
+                let message = message.to_string().trim_matches('"').to_string();
+                CliError::from_server(status, message)
+            }
+            Error::FileSystemError(err) => CliError::InvalidFile(err.to_string()),
+            Error::FileValidationError(errs) => CliError::InvalidFile(
+                errs.into_iter()
+                    .map(|FileError { file, error }| format!("{}: {}", file, error))
+                    .collect::<Vec<_>>()
+                    .join(", "),
+            ),
+            Error::ReqwestError(err) => CliError::Network(err.to_string()),
+            Error::SolanaRpcError(err) => {
+                let insufficient_funds = matches!(
+                    err.get_transaction_error(),
+                    Some(TransactionError::InsufficientFundsForFee)
+                        | Some(TransactionError::InsufficientFundsForRent { .. })
+                );
+                match err.kind() {
+                    _ if insufficient_funds => CliError::InsufficientFunds(err.to_string()),
+                    ClientErrorKind::Io(_) | ClientErrorKind::Reqwest(_) => {
+                        CliError::Network(err.to_string())
+                    }
+                    _ => CliError::Other(err.to_string()),
+                }
+            }
+            e => CliError::Other(format!("{:?}", e)),
+        }
+    }
+}
+
+/// Exit code for an error which may wrap a [CliError] anywhere in its chain.
+/// Transport failures from RPC calls made outside the Shadow Drive SDK
+/// are reported as [CliError::Network] as well.
+pub fn exit_code(err: &anyhow::Error) -> i32 {
+    match (cli_error(err), auth_error(err)) {
+        (Some(err), _) => err.exit_code(),
+        (None, Some(AuthError::Request(_))) => CliError::Network(String::new()).exit_code(),
+        (None, Some(_)) => 13,
+        (None, None) if is_network_error(err) => CliError::Network(String::new()).exit_code(),
+        (None, None) => 1,
+    }
+}
+
+/// The first [AuthError] in the chain of `err`, if any.
+fn auth_error(err: &anyhow::Error) -> Option<&AuthError> {
+    err.chain().find_map(|e| e.downcast_ref::<AuthError>())
+}
+
+fn auth_hint(err: &AuthError) -> Option<&'static str> {
+    match err {
+        AuthError::InvalidSignature { .. } => Some(
+            "sign in with the keypair owning the GenesysGo account, \
+            and check the profile's `message` if it sets one",
+        ),
+        AuthError::UnknownAccount { .. } => Some(
+            "check the account ID in `--auth-account-id`, \
+            the profile's `account_id` or the `-u/--url` path",
+        ),
+        AuthError::RateLimited { .. } => Some("wait a little before signing in again"),
+        AuthError::MalformedResponse { .. } | AuthError::Http { .. } => {
+            Some("check the profile's `portal_url` if it sets one")
+        }
+        AuthError::Request(_) => CliError::Network(String::new()).hint(),
+        AuthError::Message(_) => Some("check the profile's `message` and `format`"),
+    }
+}
+
+/// The first [CliError] in the chain of `err`, if any.
+pub fn cli_error(err: &anyhow::Error) -> Option<&CliError> {
+    err.chain().find_map(|e| e.downcast_ref::<CliError>())
+}
+
+fn is_network_error(err: &anyhow::Error) -> bool {
+    err.chain().any(|e| {
+        e.downcast_ref::<reqwest::Error>().is_some()
+            || matches!(
+                e.downcast_ref::<ClientError>().map(ClientError::kind),
+                Some(ClientErrorKind::Io(_)) | Some(ClientErrorKind::Reqwest(_))
+            )
+    })
+}
+
+/// Print `err` and its remediation hint to stderr, returning the exit code.
+pub fn report(err: &anyhow::Error) -> i32 {
+    eprintln!("Error: {:#}", err);
+    let hint = match (cli_error(err), auth_error(err)) {
+        (Some(err), _) => err.hint(),
+        (None, Some(err)) => auth_hint(err),
+        (None, None) if is_network_error(err) => CliError::Network(String::new()).hint(),
+        (None, None) => None,
+    };
+    if let Some(hint) = hint {
+        eprintln!("Hint: {}", hint);
+    }
+    exit_code(err)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn classifies_server_messages() {
+        let cases = [
+            ("File with name a.txt already exists", 400, 7),
+            ("Not enough storage available on this Storage Account", 400, 6),
+            ("Storage account is immutable", 400, 9),
+            ("Storage account not found", 400, 8),
+            ("", 404, 8),
+            ("Unexpected server error", 500, 11),
+        ];
+        for (message, status, code) in cases {
+            let err = CliError::from_server(status, message.to_string());
+            assert_eq!(err.exit_code(), code, "{}", message);
+        }
+    }
+
+    #[test]
+    fn finds_cli_error_in_chain() {
+        let err = anyhow::Error::new(CliError::Cancelled("no".to_string())).context("while deleting");
+        assert_eq!(exit_code(&err), 3);
+        assert_eq!(exit_code(&anyhow::anyhow!("plain")), 1);
+    }
+}
@@ -1,14 +1,31 @@
+pub mod client;
+pub mod completion;
+pub mod config;
+pub mod declarative;
+pub mod dry_run;
+pub mod error;
+pub mod logging;
+pub mod migration;
+pub mod output;
+pub mod stats;
+pub mod storage_accounts;
+pub mod wallet;
 
 use anyhow::anyhow;
 use byte_unit::Byte;
+use log::debug;
 use reqwest::header::HeaderMap;
 use reqwest::Response;
-use shadow_drive_rust::error::{Error, FileError};
 use shadow_drive_rust::models::ShadowDriveResult;
+use shadow_rpc_auth::TokenCache;
+use crate::client::DriveHttpClient;
+use crate::error::CliError;
+use crate::storage_accounts::StorageAccountDetails;
 use solana_sdk::pubkey::Pubkey;
 use solana_sdk::signature::{Signature, Signer, SignerError};
-use std::io::stdin;
-use std::path::PathBuf;
+use std::fs::OpenOptions;
+use std::io::{stdin, BufRead, ErrorKind, Write};
+use std::path::{Path, PathBuf};
 use std::str::FromStr;
 
 /// Shadow Drive Files are hosted at this domain.
@@ -17,6 +34,9 @@ pub const GENESYSGO_DRIVE: &str = "https://shdw-drive.genesysgo.net";
 /// Maximum amount of files to batch into a single [store_files] request.
 pub const FILE_UPLOAD_BATCH_SIZE: usize = 5;
 
+/// Shadow Drive server which handles uploads and file metadata.
+pub const SHDW_STORAGE_SERVER: &str = "https://shadow-storage.genesysgo.net";
+
 /// Clap value parser for base58 string representations of [Pubkey].
 pub fn pubkey_arg(pubkey: &str) -> anyhow::Result<Pubkey> {
     Pubkey::from_str(pubkey).map_err(
@@ -51,51 +71,24 @@ impl Signer for WrappedSigner {
     }
 }
 
-/// Further diagnostic printing wherever possible.
+/// The GenesysGo RPC token cache, kept under the user's config directory.
+pub fn token_cache() -> anyhow::Result<TokenCache> {
+    TokenCache::default_dir()
+        .map(TokenCache::new)
+        .ok_or_else(|| anyhow!("unable to determine a config directory on this OS or user"))
+}
+
+/// Convert a Shadow Drive SDK result into one carrying a typed [CliError],
+/// so the exit code reflects what went wrong. Nothing is printed here,
+/// reporting is left to the caller.
 pub fn process_shadow_api_response<T>(response: ShadowDriveResult<T>) -> anyhow::Result<T> {
-    match response {
-        Ok(response) => Ok(response),
-        Err(err) => match err {
-            Error::ShadowDriveServerError { status, message } => {
-                let err = format!(
-                    "Shadow Drive Server Error {}: {:#?}",
-                    status,
-                    message.to_string()
-                );
-                println!("{}", err);
-                Err(anyhow!("{}", err))
-            }
-            Error::FileSystemError(err) => {
-                let err = format!("Filesystem Error: {:#?}", err.to_string());
-                println!("{}", err);
-                Err(anyhow!("{}", err))
-            }
-            Error::FileValidationError(errs) => {
-                let mut err_vec = vec![];
-                for err in errs {
-                    let FileError { file, error } = err;
-                    let err = format!("File Validation Error for {}: {}", file, error);
-                    err_vec.push(err);
-                }
-                println!("{:#?}", err_vec);
-                Err(anyhow!("{:#?}", err_vec))
-            }
-            e => {
-                println!("{:#?}", e);
-                Err(anyhow!("{:#?}", e))
-            }
-        },
-    }
-}
-
-/// Generate a Shadow Drive file URL from storage account and filename.
-pub fn drive_url(storage_account: &Pubkey, file: &str) -> String {
-    format!(
-        "{}/{}/{}",
-        GENESYSGO_DRIVE,
-        storage_account.to_string(),
-        file
-    )
+    response.map_err(|err| CliError::from(err).into())
+}
+
+/// Generate a Shadow Drive file URL on `drive_host` from storage account and filename,
+/// see [DriveHttpClient::file_url].
+pub fn drive_url(drive_host: &str, storage_account: &Pubkey, file: &str) -> String {
+    format!("{}/{}/{}", drive_host, storage_account.to_string(), file)
 }
 
 /// Pull the basename off of a filepath. Useful for automatically
@@ -108,6 +101,38 @@ pub fn acquire_basename(path: &str) -> String {
         .to_string()
 }
 
+/// The local path to save the Shadow Drive file `name` to, in the current directory.
+/// Names come from the server, so only their last component is kept, and names
+/// which would still leave the directory are rejected.
+pub fn local_download_path(name: &str) -> anyhow::Result<PathBuf> {
+    match Path::new(name).file_name().and_then(|base| base.to_str()) {
+        Some(base) if !base.contains('\\') && base != "." => Ok(PathBuf::from(base)),
+        _ => Err(CliError::InvalidFile(format!(
+            "refusing to save unsafe file name {:?}",
+            name
+        ))
+        .into()),
+    }
+}
+
+/// Write `bytes` to `path`, which must not exist yet, so that a download never
+/// replaces a local file.
+pub fn save_new_file(path: &Path, bytes: &[u8]) -> anyhow::Result<()> {
+    let mut file = OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(path)
+        .map_err(|e| match e.kind() {
+            ErrorKind::AlreadyExists => CliError::Other(format!(
+                "{} already exists, remove it or save the download under another name",
+                path.display()
+            )),
+            _ => CliError::InvalidFile(format!("unable to create {}: {}", path.display(), e)),
+        })?;
+    file.write_all(bytes)?;
+    Ok(())
+}
+
 /// Returns false when "Content-Type" header is not "text/plain".
 fn is_text_response(headers: &HeaderMap) -> anyhow::Result<bool> {
     let content_type = headers
@@ -119,13 +144,73 @@ fn is_text_response(headers: &HeaderMap) -> anyhow::Result<bool> {
 
 /// Check with a HEAD that the URL exists and is a "text/plain" file.
 /// If so, return the response of a GET request.
-pub async fn get_text(url: &String) -> anyhow::Result<Response> {
-    let http_client = reqwest::Client::new();
-    let head_resp = http_client.head(url).send().await?;
+pub async fn get_text(http_client: &DriveHttpClient, url: &String) -> anyhow::Result<Response> {
+    let head_resp = http_client.send("HEAD file", http_client.head(url)).await?;
+    debug!("HEAD {}: {}", url, head_resp.status());
     if !is_text_response(head_resp.headers())? {
         return Err(anyhow!("Not a text file at url {}", url));
     }
-    Ok(http_client.get(url).send().await?)
+    Ok(http_client.send("GET file", http_client.get(url)).await?)
+}
+
+/// Fetch the metadata of the file at `location` as raw JSON,
+/// exactly as returned by the Shadow Drive server.
+pub async fn get_object_data_json(
+    http_client: &DriveHttpClient,
+    location: &str,
+) -> anyhow::Result<serde_json::Value> {
+    let request = http_client
+        .post(format!("{}/get-object-data", http_client.storage_server()))
+        .header("Content-Type", "application/json")
+        .body(serde_json::json!({ "location": location }).to_string());
+    let resp = http_client.send("get-object-data", request).await?;
+    let status = resp.status();
+    debug!("get-object-data {}: {}", location, status);
+    if !status.is_success() {
+        return Err(CliError::from_server(status.as_u16(), resp.text().await?).into());
+    }
+    Ok(serde_json::from_str(&resp.text().await?)?)
+}
+
+/// Names of the files held by `storage_account`.
+pub async fn list_objects(
+    http_client: &DriveHttpClient,
+    storage_account: &Pubkey,
+) -> anyhow::Result<Vec<String>> {
+    let request = http_client
+        .post(format!("{}/list-objects", http_client.storage_server()))
+        .header("Content-Type", "application/json")
+        .body(serde_json::json!({ "storageAccount": storage_account.to_string() }).to_string());
+    let resp = http_client.send("list-objects", request).await?;
+    let status = resp.status();
+    debug!("list-objects {}: {}", storage_account.to_string(), status);
+    if !status.is_success() {
+        return Err(CliError::from_server(status.as_u16(), resp.text().await?).into());
+    }
+    let body: serde_json::Value = serde_json::from_str(&resp.text().await?)?;
+    serde_json::from_value(body["keys"].clone())
+        .map_err(|_| anyhow!("list-objects did not return a list of keys: {}", body))
+}
+
+/// Bytes of files held by `storage_account`, as reported by the Shadow Drive server.
+pub async fn storage_used(
+    http_client: &DriveHttpClient,
+    storage_account: &Pubkey,
+) -> anyhow::Result<u64> {
+    let request = http_client
+        .post(format!("{}/storage-account-size", http_client.storage_server()))
+        .header("Content-Type", "application/json")
+        .body(serde_json::json!({ "storage_account": storage_account.to_string() }).to_string());
+    let resp = http_client.send("storage-account-size", request).await?;
+    let status = resp.status();
+    debug!("storage-account-size {}: {}", storage_account.to_string(), status);
+    if !status.is_success() {
+        return Err(CliError::from_server(status.as_u16(), resp.text().await?).into());
+    }
+    let body: serde_json::Value = serde_json::from_str(&resp.text().await?)?;
+    body["storage_used"]
+        .as_u64()
+        .ok_or_else(|| anyhow!("storage-account-size did not report storage_used: {}", body))
 }
 
 /// Pulls "last-modified" from [HeaderMap], unaltered.
@@ -148,14 +233,115 @@ pub fn parse_filesize(size: &str) -> anyhow::Result<Byte> {
     })
 }
 
-/// Confirm from the user that they definitely want some irreversible
-/// operation to occur.
+/// How much confirmation is required from the user before altering state.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ConfirmPolicy {
+    /// Bypass the "Press ENTER" prompt for ordinary state changes.
+    pub skip_confirm: bool,
+    /// Together with `skip_confirm`, also bypass the typed confirmation
+    /// of irreversible operations.
+    pub yes_i_am_sure_immutable: bool,
+}
+
+/// Confirm from the user that they want some state-altering operation to occur.
+/// Anything other than a bare ENTER, including end of input, cancels it.
 pub fn wait_for_user_confirmation(skip: bool) -> anyhow::Result<()> {
     if skip {
         return Ok(());
     }
-    println!("Press ENTER to continue, or CTRL+C to abort");
+    eprintln!("Press ENTER to continue, or type anything else to abort");
+    read_confirmation(&mut stdin().lock())
+}
+
+fn read_confirmation(input: &mut impl BufRead) -> anyhow::Result<()> {
     let mut proceed = String::new();
-    stdin().read_line(&mut proceed)?;
+    if input.read_line(&mut proceed)? == 0 {
+        return Err(CliError::Cancelled("no confirmation on standard input".to_string()).into());
+    }
+    if !proceed.trim().is_empty() {
+        return Err(CliError::Cancelled("confirmation declined".to_string()).into());
+    }
+    Ok(())
+}
+
+/// Confirm an operation that can never be undone, such as making a storage
+/// account immutable or deleting it. The account is described in full and
+/// the user must type its name (or address, if it has no name) to proceed.
+/// `--skip-confirm` alone is not enough to bypass this.
+pub fn confirm_irreversible(
+    policy: ConfirmPolicy,
+    operation: &str,
+    account: &StorageAccountDetails,
+) -> anyhow::Result<()> {
+    eprintln!("{} (IRREVERSIBLE)", operation);
+    eprintln!("  Storage Account: {}", account.key.to_string());
+    eprintln!("  Name:            {}", account.name);
+    eprintln!("  Files:           {}", account.file_count);
+    eprintln!(
+        "  Capacity:        {}",
+        account.capacity.get_appropriate_unit(false)
+    );
+    let expected = if account.name.is_empty() {
+        account.key.to_string()
+    } else {
+        account.name.clone()
+    };
+    confirm_typed(policy, operation, &expected)
+}
+
+/// Require the user to type `expected` before an irreversible `operation`,
+/// unless both `--skip-confirm` and `--yes-i-am-sure-immutable` were passed.
+pub fn confirm_typed(policy: ConfirmPolicy, operation: &str, expected: &str) -> anyhow::Result<()> {
+    if policy.skip_confirm {
+        if policy.yes_i_am_sure_immutable {
+            return Ok(());
+        }
+        return Err(CliError::Cancelled(format!(
+            "{} cannot be undone: --skip-confirm also requires --yes-i-am-sure-immutable",
+            operation
+        ))
+        .into());
+    }
+    eprintln!(
+        "Type \"{}\" to continue, or anything else to abort:",
+        expected
+    );
+    let mut typed = String::new();
+    stdin().read_line(&mut typed)?;
+    if typed.trim() != expected {
+        return Err(CliError::Cancelled("confirmation did not match".to_string()).into());
+    }
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::exit_code;
+
+    #[test]
+    fn only_a_bare_enter_confirms() {
+        assert!(read_confirmation(&mut "\n".as_bytes()).is_ok());
+        assert!(read_confirmation(&mut "  \r\n".as_bytes()).is_ok());
+        for input in ["", "n\n", "no\n"] {
+            let err = read_confirmation(&mut input.as_bytes()).unwrap_err();
+            assert_eq!(exit_code(&err), 3, "{:?}", input);
+        }
+    }
+
+    #[test]
+    fn keeps_downloads_in_the_current_directory() {
+        assert_eq!(local_download_path("a.txt").unwrap(), PathBuf::from("a.txt"));
+        assert_eq!(local_download_path("../../x").unwrap(), PathBuf::from("x"));
+        assert_eq!(local_download_path("/etc/passwd").unwrap(), PathBuf::from("passwd"));
+        for name in ["", "..", "/", "a/..", "..\\x"] {
+            assert!(local_download_path(name).is_err(), "{:?}", name);
+        }
+
+        let path = std::env::temp_dir().join(format!("cli-download-{}", std::process::id()));
+        save_new_file(&path, b"first").unwrap();
+        assert!(save_new_file(&path, b"second").is_err());
+        assert_eq!(std::fs::read(&path).unwrap(), b"first");
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,39 @@
+//! Logger setup for `-v/--verbose`, `RUST_LOG` and `--log-file`.
+//!
+//! JSON-RPC requests and responses are traced by [shadow_rpc_auth::HttpSenderWithHeaders]
+//! at `debug` and `trace`, and the CLI's own Shadow Drive requests by
+//! [DriveHttpClient](crate::client::DriveHttpClient), both with header values other
+//! than well-known ones such as `Content-Type` redacted. `reqwest` logs full URLs,
+//! which may carry API keys, so its own logging is limited to warnings unless
+//! `RUST_LOG` asks for more.
+use env_logger::{Builder, Env, Target};
+use log::LevelFilter;
+use std::fs::OpenOptions;
+use std::path::Path;
+
+/// Level for a number of `-v` flags: warnings only by default,
+/// then `info`, `debug` (request tracing) and `trace` (request and response bodies).
+pub fn verbosity_level(verbose: u8) -> LevelFilter {
+    match verbose {
+        0 => LevelFilter::Warn,
+        1 => LevelFilter::Info,
+        2 => LevelFilter::Debug,
+        _ => LevelFilter::Trace,
+    }
+}
+
+/// Initialize the global logger. `RUST_LOG`, when set, takes precedence over `-v`.
+/// Logs go to stderr, or are appended to `log_file`.
+pub fn init_logging(verbose: u8, log_file: Option<&Path>) -> anyhow::Result<()> {
+    let mut builder = Builder::new();
+    builder.filter_level(verbosity_level(verbose));
+    builder.filter_module("reqwest", verbosity_level(verbose).min(LevelFilter::Warn));
+    builder.parse_env(Env::default());
+    if let Some(path) = log_file {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        builder.target(Target::Pipe(Box::new(file)));
+        builder.write_style(env_logger::WriteStyle::Never);
+    }
+    builder.try_init()?;
+    Ok(())
+}
@@ -2,17 +2,43 @@ mod cli;
 
 use anyhow::anyhow;
 use clap::{IntoApp, Parser};
-use cli::Opts;
-use shadow_drive_cli::WrappedSigner;
-use shadow_rpc_auth::{sign_in, parse_account_id_from_url};
+use cli::{Command, Opts};
+use clap_complete::Shell;
+use shadow_drive_cli::client::{commitment_arg, ClientFactory, DEFAULT_TIMEOUT};
+use shadow_drive_cli::completion::{BASH_DYNAMIC, FISH_DYNAMIC};
+use shadow_drive_cli::logging::init_logging;
+use shadow_drive_cli::stats;
+use shadow_drive_cli::config::{
+    parse_headers, AuthConfig, CliConfig, EndpointConfig, GenesysGoSignIn, RetryConfig,
+};
+use shadow_drive_cli::{ConfirmPolicy, WrappedSigner};
+use shadow_rpc_auth::token_provider::SignerLoader;
+use shadow_rpc_auth::Cassette;
 use solana_clap_v3_utils::keypair::signer_from_path;
+use solana_sdk::signature::Signer;
+use std::sync::Arc;
+use std::time::Duration;
 
-pub const GENESYSGO_AUTH_KEYWORD: &str = "genesysgo";
-
+/// Exit codes are documented in [shadow_drive_cli::error].
 #[tokio::main]
-async fn main() -> anyhow::Result<()> {
+async fn main() {
+    if let Err(err) = run().await {
+        std::process::exit(shadow_drive_cli::error::report(&err));
+    }
+}
+
+async fn run() -> anyhow::Result<()> {
     // CLI Parse
     let opts = Opts::parse();
+    init_logging(
+        opts.cfg_override.verbose,
+        opts.cfg_override.log_file.as_deref(),
+    )?;
+
+    // Completion scripts need neither a signer nor a cluster.
+    if let Command::Completions { shell, dynamic } = &opts.command {
+        return print_completions(*shell, *dynamic);
+    }
 
     // Get signer string from either an argument or the Solana CLI config file
     let app = Opts::into_app();
@@ -23,9 +49,15 @@ async fn main() -> anyhow::Result<()> {
         solana_cli_config::Config::load(&config_file)
             .map_err(|e| anyhow!("unable to load config file: {}", e.to_string()))
     }?;
+    let profile = {
+        let path = CliConfig::default_path()
+            .ok_or_else(|| anyhow!("unable to determine the CLI config file path"))?;
+        CliConfig::load(&path)?.profile(opts.cfg_override.profile.as_deref())?
+    };
     let keypath = opts
         .cfg_override
         .keypair
+        .or(profile.keypair)
         .unwrap_or(config.keypair_path.clone());
     // Resolve it into a dyn Signer.
     let mut wallet_manager = None;
@@ -33,19 +65,177 @@ async fn main() -> anyhow::Result<()> {
         .map_err(|e| anyhow!("Could not resolve signer: {:?}", e))?;
     let signer = WrappedSigner::new(signer);
 
-    // Resolve the RPC URL from either a command-line arg or the Solana CLI config file.
-    let url = opts.cfg_override.url.unwrap_or(config.json_rpc_url);
+    // Resolve the RPC URLs from either command-line args, the profile or the Solana CLI
+    // config file. Requests go to the first and fail over to the others in order.
+    let mut urls = opts.cfg_override.url.into_iter();
+    let (url, fallbacks) = match urls.next() {
+        Some(url) => (
+            url,
+            urls.map(|url| EndpointConfig {
+                url,
+                auth: None,
+                headers: Vec::new(),
+            })
+            .collect(),
+        ),
+        None => (
+            profile.url.unwrap_or(config.json_rpc_url),
+            profile.fallbacks,
+        ),
+    };
 
-    // Possibly perform a sign-in operation
-    let mut auth: Option<String> = opts.cfg_override.auth.clone();
-    if opts.cfg_override.auth == Some(GENESYSGO_AUTH_KEYWORD.to_string()) {
-        let account_id = parse_account_id_from_url(url.to_string())?;
-        let token = sign_in(&signer, &account_id).await?;
-        auth = Some(token)
+    // Authenticate RPC requests as given by `--auth` or the profile. GenesysGo tokens
+    // are signed in for when first needed and renewed when they are rejected.
+    // (`shadow-rpc-auth` manages the cached token itself.)
+    let mut auth_config = match &opts.cfg_override.auth {
+        Some(auth) => Some(AuthConfig::from_flag(auth)),
+        None => profile.auth,
+    };
+    let auth_account_id = opts.cfg_override.auth_account_id.clone();
+    if let (Some(auth_config), Some(account_id)) = (&mut auth_config, &auth_account_id) {
+        auth_config.set_genesysgo_account_id(account_id);
+    }
+    let load_signer: SignerLoader = {
+        let matches = matches.clone();
+        let keypath = keypath.clone();
+        Arc::new(move || {
+            signer_from_path(&matches, &keypath, "keypair", &mut None)
+                .map_err(|e| anyhow!("Could not resolve signer: {:?}", e))
+        })
     };
+    let (auth, genesysgo) = match auth_config {
+        Some(auth_config) => (
+            Some(auth_config.provider(&url, signer.pubkey(), load_signer.clone())?),
+            auth_config.genesysgo_sign_in(),
+        ),
+        None => (
+            None,
+            GenesysGoSignIn {
+                account_id: auth_account_id,
+                ..Default::default()
+            },
+        ),
+    };
+    // Fallbacks authenticate and get headers only as configured for them, so that
+    // credentials for one endpoint are never sent to another.
+    let fallbacks = fallbacks
+        .into_iter()
+        .map(|endpoint| {
+            let auth = endpoint
+                .auth
+                .map(|auth| auth.provider(&endpoint.url, signer.pubkey(), load_signer.clone()))
+                .transpose()?;
+            Ok((endpoint.url, auth, parse_headers(&endpoint.headers)?))
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
+
+    // Every command builds its clients from these settings.
+    let commitment = match (opts.cfg_override.commitment, profile.commitment) {
+        (Some(commitment), _) => commitment,
+        (None, Some(commitment)) => commitment_arg(&commitment)?,
+        (None, None) => commitment_arg(&config.commitment)?,
+    };
+    let timeout = opts
+        .cfg_override
+        .rpc_timeout
+        .or(profile.rpc_timeout)
+        .map(Duration::from_secs)
+        .unwrap_or(DEFAULT_TIMEOUT);
+    let mut rpc_headers = parse_headers(&profile.rpc_headers)?;
+    rpc_headers.extend(parse_headers(&opts.cfg_override.rpc_header)?);
+    let mut drive_headers = parse_headers(&profile.drive_headers)?;
+    drive_headers.extend(parse_headers(&opts.cfg_override.drive_header)?);
+    let retry = RetryConfig {
+        max_attempts: opts.cfg_override.rpc_max_attempts,
+        deadline: opts.cfg_override.rpc_retry_deadline,
+        ..Default::default()
+    };
+    let retry = match profile.retry {
+        Some(profile_retry) => RetryConfig {
+            max_attempts: retry.max_attempts.or(profile_retry.max_attempts),
+            deadline: retry.deadline.or(profile_retry.deadline),
+            ..profile_retry
+        },
+        None => retry,
+    }
+    .policy()?;
+    let hedge = opts
+        .cfg_override
+        .rpc_hedge
+        .or(profile.hedge_ms)
+        .map(Duration::from_millis);
+    let cassette = match (&opts.cfg_override.record, &opts.cfg_override.replay) {
+        (Some(path), _) => Some(Arc::new(Cassette::record(path)?)),
+        (None, Some(path)) => Some(Arc::new(Cassette::replay(path)?)),
+        (None, None) => None,
+    };
+    let clients = fallbacks
+        .into_iter()
+        .fold(ClientFactory::new(&url), |clients, (url, auth, headers)| {
+            clients.with_fallback(url, auth, headers)
+        })
+        .with_hedging(hedge)
+        .with_cassette(cassette)
+        .with_auth(auth)
+        .with_retry_policy(retry)
+        .with_rpc_headers(rpc_headers)
+        .with_drive_headers(drive_headers)
+        .with_drive_host(opts.cfg_override.drive_host.or(profile.drive_host))
+        .with_storage_server(opts.cfg_override.storage_server.or(profile.storage_server))
+        .with_timeout(timeout)
+        .with_commitment(commitment);
+
+    let confirm = ConfirmPolicy {
+        skip_confirm: opts.cfg_override.skip_confirm,
+        yes_i_am_sure_immutable: opts.cfg_override.yes_i_am_sure_immutable,
+    };
+    let metrics_file = opts.cfg_override.metrics_file;
+    if let Some(path) = &metrics_file {
+        stats::spawn_prometheus_writer(clients.metrics(), path.clone());
+    }
+    let result = opts
+        .command
+        .process(
+            signer,
+            &clients,
+            confirm,
+            genesysgo,
+            opts.cfg_override.output,
+            opts.cfg_override.dry_run,
+        )
+        .await;
+    // Failed runs are the ones whose requests are most worth a look.
+    if opts.cfg_override.stats {
+        eprintln!("{}", stats::summary(&clients.metrics()));
+        if opts.command.uses_sdk_drive_requests() && !opts.cfg_override.dry_run {
+            eprintln!(
+                "Not included: the uploads, edits, deletions and storage account changes \
+                the Shadow Drive SDK sent itself"
+            );
+        }
+    }
+    if let Some(path) = &metrics_file {
+        stats::write_prometheus(&clients.metrics(), path)?;
+    }
+    result
+}
 
-    opts.command
-        .process(signer, &url, opts.cfg_override.skip_confirm, auth)
-        .await?;
+/// Print the completion script for `shell`, generated from the clap definitions,
+/// optionally followed by the dynamic storage account and filename completion.
+fn print_completions(shell: Shell, dynamic: bool) -> anyhow::Result<()> {
+    let mut app = Opts::into_app();
+    clap_complete::generate(shell, &mut app, "shadow-drive-cli", &mut std::io::stdout());
+    if dynamic {
+        match shell {
+            Shell::Bash => println!("{}", BASH_DYNAMIC),
+            Shell::Fish => println!("{}", FISH_DYNAMIC),
+            _ => {
+                return Err(anyhow!(
+                    "dynamic completion is only available for bash and fish, \
+                    zsh users can load the bash script through `bashcompinit`"
+                ))
+            }
+        }
+    }
     Ok(())
 }
@@ -0,0 +1,382 @@
+use anyhow::anyhow;
+use log::debug;
+use serde::{Deserialize, Serialize};
+use shadow_drive_rust::models::storage_acct::StorageAcct;
+use shadow_drive_rust::models::ShadowFile;
+use shadow_drive_rust::{ShadowDriveClient, StorageAccountVersion};
+use solana_sdk::hash::hash;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::Signer;
+use std::collections::{BTreeMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use crate::client::DriveHttpClient;
+use crate::output::{Render, UploadView};
+use crate::storage_accounts::{
+    owned_storage_accounts, storage_account_capacity, storage_account_name,
+};
+use crate::{list_objects, process_shadow_api_response};
+
+/// Default location of the state file for migrating `source`.
+pub fn default_state_file(source: &Pubkey) -> PathBuf {
+    PathBuf::from(format!("migrate-{}.json", source.to_string()))
+}
+
+/// A file which has been copied to the destination account.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CopiedFile {
+    /// SHA-256 of the file contents, as downloaded from the source account.
+    pub hash: String,
+    pub size: usize,
+    /// Set once the destination copy has been downloaded and its hash matched.
+    #[serde(default)]
+    pub verified: bool,
+}
+
+/// A destination account about to be created: its name, and the accounts
+/// which already had that name, so that a resumed run can tell the new one apart.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PendingDestination {
+    pub name: String,
+    #[serde(default)]
+    pub existing: Vec<String>,
+}
+
+/// Progress of a v1 to v2 migration, persisted after every step so that
+/// an interrupted migration can be resumed by running the command again.
+/// This is also the output of `migrate-account`:
+///
+/// ```json
+/// {"source": "<base58>", "destination": "<base58>",
+///  "files": {"a.txt": {"hash": "<base58 sha256>", "size": 12, "verified": true}},
+///  "source_deletion_queued": false}
+/// ```
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MigrationState {
+    pub source: String,
+    pub destination: Option<String>,
+    /// Set while the destination is being created.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub creating: Option<PendingDestination>,
+    #[serde(default)]
+    pub files: BTreeMap<String, CopiedFile>,
+    #[serde(default)]
+    pub source_deletion_queued: bool,
+}
+
+impl MigrationState {
+    pub fn new(source: &Pubkey) -> Self {
+        Self {
+            source: source.to_string(),
+            destination: None,
+            creating: None,
+            files: BTreeMap::new(),
+            source_deletion_queued: false,
+        }
+    }
+
+    /// Load the state for `source` from `path`, or start fresh if it doesn't exist.
+    pub fn load_or_new(path: &Path, source: &Pubkey) -> anyhow::Result<Self> {
+        if !path.exists() {
+            return Ok(Self::new(source));
+        }
+        let state: Self = serde_json::from_str(&fs::read_to_string(path)?)
+            .map_err(|e| anyhow!("invalid migration state file {}: {}", path.display(), e))?;
+        if state.source != source.to_string() {
+            return Err(anyhow!(
+                "state file {} belongs to a migration of {}, not {}",
+                path.display(),
+                state.source,
+                source.to_string()
+            ));
+        }
+        Ok(state)
+    }
+
+    pub fn save(&self, path: &Path) -> anyhow::Result<()> {
+        fs::write(path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+
+    pub fn destination(&self) -> anyhow::Result<Option<Pubkey>> {
+        self.destination
+            .as_ref()
+            .map(|key| {
+                Pubkey::from_str(key).map_err(|e| anyhow!("invalid destination in state: {}", e))
+            })
+            .transpose()
+    }
+
+    pub fn is_fully_verified(&self) -> bool {
+        self.files.values().all(|file| file.verified)
+    }
+}
+
+impl Render for MigrationState {
+    fn text(&self) -> String {
+        let verified = self.files.values().filter(|file| file.verified).count();
+        let mut text = format!(
+            "Migrated {} to {}\nFiles copied: {}, verified: {}",
+            self.source,
+            self.destination.as_deref().unwrap_or("<not created>"),
+            self.files.len(),
+            verified
+        );
+        if self.source_deletion_queued {
+            text = format!("{}\n{} is queued for deletion", text, self.source);
+        }
+        text
+    }
+}
+
+/// Download a file from the Shadow Drive file host.
+pub async fn download_file(
+    http_client: &DriveHttpClient,
+    storage_account: &Pubkey,
+    file: &str,
+) -> anyhow::Result<Vec<u8>> {
+    let location = http_client.file_url(storage_account, file);
+    let resp = http_client
+        .send("GET file", http_client.get(&location))
+        .await?;
+    debug!("GET {}: {}", location, resp.status());
+    let resp = resp.error_for_status()?;
+    Ok(resp.bytes().await?.to_vec())
+}
+
+/// SHA-256 of file contents, in base58.
+pub fn content_hash(bytes: &[u8]) -> String {
+    hash(bytes).to_string()
+}
+
+/// Migrates a v1 storage account to a new v2 account of the same size,
+/// recording progress in the state file at `state_path`.
+pub struct Migration<'a, T: Signer> {
+    pub client: &'a ShadowDriveClient<T>,
+    /// Lists and downloads files on the Shadow Drive server and file host.
+    pub http_client: DriveHttpClient,
+    pub source: Pubkey,
+    pub state_path: PathBuf,
+    pub state: MigrationState,
+}
+
+impl<'a, T: Signer> Migration<'a, T> {
+    pub fn new(
+        client: &'a ShadowDriveClient<T>,
+        http_client: DriveHttpClient,
+        source: Pubkey,
+        state_path: PathBuf,
+    ) -> anyhow::Result<Self> {
+        let state = MigrationState::load_or_new(&state_path, &source)?;
+        Ok(Self {
+            client,
+            http_client,
+            source,
+            state_path,
+            state,
+        })
+    }
+
+    /// Create the v2 destination account, unless a previous run already did.
+    /// The account's name is saved before it is created, so that a run interrupted
+    /// before it could save the new address adopts that account on resume, instead
+    /// of paying for another one.
+    pub async fn create_destination(&mut self, name: Option<&str>) -> anyhow::Result<Pubkey> {
+        if let Some(destination) = self.state.destination()? {
+            return Ok(destination);
+        }
+        let response = self.client.get_storage_account(&self.source).await;
+        let account = process_shadow_api_response(response)?;
+        let owner = match &account {
+            StorageAcct::V1(v1) => v1.owner_1,
+            StorageAcct::V2(_) => {
+                return Err(anyhow!(
+                    "{} is already a v2 storage account",
+                    self.source.to_string()
+                ))
+            }
+        };
+        let created = match self.state.creating.clone() {
+            Some(pending) => self.find_created(&owner, &pending).await?,
+            None => None,
+        };
+        let destination = match created {
+            Some(destination) => {
+                eprintln!(
+                    "Found {}, created by an interrupted run",
+                    destination.to_string()
+                );
+                destination
+            }
+            None => {
+                let name = match &self.state.creating {
+                    Some(pending) => pending.name.clone(),
+                    None => name
+                        .unwrap_or_else(|| storage_account_name(&account))
+                        .to_string(),
+                };
+                let existing = self.named_v2_accounts(&owner, &name).await?;
+                self.state.creating = Some(PendingDestination {
+                    name: name.clone(),
+                    existing: existing.iter().map(|key| key.to_string()).collect(),
+                });
+                self.state.save(&self.state_path)?;
+                let response = self
+                    .client
+                    .create_storage_account(
+                        &name,
+                        storage_account_capacity(&account),
+                        StorageAccountVersion::v2(),
+                    )
+                    .await;
+                let resp = process_shadow_api_response(response)?;
+                let destination = resp
+                    .shdw_bucket
+                    .ok_or_else(|| anyhow!("storage account creation did not return an address"))?;
+                Pubkey::from_str(&destination)
+                    .map_err(|e| anyhow!("invalid address of the new storage account: {}", e))?
+            }
+        };
+        self.state.destination = Some(destination.to_string());
+        self.state.creating = None;
+        self.state.save(&self.state_path)?;
+        Ok(destination)
+    }
+
+    /// The v2 storage accounts of `owner` named `name`.
+    async fn named_v2_accounts(&self, owner: &Pubkey, name: &str) -> anyhow::Result<Vec<Pubkey>> {
+        let accounts = owned_storage_accounts(self.client, owner, &Default::default()).await?;
+        Ok(accounts
+            .into_iter()
+            .filter(|(_, account)| {
+                matches!(account, StorageAcct::V2(_)) && storage_account_name(account) == name
+            })
+            .map(|(key, _)| key)
+            .collect())
+    }
+
+    /// The account an interrupted run created for `pending`, if it got that far:
+    /// the one account with its name which did not exist before.
+    async fn find_created(
+        &self,
+        owner: &Pubkey,
+        pending: &PendingDestination,
+    ) -> anyhow::Result<Option<Pubkey>> {
+        let mut created: Vec<Pubkey> = self
+            .named_v2_accounts(owner, &pending.name)
+            .await?
+            .into_iter()
+            .filter(|key| !pending.existing.contains(&key.to_string()))
+            .collect();
+        match created.len() {
+            0 | 1 => Ok(created.pop()),
+            _ => Err(anyhow!(
+                "several new storage accounts are named {:?}, set \"destination\" in {} \
+                to the one to migrate to",
+                pending.name,
+                self.state_path.display()
+            )),
+        }
+    }
+
+    /// Copy every file not yet recorded in the state over to `destination`.
+    /// Files which are already there, e.g. uploaded by a run which stopped before
+    /// it could save its progress, are compared against the source instead of
+    /// being uploaded again, and replaced if they differ.
+    pub async fn copy_files(&mut self, destination: &Pubkey) -> anyhow::Result<()> {
+        let files = list_objects(&self.http_client, &self.source).await?;
+        let present: HashSet<String> = list_objects(&self.http_client, destination)
+            .await?
+            .into_iter()
+            .collect();
+        for file in files {
+            if self.state.files.contains_key(&file) {
+                continue;
+            }
+            let bytes = download_file(&self.http_client, &self.source, &file).await?;
+            let mut copied = CopiedFile {
+                hash: content_hash(&bytes),
+                size: bytes.len(),
+                verified: false,
+            };
+            if present.contains(&file) {
+                let existing = download_file(&self.http_client, destination, &file).await?;
+                if existing.len() == copied.size && content_hash(&existing) == copied.hash {
+                    eprintln!("{} was already copied", file);
+                    copied.verified = true;
+                } else {
+                    eprintln!("Replacing {}", file);
+                    let response = self
+                        .client
+                        .edit_file(destination, ShadowFile::bytes(file.clone(), bytes))
+                        .await;
+                    process_shadow_api_response(response)?;
+                }
+            } else {
+                eprintln!("Copying {}", file);
+                let response = self
+                    .client
+                    .store_files(destination, vec![ShadowFile::bytes(file.clone(), bytes)])
+                    .await;
+                let resp = process_shadow_api_response(response)?;
+                let mut view = UploadView::new(*destination);
+                view.record_response(&[file.clone()], &resp);
+                if let Some(err) = view.failure() {
+                    return Err(err.into());
+                }
+            }
+            self.state.files.insert(file, copied);
+            self.state.save(&self.state_path)?;
+        }
+        Ok(())
+    }
+
+    /// Download every copied file from `destination` and compare it against
+    /// the hash taken from the source. Returns the names of files that are
+    /// missing or differ. Those are forgotten, so that the next run copies
+    /// them again, or replaces them if they are there but differ.
+    pub async fn verify(&mut self, destination: &Pubkey) -> anyhow::Result<Vec<String>> {
+        let present = list_objects(&self.http_client, destination).await?;
+        let mut mismatched = vec![];
+        let names: Vec<String> = self.state.files.keys().cloned().collect();
+        for name in names {
+            if self.state.files[&name].verified {
+                continue;
+            }
+            let matches = present.contains(&name) && {
+                let bytes = download_file(&self.http_client, destination, &name).await?;
+                let expected = &self.state.files[&name];
+                bytes.len() == expected.size && content_hash(&bytes) == expected.hash
+            };
+            if matches {
+                if let Some(file) = self.state.files.get_mut(&name) {
+                    file.verified = true;
+                }
+            } else {
+                self.state.files.remove(&name);
+                mismatched.push(name);
+            }
+            self.state.save(&self.state_path)?;
+        }
+        Ok(mismatched)
+    }
+
+    /// Queue the source account for deletion once every copy is verified.
+    pub async fn delete_source(&mut self) -> anyhow::Result<()> {
+        if self.state.source_deletion_queued {
+            return Ok(());
+        }
+        if !self.state.is_fully_verified() {
+            return Err(anyhow!(
+                "refusing to delete {}: not every file has been verified",
+                self.source.to_string()
+            ));
+        }
+        let response = self.client.delete_storage_account(&self.source).await;
+        process_shadow_api_response(response)?;
+        self.state.source_deletion_queued = true;
+        self.state.save(&self.state_path)
+    }
+}
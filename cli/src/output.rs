@@ -0,0 +1,842 @@
+//! Stable, machine-readable command output.
+//!
+//! Every command prints exactly one value to stdout, in the format chosen
+//! with `--output`. Progress and informational lines go to stderr, so stdout
+//! can be piped straight into `jq` or a YAML parser. The JSON shape of every
+//! value is documented on its struct below, and in the README.
+use clap::ArgEnum;
+use serde::{Serialize, Serializer};
+use shadow_drive_rust::models::storage_acct::StorageAcct;
+use shadow_drive_rust::models::ShadowUploadResponse;
+use solana_sdk::pubkey::Pubkey;
+use std::fmt::Display;
+
+use crate::error::CliError;
+use crate::storage_accounts::{
+    storage_account_capacity, storage_account_is_immutable, storage_account_name,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ArgEnum)]
+pub enum OutputFormat {
+    #[default]
+    Text,
+    Json,
+    Yaml,
+    Table,
+}
+
+/// A command result which can be printed in any [OutputFormat].
+pub trait Render: Serialize {
+    /// Human-readable form, used by `--output text`.
+    fn text(&self) -> String;
+
+    /// Columnar form, used by `--output table`. Defaults to the text form.
+    fn table(&self) -> String {
+        self.text()
+    }
+}
+
+/// Print `value` to stdout in the requested format.
+pub fn print_output<R: Render>(format: OutputFormat, value: &R) -> anyhow::Result<()> {
+    match format {
+        OutputFormat::Text => println!("{}", value.text()),
+        OutputFormat::Table => println!("{}", value.table()),
+        OutputFormat::Json => println!("{}", serde_json::to_string_pretty(value)?),
+        OutputFormat::Yaml => print!("{}", serde_yaml::to_string(value)?),
+    }
+    Ok(())
+}
+
+/// Serialize a field through its [Display] impl, e.g. a [Pubkey] as base58.
+pub fn display<T: Display, S: Serializer>(value: &T, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.collect_str(value)
+}
+
+/// Serialize a [byte_unit::Byte] as a plain number of bytes.
+pub fn bytes<S: Serializer>(value: &byte_unit::Byte, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_u64(value.get_bytes() as u64)
+}
+
+/// Lay out `rows` as whitespace-aligned columns under `headers`.
+pub fn table(headers: &[&str], rows: Vec<Vec<String>>) -> String {
+    let mut widths: Vec<usize> = headers.iter().map(|h| h.len()).collect();
+    for row in &rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.len());
+        }
+    }
+    let line = |cells: Vec<String>| {
+        cells
+            .iter()
+            .zip(&widths)
+            .map(|(cell, width)| format!("{:<width$}", cell, width = *width))
+            .collect::<Vec<_>>()
+            .join("  ")
+            .trim_end()
+            .to_string()
+    };
+    let mut lines = vec![line(headers.iter().map(|h| h.to_string()).collect())];
+    lines.extend(rows.into_iter().map(line));
+    lines.join("\n")
+}
+
+/// A storage account.
+///
+/// ```json
+/// {"address": "<base58>", "name": "...", "version": "v2", "owner": "<base58>",
+///  "capacity_bytes": 1000000, "immutable": false, "to_be_deleted": false}
+/// ```
+#[derive(Debug, Clone, Serialize)]
+pub struct StorageAccountView {
+    #[serde(serialize_with = "display")]
+    pub address: Pubkey,
+    pub name: String,
+    pub version: &'static str,
+    #[serde(serialize_with = "display")]
+    pub owner: Pubkey,
+    pub capacity_bytes: u64,
+    pub immutable: bool,
+    pub to_be_deleted: bool,
+}
+
+impl StorageAccountView {
+    pub fn new(address: Pubkey, account: &StorageAcct) -> Self {
+        let (version, owner, to_be_deleted) = match account {
+            StorageAcct::V1(v1) => ("v1", v1.owner_1, v1.to_be_deleted),
+            StorageAcct::V2(v2) => ("v2", v2.owner_1, v2.to_be_deleted),
+        };
+        Self {
+            address,
+            name: storage_account_name(account).to_string(),
+            version,
+            owner,
+            capacity_bytes: storage_account_capacity(account).get_bytes() as u64,
+            immutable: storage_account_is_immutable(account),
+            to_be_deleted,
+        }
+    }
+
+    fn row(&self) -> Vec<String> {
+        vec![
+            self.address.to_string(),
+            self.name.clone(),
+            self.version.to_string(),
+            byte_unit::Byte::from_bytes(self.capacity_bytes as u128)
+                .get_appropriate_unit(false)
+                .to_string(),
+            self.immutable.to_string(),
+            self.to_be_deleted.to_string(),
+        ]
+    }
+}
+
+const STORAGE_ACCOUNT_HEADERS: [&str; 6] = [
+    "ADDRESS",
+    "NAME",
+    "VERSION",
+    "CAPACITY",
+    "IMMUTABLE",
+    "TO BE DELETED",
+];
+
+impl Render for StorageAccountView {
+    fn text(&self) -> String {
+        format!(
+            "Storage Account {}\n  Name:          {}\n  Version:       {}\n  Owner:         {}\n  \
+            Capacity:      {}\n  Immutable:     {}\n  To Be Deleted: {}",
+            self.address.to_string(),
+            self.name,
+            self.version,
+            self.owner.to_string(),
+            byte_unit::Byte::from_bytes(self.capacity_bytes as u128).get_appropriate_unit(false),
+            self.immutable,
+            self.to_be_deleted
+        )
+    }
+
+    fn table(&self) -> String {
+        table(&STORAGE_ACCOUNT_HEADERS, vec![self.row()])
+    }
+}
+
+/// A list of storage accounts, serialized as a JSON array of [StorageAccountView].
+#[derive(Debug, Clone, Serialize)]
+#[serde(transparent)]
+pub struct StorageAccountListView(pub Vec<StorageAccountView>);
+
+impl Render for StorageAccountListView {
+    fn text(&self) -> String {
+        self.0
+            .iter()
+            .map(|account| account.text())
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    fn table(&self) -> String {
+        table(
+            &STORAGE_ACCOUNT_HEADERS,
+            self.0.iter().map(|account| account.row()).collect(),
+        )
+    }
+}
+
+/// How much of a storage account's capacity is in use.
+///
+/// ```json
+/// {"storage_account": "<base58>", "name": "...", "capacity_bytes": 1000000,
+///  "used_bytes": 250000, "free_bytes": 750000}
+/// ```
+#[derive(Debug, Clone, Serialize)]
+pub struct StorageUsageView {
+    #[serde(serialize_with = "display")]
+    pub storage_account: Pubkey,
+    pub name: String,
+    pub capacity_bytes: u64,
+    pub used_bytes: u64,
+    pub free_bytes: u64,
+}
+
+impl StorageUsageView {
+    /// The usage of `account`, which holds `used_bytes` of files.
+    pub fn new(storage_account: Pubkey, account: &StorageAcct, used_bytes: u64) -> Self {
+        let capacity_bytes = storage_account_capacity(account).get_bytes() as u64;
+        Self {
+            storage_account,
+            name: storage_account_name(account).to_string(),
+            capacity_bytes,
+            used_bytes,
+            free_bytes: capacity_bytes.saturating_sub(used_bytes),
+        }
+    }
+
+    fn percent_used(&self) -> f64 {
+        if self.capacity_bytes == 0 {
+            return 0.0;
+        }
+        self.used_bytes as f64 * 100.0 / self.capacity_bytes as f64
+    }
+}
+
+impl Render for StorageUsageView {
+    fn text(&self) -> String {
+        let size = |bytes: u64| {
+            byte_unit::Byte::from_bytes(bytes as u128).get_appropriate_unit(false)
+        };
+        format!(
+            "Storage Account {} ({})\n  Used: {} of {} ({:.1}%)\n  Free: {}",
+            self.storage_account.to_string(),
+            self.name,
+            size(self.used_bytes),
+            size(self.capacity_bytes),
+            self.percent_used(),
+            size(self.free_bytes)
+        )
+    }
+}
+
+/// Files held by a storage account.
+///
+/// ```json
+/// {"storage_account": "<base58>", "files": ["a.txt", "b.png"]}
+/// ```
+#[derive(Debug, Clone, Serialize)]
+pub struct FileListView {
+    #[serde(serialize_with = "display")]
+    pub storage_account: Pubkey,
+    pub files: Vec<String>,
+    /// Where the files are hosted, for their URLs in the table.
+    #[serde(skip)]
+    pub drive_host: String,
+}
+
+impl Render for FileListView {
+    fn text(&self) -> String {
+        self.files.join("\n")
+    }
+
+    fn table(&self) -> String {
+        table(
+            &["FILE", "URL"],
+            self.files
+                .iter()
+                .map(|file| {
+                    vec![
+                        file.clone(),
+                        crate::drive_url(&self.drive_host, &self.storage_account, file),
+                    ]
+                })
+                .collect(),
+        )
+    }
+}
+
+/// A freshly acquired RPC auth token.
+///
+/// ```json
+/// {"token": "<jwt>"}
+/// ```
+#[derive(Debug, Clone, Serialize)]
+pub struct AuthTokenView {
+    pub token: String,
+}
+
+impl Render for AuthTokenView {
+    fn text(&self) -> String {
+        self.token.clone()
+    }
+}
+
+/// The outcome of an on-chain operation.
+///
+/// ```json
+/// {"operation": "add-storage", "storage_account": "<base58>",
+///  "signature": "<base58>", "message": null}
+/// ```
+/// `storage_account` is `null` when the operation doesn't target one,
+/// and `message` carries any note returned by the Shadow Drive server.
+#[derive(Debug, Clone, Serialize)]
+pub struct TransactionView {
+    pub operation: String,
+    pub storage_account: Option<String>,
+    pub signature: String,
+    pub message: Option<String>,
+}
+
+impl TransactionView {
+    pub fn new<S: ToString>(
+        operation: &str,
+        storage_account: Option<&Pubkey>,
+        signature: S,
+        message: Option<String>,
+    ) -> Self {
+        Self {
+            operation: operation.to_string(),
+            storage_account: storage_account.map(|key| key.to_string()),
+            signature: signature.to_string(),
+            message,
+        }
+    }
+}
+
+impl Render for TransactionView {
+    fn text(&self) -> String {
+        let mut text = format!("{}: {}", self.operation, self.signature);
+        if let Some(storage_account) = &self.storage_account {
+            text = format!("{}\nStorage Account: {}", text, storage_account);
+        }
+        if let Some(message) = &self.message {
+            text = format!("{}\n{}", text, message);
+        }
+        text
+    }
+}
+
+/// Files written to a storage account by `store-files` or `edit-file`, and
+/// those the server refused.
+///
+/// ```json
+/// {"storage_account": "<base58>", "files": [{"file": "./a.txt", "url": "https://..."}],
+///  "failed": [{"file": "./b.txt", "error": "file already exists"}]}
+/// ```
+#[derive(Debug, Clone, Serialize)]
+pub struct UploadView {
+    #[serde(serialize_with = "display")]
+    pub storage_account: Pubkey,
+    pub files: Vec<UploadedFileView>,
+    pub failed: Vec<FailedUploadView>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct UploadedFileView {
+    /// Local path that was uploaded.
+    pub file: String,
+    /// Where the file is now hosted.
+    pub url: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct FailedUploadView {
+    /// Local path that was not uploaded.
+    pub file: String,
+    /// Why, as reported by the Shadow Drive server.
+    pub error: String,
+}
+
+impl UploadView {
+    pub fn new(storage_account: Pubkey) -> Self {
+        Self {
+            storage_account,
+            files: vec![],
+            failed: vec![],
+        }
+    }
+
+    /// Add the server's response to one `store_files` request for the local `paths`.
+    pub fn record_response(&mut self, paths: &[String], response: &ShadowUploadResponse) {
+        let errors: Vec<(String, String)> = response
+            .upload_errors
+            .iter()
+            .map(|err| (err.file.clone(), err.error.clone()))
+            .collect();
+        self.record_batch(paths, &response.finalized_locations, &errors);
+    }
+
+    /// Add the outcome of one `store_files` request for the local `paths`: each
+    /// file is matched by name to one of the server's finalized `locations`, or
+    /// else to its upload `errors`, given as filename and message.
+    pub fn record_batch(
+        &mut self,
+        paths: &[String],
+        locations: &[String],
+        errors: &[(String, String)],
+    ) {
+        for path in paths {
+            let name = crate::acquire_basename(path);
+            let suffixes = [format!("/{}", name), format!("/{}", name.replace(' ', "%20"))];
+            let stored = |url: &&String| suffixes.iter().any(|suffix| url.ends_with(suffix));
+            if let Some(url) = locations.iter().find(stored) {
+                self.files.push(UploadedFileView {
+                    file: path.clone(),
+                    url: url.clone(),
+                });
+                continue;
+            }
+            let error = errors
+                .iter()
+                .find(|(file, _)| *file == name)
+                .map(|(_, error)| error.clone())
+                .unwrap_or_else(|| "the server did not report this file as stored".to_string());
+            self.failed.push(FailedUploadView {
+                file: path.clone(),
+                error,
+            });
+        }
+    }
+
+    /// The error to exit with if any file failed, classified by the first
+    /// failure, e.g. [CliError::FileAlreadyExists].
+    pub fn failure(&self) -> Option<CliError> {
+        let first = self.failed.first()?;
+        Some(CliError::from_server(
+            400,
+            format!(
+                "{} of {} files were not uploaded, {}: {}",
+                self.failed.len(),
+                self.failed.len() + self.files.len(),
+                first.file,
+                first.error
+            ),
+        ))
+    }
+}
+
+impl Render for UploadView {
+    fn text(&self) -> String {
+        self.files
+            .iter()
+            .map(|file| file.url.clone())
+            .chain(
+                self.failed
+                    .iter()
+                    .map(|failed| format!("FAILED {}: {}", failed.file, failed.error)),
+            )
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    fn table(&self) -> String {
+        table(
+            &["FILE", "URL"],
+            self.files
+                .iter()
+                .map(|file| vec![file.file.clone(), file.url.clone()])
+                .chain(self.failed.iter().map(|failed| {
+                    vec![failed.file.clone(), format!("FAILED: {}", failed.error)]
+                }))
+                .collect(),
+        )
+    }
+}
+
+/// A file removed from a storage account.
+///
+/// ```json
+/// {"storage_account": "<base58>", "url": "https://...", "message": "..."}
+/// ```
+#[derive(Debug, Clone, Serialize)]
+pub struct DeletedFileView {
+    #[serde(serialize_with = "display")]
+    pub storage_account: Pubkey,
+    pub url: String,
+    pub message: String,
+}
+
+impl Render for DeletedFileView {
+    fn text(&self) -> String {
+        format!("Deleted {}\n{}", self.url, self.message)
+    }
+}
+
+/// The contents of a text file.
+///
+/// ```json
+/// {"url": "https://...", "last_modified": "...", "text": "..."}
+/// ```
+#[derive(Debug, Clone, Serialize)]
+pub struct TextFileView {
+    pub url: String,
+    pub last_modified: String,
+    pub text: String,
+}
+
+impl Render for TextFileView {
+    fn text(&self) -> String {
+        self.text.clone()
+    }
+}
+
+/// Any JSON value passed through as-is, e.g. file object data returned by
+/// the Shadow Drive server.
+#[derive(Debug, Clone, Serialize)]
+#[serde(transparent)]
+pub struct JsonView(pub serde_json::Value);
+
+impl Render for JsonView {
+    fn text(&self) -> String {
+        serde_json::to_string_pretty(&self.0).unwrap_or_default()
+    }
+}
+
+/// A short message with no further structure.
+///
+/// ```json
+/// {"message": "..."}
+/// ```
+#[derive(Debug, Clone, Serialize)]
+pub struct MessageView {
+    pub message: String,
+}
+
+impl MessageView {
+    pub fn new<S: ToString>(message: S) -> Self {
+        Self {
+            message: message.to_string(),
+        }
+    }
+}
+
+impl Render for MessageView {
+    fn text(&self) -> String {
+        self.message.clone()
+    }
+}
+
+/// The JSON shape of every command's output, as documented in the README.
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::declarative::{AccountRef, Change, Plan};
+    use crate::dry_run::{DryRunView, SimulationReport, TouchedAccount};
+    use crate::migration::{CopiedFile, MigrationState};
+    use crate::storage_accounts::{BatchFailure, BatchSuccess, BatchView};
+    use crate::wallet::{OperationCost, WalletBalances};
+    use byte_unit::Byte;
+    use serde_json::{json, Value};
+    use std::path::PathBuf;
+
+    fn to_json<R: Render>(value: &R) -> Value {
+        serde_json::to_value(value).unwrap()
+    }
+
+    #[test]
+    fn storage_account_shapes() {
+        let (address, owner) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let account = StorageAccountView {
+            address,
+            name: "photos".to_string(),
+            version: "v2",
+            owner,
+            capacity_bytes: 1_000_000,
+            immutable: false,
+            to_be_deleted: true,
+        };
+        let expected = json!({
+            "address": address.to_string(),
+            "name": "photos",
+            "version": "v2",
+            "owner": owner.to_string(),
+            "capacity_bytes": 1_000_000,
+            "immutable": false,
+            "to_be_deleted": true,
+        });
+        assert_eq!(to_json(&account), expected);
+        assert_eq!(
+            to_json(&StorageAccountListView(vec![account])),
+            json!([expected])
+        );
+
+        let usage = StorageUsageView {
+            storage_account: address,
+            name: "photos".to_string(),
+            capacity_bytes: 1_000_000,
+            used_bytes: 250_000,
+            free_bytes: 750_000,
+        };
+        assert_eq!(
+            to_json(&usage),
+            json!({
+                "storage_account": address.to_string(),
+                "name": "photos",
+                "capacity_bytes": 1_000_000,
+                "used_bytes": 250_000,
+                "free_bytes": 750_000,
+            })
+        );
+    }
+
+    #[test]
+    fn file_shapes() {
+        let storage_account = Pubkey::new_unique();
+        let list = FileListView {
+            storage_account,
+            files: vec!["a.txt".to_string()],
+            drive_host: crate::GENESYSGO_DRIVE.to_string(),
+        };
+        assert_eq!(
+            to_json(&list),
+            json!({ "storage_account": storage_account.to_string(), "files": ["a.txt"] })
+        );
+
+        let mut upload = UploadView::new(storage_account);
+        upload.record_batch(
+            &["./a.txt".to_string(), "docs/b.txt".to_string()],
+            &["https://host/acct/a.txt".to_string()],
+            &[("b.txt".to_string(), "file already exists".to_string())],
+        );
+        assert_eq!(
+            to_json(&upload),
+            json!({
+                "storage_account": storage_account.to_string(),
+                "files": [{ "file": "./a.txt", "url": "https://host/acct/a.txt" }],
+                "failed": [{ "file": "docs/b.txt", "error": "file already exists" }],
+            })
+        );
+        assert_eq!(upload.failure().unwrap().exit_code(), 7);
+        upload.failed.clear();
+        assert!(upload.failure().is_none());
+
+        let deleted = DeletedFileView {
+            storage_account,
+            url: "https://host/a.txt".to_string(),
+            message: "deleted".to_string(),
+        };
+        assert_eq!(
+            to_json(&deleted),
+            json!({
+                "storage_account": storage_account.to_string(),
+                "url": "https://host/a.txt",
+                "message": "deleted",
+            })
+        );
+
+        let text = TextFileView {
+            url: "https://host/a.txt".to_string(),
+            last_modified: "yesterday".to_string(),
+            text: "hello".to_string(),
+        };
+        assert_eq!(
+            to_json(&text),
+            json!({ "url": "https://host/a.txt", "last_modified": "yesterday", "text": "hello" })
+        );
+
+        let object_data = json!({ "file_data": { "size": 5 } });
+        assert_eq!(to_json(&JsonView(object_data.clone())), object_data);
+    }
+
+    #[test]
+    fn transaction_and_message_shapes() {
+        let storage_account = Pubkey::new_unique();
+        let transaction =
+            TransactionView::new("add-storage", Some(&storage_account), "sig", None);
+        assert_eq!(
+            to_json(&transaction),
+            json!({
+                "operation": "add-storage",
+                "storage_account": storage_account.to_string(),
+                "signature": "sig",
+                "message": null,
+            })
+        );
+        let transaction =
+            TransactionView::new("claim-stake", None, "sig", Some("note".to_string()));
+        assert_eq!(to_json(&transaction)["storage_account"], Value::Null);
+        assert_eq!(to_json(&transaction)["message"], json!("note"));
+
+        let token = AuthTokenView {
+            token: "jwt".to_string(),
+        };
+        assert_eq!(to_json(&token), json!({ "token": "jwt" }));
+        assert_eq!(to_json(&MessageView::new("done")), json!({ "message": "done" }));
+    }
+
+    #[test]
+    fn wallet_shapes() {
+        let (owner, shdw_token_account) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let balances = WalletBalances {
+            owner,
+            lamports: 5,
+            shdw_token_account,
+            shades: None,
+        };
+        assert_eq!(
+            to_json(&balances),
+            json!({
+                "owner": owner.to_string(),
+                "lamports": 5,
+                "shdw_token_account": shdw_token_account.to_string(),
+                "shades": null,
+            })
+        );
+        assert_eq!(
+            to_json(&OperationCost::fee_only()),
+            json!({ "lamports": 10_000, "shades": 0 })
+        );
+    }
+
+    #[test]
+    fn batch_shape() {
+        let (succeeded, failed) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let batch = BatchView {
+            succeeded: vec![BatchSuccess {
+                storage_account: succeeded,
+                result: MessageView::new("done"),
+            }],
+            failed: vec![BatchFailure {
+                storage_account: failed,
+                error: "out of space".to_string(),
+            }],
+        };
+        assert_eq!(
+            to_json(&batch),
+            json!({
+                "succeeded": [{
+                    "storage_account": succeeded.to_string(),
+                    "result": { "message": "done" },
+                }],
+                "failed": [{ "storage_account": failed.to_string(), "error": "out of space" }],
+            })
+        );
+    }
+
+    #[test]
+    fn migration_state_round_trips() {
+        let source = Pubkey::new_unique();
+        let mut state = MigrationState::new(&source);
+        state.destination = Some(Pubkey::new_unique().to_string());
+        state.files.insert(
+            "a.txt".to_string(),
+            CopiedFile {
+                hash: "hash".to_string(),
+                size: 12,
+                verified: true,
+            },
+        );
+        let json = to_json(&state);
+        assert_eq!(
+            json,
+            json!({
+                "source": source.to_string(),
+                "destination": state.destination,
+                "files": { "a.txt": { "hash": "hash", "size": 12, "verified": true } },
+                "source_deletion_queued": false,
+            })
+        );
+        let parsed: MigrationState = serde_json::from_value(json.clone()).unwrap();
+        assert_eq!(to_json(&parsed), json);
+    }
+
+    #[test]
+    fn plan_shape() {
+        let account = Pubkey::new_unique();
+        let plan = Plan {
+            changes: vec![
+                Change::CreateAccount {
+                    name: "photos".to_string(),
+                    size: Byte::from_bytes(1_000_000),
+                },
+                Change::AddStorage {
+                    account,
+                    size: Byte::from_bytes(1_000),
+                },
+                Change::UploadFiles {
+                    account: AccountRef::New("photos".to_string()),
+                    files: vec![PathBuf::from("photos/a.png")],
+                },
+                Change::EditFiles {
+                    account,
+                    files: vec![PathBuf::from("docs/b.txt")],
+                },
+                Change::DeleteFiles {
+                    account,
+                    files: vec!["c.txt".to_string()],
+                },
+                Change::MakeImmutable {
+                    account: AccountRef::Existing(account),
+                },
+            ],
+        };
+        let account = account.to_string();
+        assert_eq!(
+            to_json(&plan),
+            json!({ "changes": [
+                { "action": "create_account", "name": "photos", "size": 1_000_000 },
+                { "action": "add_storage", "account": account, "size": 1_000 },
+                {
+                    "action": "upload_files",
+                    "account": "<new account photos>",
+                    "files": ["photos/a.png"],
+                },
+                { "action": "edit_files", "account": account, "files": ["docs/b.txt"] },
+                { "action": "delete_files", "account": account, "files": ["c.txt"] },
+                { "action": "make_immutable", "account": account },
+            ]})
+        );
+    }
+
+    #[test]
+    fn dry_run_shape() {
+        let address = Pubkey::new_unique();
+        let view = DryRunView {
+            operation: "claim-stake".to_string(),
+            estimated_cost: Some(OperationCost::fee_only()),
+            simulations: vec![SimulationReport {
+                accounts: vec![TouchedAccount {
+                    address,
+                    signer: true,
+                    writable: true,
+                }],
+                fee_lamports: Some(5_000),
+                units_consumed: None,
+                error: None,
+                logs: vec!["Program log: ok".to_string()],
+            }],
+            note: None,
+        };
+        assert_eq!(
+            to_json(&view),
+            json!({
+                "operation": "claim-stake",
+                "estimated_cost": { "lamports": 10_000, "shades": 0 },
+                "simulations": [{
+                    "accounts": [{ "address": address.to_string(), "signer": true, "writable": true }],
+                    "fee_lamports": 5_000,
+                    "units_consumed": null,
+                    "error": null,
+                    "logs": ["Program log: ok"],
+                }],
+                "note": null,
+            })
+        );
+    }
+}
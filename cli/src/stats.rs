@@ -0,0 +1,133 @@
+//! `--stats` and `--metrics-file`: what the run's clients recorded in their
+//! [TransportMetrics], see [crate::client::ClientFactory::metrics].
+use byte_unit::Byte;
+use log::warn;
+use shadow_rpc_auth::metrics::MethodStats;
+use shadow_rpc_auth::TransportMetrics;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::output::table;
+
+/// How often `--metrics-file` is rewritten during a run.
+pub const METRICS_INTERVAL: Duration = Duration::from_secs(15);
+
+fn bytes(bytes: u64) -> String {
+    Byte::from_bytes(bytes as u128)
+        .get_appropriate_unit(false)
+        .to_string()
+}
+
+fn millis(duration: Duration) -> String {
+    format!("{}ms", duration.as_millis())
+}
+
+/// A table of requests by transport and method, and the totals.
+pub fn summary(metrics: &TransportMetrics) -> String {
+    let stats = metrics.snapshot();
+    if stats.is_empty() {
+        return "No requests sent".to_string();
+    }
+    let rows = stats
+        .iter()
+        .map(|stat| {
+            vec![
+                stat.transport.to_string(),
+                stat.method.clone(),
+                stat.requests.to_string(),
+                stat.errors.to_string(),
+                stat.retries.to_string(),
+                millis(stat.p50),
+                millis(stat.p95),
+                bytes(stat.bytes_sent),
+                bytes(stat.bytes_received),
+            ]
+        })
+        .collect();
+    let total = |value: fn(&MethodStats) -> u64| stats.iter().map(value).sum::<u64>();
+    let rate_limited: Duration = stats.iter().map(|stat| stat.rate_limited).sum();
+    format!(
+        "{}\nTotal: {} requests, {} errors, {} retries, {} sent, {} received, {:.1}s rate limited",
+        table(
+            &[
+                "TRANSPORT",
+                "METHOD",
+                "REQUESTS",
+                "ERRORS",
+                "RETRIES",
+                "P50",
+                "P95",
+                "SENT",
+                "RECEIVED",
+            ],
+            rows,
+        ),
+        total(|stat| stat.requests),
+        total(|stat| stat.errors),
+        total(|stat| stat.retries),
+        bytes(total(|stat| stat.bytes_sent)),
+        bytes(total(|stat| stat.bytes_received)),
+        rate_limited.as_secs_f64(),
+    )
+}
+
+/// Replace `path` with the metrics in the Prometheus text format. The file is
+/// renamed into place, so that a collector never reads it half-written.
+pub fn write_prometheus(metrics: &TransportMetrics, path: &Path) -> anyhow::Result<()> {
+    let mut partial = path.as_os_str().to_owned();
+    partial.push(".partial");
+    fs::write(&partial, metrics.to_prometheus())?;
+    fs::rename(&partial, path)?;
+    Ok(())
+}
+
+/// Rewrite `path` every [METRICS_INTERVAL] until the run ends.
+pub fn spawn_prometheus_writer(metrics: Arc<TransportMetrics>, path: PathBuf) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(METRICS_INTERVAL);
+        loop {
+            interval.tick().await;
+            if let Err(err) = write_prometheus(&metrics, &path) {
+                warn!("Unable to write metrics to {}: {}", path.display(), err);
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use shadow_rpc_auth::metrics::{RequestRecord, RPC};
+
+    #[test]
+    fn summarizes_requests() {
+        let metrics = TransportMetrics::new();
+        assert_eq!(summary(&metrics), "No requests sent");
+        metrics.record(
+            RPC,
+            "getBalance",
+            RequestRecord {
+                elapsed: Duration::from_millis(120),
+                bytes_received: 2048,
+                retries: 1,
+                ..Default::default()
+            },
+        );
+        let text = summary(&metrics);
+        assert!(text.starts_with("TRANSPORT  METHOD      REQUESTS"));
+        assert!(text.contains("rpc        getBalance  1         0       1        120ms"));
+        assert!(text.ends_with(
+            "1 requests, 0 errors, 1 retries, 0 B sent, 2.05 KB received, 0.0s rate limited"
+        ));
+
+        let path = std::env::temp_dir().join(format!("shdw-metrics-{}.prom", std::process::id()));
+        write_prometheus(&metrics, &path).unwrap();
+        let text = fs::read_to_string(&path).unwrap();
+        assert!(text.contains(
+            "shadow_drive_request_retries_total{transport=\"rpc\",method=\"getBalance\"} 1"
+        ));
+        fs::remove_file(path).unwrap();
+    }
+}
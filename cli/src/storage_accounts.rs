@@ -0,0 +1,222 @@
+use byte_unit::Byte;
+use regex::Regex;
+use shadow_drive_rust::derived_addresses;
+use shadow_drive_rust::models::storage_acct::StorageAcct;
+use shadow_drive_rust::ShadowDriveClient;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::Signer;
+use serde::Serialize;
+
+use crate::client::DriveHttpClient;
+use crate::error::CliError;
+use crate::output::{display, print_output, OutputFormat, Render};
+use crate::{list_objects, process_shadow_api_response};
+
+/// Address of a storage account, derived from its owner and counter seed.
+pub fn storage_account_key(account: &StorageAcct) -> Pubkey {
+    let (owner, seed) = match account {
+        StorageAcct::V1(v1) => (v1.owner_1, v1.account_counter_seed),
+        StorageAcct::V2(v2) => (v2.owner_1, v2.account_counter_seed),
+    };
+    derived_addresses::storage_account(&owner, seed).0
+}
+
+/// The user-supplied name of a storage account.
+pub fn storage_account_name(account: &StorageAcct) -> &str {
+    match account {
+        StorageAcct::V1(v1) => &v1.identifier,
+        StorageAcct::V2(v2) => &v2.identifier,
+    }
+}
+
+pub fn storage_account_is_immutable(account: &StorageAcct) -> bool {
+    match account {
+        StorageAcct::V1(v1) => v1.immutable,
+        StorageAcct::V2(v2) => v2.immutable,
+    }
+}
+
+/// Total reserved capacity of a storage account.
+pub fn storage_account_capacity(account: &StorageAcct) -> Byte {
+    match account {
+        StorageAcct::V1(v1) => Byte::from_bytes(v1.storage as u128),
+        StorageAcct::V2(v2) => Byte::from_bytes(v2.storage as u128),
+    }
+}
+
+/// What a user should see about a storage account before changing it irreversibly.
+#[derive(Debug, Clone)]
+pub struct StorageAccountDetails {
+    pub key: Pubkey,
+    pub name: String,
+    pub capacity: Byte,
+    pub immutable: bool,
+    pub file_count: usize,
+}
+
+/// Fetch a storage account along with the number of files it holds.
+pub async fn storage_account_details<T: Signer>(
+    client: &ShadowDriveClient<T>,
+    http_client: &DriveHttpClient,
+    storage_account: &Pubkey,
+) -> anyhow::Result<StorageAccountDetails> {
+    let response = client.get_storage_account(storage_account).await;
+    let account = process_shadow_api_response(response)?;
+    let files = list_objects(http_client, storage_account).await?;
+    Ok(StorageAccountDetails {
+        key: *storage_account,
+        name: storage_account_name(&account).to_string(),
+        capacity: storage_account_capacity(&account),
+        immutable: storage_account_is_immutable(&account),
+        file_count: files.len(),
+    })
+}
+
+/// Narrows down a list of storage accounts by name and/or mutability.
+#[derive(Debug, Default)]
+pub struct StorageAccountFilter {
+    pub name: Option<Regex>,
+    pub immutable: Option<bool>,
+}
+
+impl StorageAccountFilter {
+    pub fn matches(&self, account: &StorageAcct) -> bool {
+        if let Some(name) = &self.name {
+            if !name.is_match(storage_account_name(account)) {
+                return false;
+            }
+        }
+        if let Some(immutable) = self.immutable {
+            if storage_account_is_immutable(account) != immutable {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+/// Fetch every storage account owned by `owner` which passes the `filter`,
+/// paired with its address.
+pub async fn owned_storage_accounts<T: Signer>(
+    client: &ShadowDriveClient<T>,
+    owner: &Pubkey,
+    filter: &StorageAccountFilter,
+) -> anyhow::Result<Vec<(Pubkey, StorageAcct)>> {
+    let response = client.get_storage_accounts(owner).await;
+    let accounts = process_shadow_api_response(response)?;
+    Ok(accounts
+        .into_iter()
+        .filter(|account| filter.matches(account))
+        .map(|account| (storage_account_key(&account), account))
+        .collect())
+}
+
+/// Collected outcomes of running one operation against many storage accounts.
+#[derive(Debug)]
+pub struct BatchSummary<T> {
+    pub succeeded: Vec<(Pubkey, T)>,
+    pub failed: Vec<(Pubkey, anyhow::Error)>,
+}
+
+impl<T> Default for BatchSummary<T> {
+    fn default() -> Self {
+        Self {
+            succeeded: vec![],
+            failed: vec![],
+        }
+    }
+}
+
+/// Output of a [BatchSummary].
+///
+/// ```json
+/// {"succeeded": [{"storage_account": "<base58>", "result": ...}],
+///  "failed": [{"storage_account": "<base58>", "error": "..."}]}
+/// ```
+#[derive(Debug, Serialize)]
+pub struct BatchView<T> {
+    pub succeeded: Vec<BatchSuccess<T>>,
+    pub failed: Vec<BatchFailure>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct BatchSuccess<T> {
+    #[serde(serialize_with = "display")]
+    pub storage_account: Pubkey,
+    pub result: T,
+}
+
+#[derive(Debug, Serialize)]
+pub struct BatchFailure {
+    #[serde(serialize_with = "display")]
+    pub storage_account: Pubkey,
+    pub error: String,
+}
+
+impl<T: Render> Render for BatchView<T> {
+    fn text(&self) -> String {
+        let mut lines = vec![];
+        for success in &self.succeeded {
+            lines.push(format!(
+                "{}:\n{}",
+                success.storage_account.to_string(),
+                success.result.text()
+            ));
+        }
+        for failure in &self.failed {
+            lines.push(format!(
+                "{}: FAILED: {}",
+                failure.storage_account.to_string(),
+                failure.error
+            ));
+        }
+        lines.push(format!(
+            "{} succeeded, {} failed",
+            self.succeeded.len(),
+            self.failed.len()
+        ));
+        lines.join("\n")
+    }
+}
+
+impl<T: Render> BatchSummary<T> {
+    pub fn record(&mut self, storage_account: Pubkey, result: anyhow::Result<T>) {
+        match result {
+            Ok(value) => self.succeeded.push((storage_account, value)),
+            Err(err) => self.failed.push((storage_account, err)),
+        }
+    }
+
+    /// Print per-account results followed by a success/failure tally.
+    /// Errors if any account failed, so the exit status reflects the batch.
+    pub fn finish(self, format: OutputFormat) -> anyhow::Result<()> {
+        let total = self.succeeded.len() + self.failed.len();
+        let view = BatchView {
+            succeeded: self
+                .succeeded
+                .into_iter()
+                .map(|(storage_account, result)| BatchSuccess {
+                    storage_account,
+                    result,
+                })
+                .collect(),
+            failed: self
+                .failed
+                .into_iter()
+                .map(|(storage_account, err)| BatchFailure {
+                    storage_account,
+                    error: err.to_string(),
+                })
+                .collect(),
+        };
+        print_output(format, &view)?;
+        if !view.failed.is_empty() {
+            return Err(CliError::BatchFailed {
+                failed: view.failed.len(),
+                total,
+            }
+            .into());
+        }
+        Ok(())
+    }
+}
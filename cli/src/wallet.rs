@@ -0,0 +1,268 @@
+use anyhow::anyhow;
+use byte_unit::Byte;
+use shadow_drive_rust::derived_addresses;
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_sdk::native_token::lamports_to_sol;
+use solana_sdk::pubkey;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::{Signature, Signer};
+use solana_sdk::transaction::Transaction;
+use spl_associated_token_account::{
+    get_associated_token_address, instruction::create_associated_token_account,
+};
+use serde::Serialize;
+use std::fmt::{Display, Formatter};
+
+use crate::error::CliError;
+use crate::output::{display, Render};
+
+/// Mint of the SHDW token, used to pay for storage.
+pub const SHDW_MINT: Pubkey = pubkey!("SHDWyBxihqiCj6YekG2GUr7wqKLeLAMK1gHZck9pL6y");
+
+/// SHDW has 9 decimals, the smallest unit is called a "shade".
+pub const SHADES_PER_SHDW: u64 = 1_000_000_000;
+
+/// Base fee for a transaction with up to two signatures,
+/// which covers every Shadow Drive instruction.
+pub const TRANSACTION_FEE_LAMPORTS: u64 = 10_000;
+
+/// Upper bound on the space of the accounts created alongside a new
+/// storage account, used to estimate rent.
+pub const NEW_STORAGE_ACCOUNT_SPACE: usize = 512;
+
+pub fn shades_to_shdw(shades: u64) -> f64 {
+    shades as f64 / SHADES_PER_SHDW as f64
+}
+
+/// SOL and SHDW held by a wallet.
+///
+/// ```json
+/// {"owner": "<base58>", "lamports": 1000000, "shdw_token_account": "<base58>",
+///  "shades": 250000000}
+/// ```
+/// `shades` is `null` when the SHDW token account does not exist.
+#[derive(Debug, Clone, Serialize)]
+pub struct WalletBalances {
+    #[serde(serialize_with = "display")]
+    pub owner: Pubkey,
+    pub lamports: u64,
+    #[serde(serialize_with = "display")]
+    pub shdw_token_account: Pubkey,
+    /// `None` when the SHDW associated token account does not exist yet.
+    pub shades: Option<u64>,
+}
+
+impl Display for WalletBalances {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "Wallet: {}", self.owner.to_string())?;
+        writeln!(f, "SOL:    {}", lamports_to_sol(self.lamports))?;
+        match self.shades {
+            Some(shades) => write!(
+                f,
+                "SHDW:   {} (token account {})",
+                shades_to_shdw(shades),
+                self.shdw_token_account.to_string()
+            ),
+            None => write!(
+                f,
+                "SHDW:   no token account, create one with `wallet create-shdw-account`"
+            ),
+        }
+    }
+}
+
+/// Whether `account` exists. Failures to find out, such as timeouts or
+/// rate limiting, are errors rather than a missing account.
+async fn account_exists(rpc: &RpcClient, account: &Pubkey) -> anyhow::Result<bool> {
+    let response = rpc
+        .get_account_with_commitment(account, rpc.commitment())
+        .await?;
+    Ok(response.value.is_some())
+}
+
+pub async fn wallet_balances(rpc: &RpcClient, owner: &Pubkey) -> anyhow::Result<WalletBalances> {
+    let lamports = rpc.get_balance(owner).await?;
+    let shdw_token_account = get_associated_token_address(owner, &SHDW_MINT);
+    let shades = if !account_exists(rpc, &shdw_token_account).await? {
+        None
+    } else {
+        let amount = rpc.get_token_account_balance(&shdw_token_account).await?;
+        Some(amount.amount.parse::<u64>()?)
+    };
+    Ok(WalletBalances {
+        owner: *owner,
+        lamports,
+        shdw_token_account,
+        shades,
+    })
+}
+
+/// Create the signer's SHDW associated token account if it doesn't exist.
+/// Returns the transaction signature, or `None` if the account already existed.
+pub async fn create_shdw_token_account<T: Signer>(
+    rpc: &RpcClient,
+    signer: &T,
+) -> anyhow::Result<Option<Signature>> {
+    let owner = signer.pubkey();
+    let token_account = get_associated_token_address(&owner, &SHDW_MINT);
+    if account_exists(rpc, &token_account).await? {
+        return Ok(None);
+    }
+    let ix = create_associated_token_account(&owner, &owner, &SHDW_MINT);
+    let blockhash = rpc.get_latest_blockhash().await?;
+    let txn = Transaction::new_signed_with_payer(&[ix], Some(&owner), &[signer], blockhash);
+    let signature = rpc.send_and_confirm_transaction(&txn).await?;
+    Ok(Some(signature))
+}
+
+/// Current price of storage, read from the Shadow Drive storage config account.
+pub async fn shades_per_gib(rpc: &RpcClient) -> anyhow::Result<u64> {
+    let (storage_config, _) = derived_addresses::storage_config_pda();
+    let data = rpc.get_account_data(&storage_config).await?;
+    // `shades_per_gib` is the first field after the 8-byte Anchor discriminator.
+    let bytes = data
+        .get(8..16)
+        .ok_or_else(|| anyhow!("unexpected storage config account layout"))?;
+    Ok(u64::from_le_bytes(bytes.try_into()?))
+}
+
+/// Estimated price in shades of reserving `size` of storage.
+pub fn storage_cost(size: &Byte, shades_per_gib: u64) -> u64 {
+    let gib = 1u128 << 30;
+    ((size.get_bytes() * shades_per_gib as u128 + gib - 1) / gib) as u64
+}
+
+/// What an operation is expected to cost the signer.
+///
+/// ```json
+/// {"lamports": 10000, "shades": 250000000}
+/// ```
+#[derive(Debug, Clone, Copy, Default, Serialize)]
+pub struct OperationCost {
+    pub lamports: u64,
+    pub shades: u64,
+}
+
+impl OperationCost {
+    /// Cost of a transaction which only pays network fees.
+    pub fn fee_only() -> Self {
+        Self {
+            lamports: TRANSACTION_FEE_LAMPORTS,
+            shades: 0,
+        }
+    }
+
+    /// Cost of reserving `size` of storage, either in a new storage account
+    /// or added to an existing one.
+    pub async fn storage(
+        rpc: &RpcClient,
+        size: &Byte,
+        new_account: bool,
+    ) -> anyhow::Result<Self> {
+        let mut lamports = TRANSACTION_FEE_LAMPORTS;
+        if new_account {
+            lamports += rpc
+                .get_minimum_balance_for_rent_exemption(NEW_STORAGE_ACCOUNT_SPACE)
+                .await?;
+        }
+        Ok(Self {
+            lamports,
+            shades: storage_cost(size, shades_per_gib(rpc).await?),
+        })
+    }
+}
+
+impl Render for OperationCost {
+    fn text(&self) -> String {
+        self.to_string()
+    }
+}
+
+impl Render for WalletBalances {
+    fn text(&self) -> String {
+        self.to_string()
+    }
+}
+
+impl Display for OperationCost {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "~{} SOL and ~{} SHDW",
+            lamports_to_sol(self.lamports),
+            shades_to_shdw(self.shades)
+        )
+    }
+}
+
+/// Check up front that `owner` can afford `cost`, so the operation isn't
+/// attempted only to fail on-chain.
+pub async fn ensure_affordable(
+    rpc: &RpcClient,
+    owner: &Pubkey,
+    cost: &OperationCost,
+) -> anyhow::Result<WalletBalances> {
+    let balances = wallet_balances(rpc, owner).await?;
+    if balances.lamports < cost.lamports {
+        return Err(CliError::InsufficientFunds(format!(
+            "operation needs {} but {} holds {} SOL",
+            cost,
+            owner.to_string(),
+            lamports_to_sol(balances.lamports)
+        ))
+        .into());
+    }
+    if cost.shades > 0 {
+        match balances.shades {
+            None => {
+                return Err(CliError::InsufficientFunds(format!(
+                    "{} has no SHDW token account",
+                    owner.to_string()
+                ))
+                .into())
+            }
+            Some(shades) if shades < cost.shades => {
+                return Err(CliError::InsufficientFunds(format!(
+                    "operation needs {} but {} holds {} SHDW",
+                    cost,
+                    owner.to_string(),
+                    shades_to_shdw(shades)
+                ))
+                .into())
+            }
+            Some(_) => {}
+        }
+    }
+    Ok(balances)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use shadow_drive_test_support::MockServer;
+
+    #[tokio::test]
+    async fn tells_missing_token_accounts_from_failed_lookups() {
+        let missing = MockServer::rpc(|method, _| match method {
+            "getBalance" => Ok(json!({ "context": { "slot": 1 }, "value": 5 })),
+            "getAccountInfo" => Ok(json!({ "context": { "slot": 1 }, "value": null })),
+            _ => Err((-32601, "Method not found".to_string())),
+        })
+        .unwrap();
+        let owner = Pubkey::new_unique();
+        let rpc = RpcClient::new(missing.url().to_string());
+        let balances = wallet_balances(&rpc, &owner).await.unwrap();
+        assert_eq!((balances.lamports, balances.shades), (5, None));
+
+        let rate_limited = MockServer::rpc(|method, _| match method {
+            "getBalance" => Ok(json!({ "context": { "slot": 1 }, "value": 5 })),
+            _ => Err((429, "Too many requests".to_string())),
+        })
+        .unwrap();
+        let rpc = RpcClient::new(rate_limited.url().to_string());
+        assert!(wallet_balances(&rpc, &owner).await.is_err());
+        let signer = solana_sdk::signature::Keypair::new();
+        assert!(create_shdw_token_account(&rpc, &signer).await.is_err());
+    }
+}
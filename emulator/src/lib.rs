@@ -0,0 +1,451 @@
+//! A local stand-in for the Shadow Drive server and file host, for development
+//! and integration tests without mainnet. It serves:
+//!
+//! - `POST /upload` and `POST /edit`, multipart forms with `storage_account`
+//!   (and for edits, the `url` of the file) and the files as `file` fields.
+//! - `POST /delete-file`, `/list-objects`, `/list-objects-and-sizes`,
+//!   `/storage-account-size` and `/get-object-data`, with JSON bodies.
+//! - `GET` and `HEAD /<storage account>/<file>`, the hosted files.
+//!
+//! The CLI reaches the listing, metadata and file endpoints through `--drive-host`
+//! and `--storage-server`, so its read-only commands can be tested end to end here.
+//! Its uploads, edits and deletions go through the Shadow Drive SDK, which only
+//! talks to the real server; testing those commands against the emulator is out of
+//! scope until the SDK can be pointed elsewhere. The write endpoints serve other
+//! clients and this crate's tests.
+//!
+//! Files are kept per storage account, in memory or on disk, see [Store]. Signatures
+//! are not checked and storage accounts need not exist on chain, so any signer can
+//! write to any account. Storage account transactions, which the real server signs
+//! and submits, are rejected; run those against `solana-test-validator` or a mock RPC.
+pub mod multipart;
+pub mod store;
+
+pub use store::Store;
+
+use anyhow::anyhow;
+use hyper::header::{HeaderValue, CONTENT_LENGTH, CONTENT_TYPE, LAST_MODIFIED};
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Method, Request, Response, Server, StatusCode};
+use log::{debug, error, info};
+use serde_json::{json, Value};
+use std::convert::Infallible;
+use std::net::{SocketAddr, TcpListener};
+use std::sync::Arc;
+
+/// Address the emulator listens on by default.
+pub const DEFAULT_ADDR: &str = "127.0.0.1:8910";
+
+/// Endpoints which submit storage account transactions on the real server.
+pub const TRANSACTION_ENDPOINTS: [&str; 4] = [
+    "/storage-account",
+    "/add-storage",
+    "/reduce-storage",
+    "/make-immutable",
+];
+
+struct Emulator {
+    store: Store,
+    /// Where files are hosted, e.g. `http://127.0.0.1:8910`.
+    url: String,
+}
+
+/// Serve `store` on `addr` in the background, returning the URL of the emulator,
+/// which is both its server and file host. Port 0 picks a free port, e.g. for tests.
+/// Must be called within a Tokio runtime.
+pub fn spawn(addr: SocketAddr, store: Store) -> anyhow::Result<String> {
+    let listener =
+        TcpListener::bind(addr).map_err(|e| anyhow!("unable to listen on {}: {}", addr, e))?;
+    let url = format!("http://{}", listener.local_addr()?);
+    let emulator = Arc::new(Emulator {
+        store,
+        url: url.clone(),
+    });
+    let make_service = make_service_fn(move |_| {
+        let emulator = emulator.clone();
+        async move { Ok::<_, Infallible>(service_fn(move |request| handle(emulator.clone(), request))) }
+    });
+    let server = Server::from_tcp(listener)?.serve(make_service);
+    tokio::spawn(async move {
+        if let Err(err) = server.await {
+            error!("Shadow Drive emulator stopped: {}", err);
+        }
+    });
+    info!("Shadow Drive emulator listening on {}", url);
+    Ok(url)
+}
+
+async fn handle(
+    emulator: Arc<Emulator>,
+    request: Request<Body>,
+) -> Result<Response<Body>, Infallible> {
+    let (method, path) = (request.method().clone(), request.uri().path().to_string());
+    let response = emulator
+        .route(request)
+        .await
+        .unwrap_or_else(|err| error_response(StatusCode::BAD_REQUEST, &format!("{:#}", err)));
+    debug!("{} {}: {}", method, path, response.status());
+    Ok(response)
+}
+
+fn json_response(status: StatusCode, body: Value) -> Response<Body> {
+    let mut response = Response::new(Body::from(body.to_string()));
+    *response.status_mut() = status;
+    response
+        .headers_mut()
+        .insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+    response
+}
+
+fn error_response(status: StatusCode, message: &str) -> Response<Body> {
+    json_response(status, json!({ "error": message }))
+}
+
+/// The Content-Type a file is hosted with, by its extension.
+fn content_type(name: &str) -> &'static str {
+    let extension = name
+        .rsplit_once('.')
+        .map(|(_, ext)| ext.to_ascii_lowercase());
+    match extension.as_deref() {
+        Some("txt") => "text/plain",
+        Some("json") => "application/json",
+        Some("html" | "htm") => "text/html",
+        Some("css") => "text/css",
+        Some("js") => "text/javascript",
+        Some("png") => "image/png",
+        Some("jpg" | "jpeg") => "image/jpeg",
+        Some("gif") => "image/gif",
+        Some("svg") => "image/svg+xml",
+        Some("pdf") => "application/pdf",
+        _ => "application/octet-stream",
+    }
+}
+
+/// The storage account and filename of a file's URL, on any host.
+fn parse_location(location: &str) -> anyhow::Result<(&str, &str)> {
+    let mut segments = location.rsplitn(3, '/');
+    match (segments.next(), segments.next()) {
+        (Some(name), Some(account)) if !name.is_empty() && !account.is_empty() => {
+            Ok((account, name))
+        }
+        _ => Err(anyhow!("invalid file location: {}", location)),
+    }
+}
+
+/// The storage account of a JSON request, under either of the names the API uses.
+fn account_field(body: &Value) -> anyhow::Result<&str> {
+    body.get("storageAccount")
+        .or_else(|| body.get("storage_account"))
+        .and_then(Value::as_str)
+        .ok_or_else(|| anyhow!("missing storage account"))
+}
+
+/// `path` with `%XX` escapes decoded, e.g. the spaces in filenames.
+fn percent_decode(path: &str) -> anyhow::Result<String> {
+    let mut bytes = Vec::with_capacity(path.len());
+    let mut rest = path.as_bytes();
+    while let Some((&byte, tail)) = rest.split_first() {
+        if byte == b'%' && tail.len() >= 2 {
+            let hex = std::str::from_utf8(&tail[..2])?;
+            bytes.push(u8::from_str_radix(hex, 16)?);
+            rest = &tail[2..];
+        } else {
+            bytes.push(byte);
+            rest = tail;
+        }
+    }
+    Ok(String::from_utf8(bytes)?)
+}
+
+fn string_field<'a>(body: &'a Value, field: &str) -> anyhow::Result<&'a str> {
+    body.get(field)
+        .and_then(Value::as_str)
+        .ok_or_else(|| anyhow!("missing {}", field))
+}
+
+impl Emulator {
+    fn location(&self, account: &str, name: &str) -> String {
+        format!("{}/{}/{}", self.url, account, name)
+    }
+
+    async fn route(&self, request: Request<Body>) -> anyhow::Result<Response<Body>> {
+        let (parts, body) = request.into_parts();
+        let body = hyper::body::to_bytes(body).await?;
+        let path = parts.uri.path();
+        if parts.method == Method::POST && TRANSACTION_ENDPOINTS.contains(&path) {
+            return Ok(error_response(
+                StatusCode::NOT_IMPLEMENTED,
+                "storage account transactions are not emulated",
+            ));
+        }
+        match (&parts.method, path) {
+            (&Method::POST, "/upload" | "/edit") => {
+                let content_type = parts
+                    .headers
+                    .get(CONTENT_TYPE)
+                    .and_then(|value| value.to_str().ok())
+                    .unwrap_or_default();
+                let boundary = multipart::boundary(content_type)
+                    .ok_or_else(|| anyhow!("expected a multipart/form-data body"))?;
+                let form = multipart::parse(&body, boundary)?;
+                if path == "/upload" {
+                    self.upload(form)
+                } else {
+                    self.edit(form)
+                }
+            }
+            (&Method::POST, _) => {
+                let body: Value = serde_json::from_slice(&body)
+                    .map_err(|e| anyhow!("expected a JSON body: {}", e))?;
+                match path {
+                    "/delete-file" => self.delete_file(&body),
+                    "/list-objects" => {
+                        let files = self.store.list(account_field(&body)?)?;
+                        let keys: Vec<String> = files.into_iter().map(|(name, _)| name).collect();
+                        Ok(json_response(StatusCode::OK, json!({ "keys": keys })))
+                    }
+                    "/list-objects-and-sizes" => {
+                        let files = self.store.list(account_field(&body)?)?;
+                        let files: Vec<Value> = files
+                            .into_iter()
+                            .map(|(name, size)| json!({ "file_name": name, "size": size }))
+                            .collect();
+                        Ok(json_response(StatusCode::OK, json!({ "files": files })))
+                    }
+                    "/storage-account-size" => {
+                        let files = self.store.list(account_field(&body)?)?;
+                        let used: u64 = files.iter().map(|(_, size)| size).sum();
+                        Ok(json_response(
+                            StatusCode::OK,
+                            json!({ "storage_used": used }),
+                        ))
+                    }
+                    "/get-object-data" => self.object_data(string_field(&body, "location")?),
+                    _ => Ok(error_response(StatusCode::NOT_FOUND, "no such endpoint")),
+                }
+            }
+            (&Method::GET | &Method::HEAD, _) => {
+                self.hosted_file(path.trim_start_matches('/'), parts.method == Method::HEAD)
+            }
+            _ => Ok(error_response(
+                StatusCode::METHOD_NOT_ALLOWED,
+                "method not allowed",
+            )),
+        }
+    }
+
+    /// Store new files. Files which already exist are left as they are and
+    /// reported in `upload_errors`, as they have to be edited instead.
+    fn upload(&self, form: Vec<multipart::Part>) -> anyhow::Result<Response<Body>> {
+        let account = form
+            .iter()
+            .find(|part| part.name == "storage_account")
+            .ok_or_else(|| anyhow!("missing storage_account"))?
+            .text()?;
+        let mut locations = Vec::new();
+        let mut errors = Vec::new();
+        for file in form.iter().filter(|part| part.name == "file") {
+            let name = file
+                .filename
+                .as_deref()
+                .ok_or_else(|| anyhow!("file field without a filename"))?;
+            if self.store.get(account, name)?.is_some() {
+                errors.push(json!({
+                    "file": name,
+                    "storage_account": account,
+                    "error": "file already exists",
+                }));
+                continue;
+            }
+            self.store.put(account, name, file.data.clone())?;
+            locations.push(self.location(account, name));
+        }
+        if locations.is_empty() && errors.is_empty() {
+            return Err(anyhow!("no files to upload"));
+        }
+        Ok(json_response(
+            StatusCode::OK,
+            json!({
+                "finalized_locations": locations,
+                "message": format!("{} file(s) uploaded", locations.len()),
+                "upload_errors": errors,
+            }),
+        ))
+    }
+
+    /// Replace the file at `url`, which must exist.
+    fn edit(&self, form: Vec<multipart::Part>) -> anyhow::Result<Response<Body>> {
+        let field = |name: &str| {
+            form.iter()
+                .find(|part| part.name == name)
+                .ok_or_else(|| anyhow!("missing {}", name))
+        };
+        let (account, name) = parse_location(field("url")?.text()?)?;
+        if field("storage_account")?.text()? != account {
+            return Err(anyhow!("the file is not in storage account {}", account));
+        }
+        if self.store.get(account, name)?.is_none() {
+            return Ok(error_response(StatusCode::NOT_FOUND, "file not found"));
+        }
+        self.store.put(account, name, field("file")?.data.clone())?;
+        Ok(json_response(
+            StatusCode::OK,
+            json!({ "finalized_location": self.location(account, name), "error": null }),
+        ))
+    }
+
+    fn delete_file(&self, body: &Value) -> anyhow::Result<Response<Body>> {
+        let (account, name) = parse_location(string_field(body, "location")?)?;
+        if !self.store.delete(account, name)? {
+            return Ok(error_response(StatusCode::NOT_FOUND, "file not found"));
+        }
+        Ok(json_response(
+            StatusCode::OK,
+            json!({ "message": "File deleted", "error": null }),
+        ))
+    }
+
+    fn object_data(&self, location: &str) -> anyhow::Result<Response<Body>> {
+        let (account, name) = parse_location(location)?;
+        let file = match self.store.get(account, name)? {
+            Some(file) => file,
+            None => return Ok(error_response(StatusCode::NOT_FOUND, "file not found")),
+        };
+        Ok(json_response(
+            StatusCode::OK,
+            json!({
+                "file_data": {
+                    "file-name": name,
+                    "storage-account": account,
+                    "size": file.data.len(),
+                    "content-type": content_type(name),
+                    "last-modified": httpdate::fmt_http_date(file.modified),
+                }
+            }),
+        ))
+    }
+
+    fn hosted_file(&self, path: &str, head: bool) -> anyhow::Result<Response<Body>> {
+        let path = percent_decode(path)?;
+        let file = match path.split_once('/') {
+            Some((account, name)) => self.store.get(account, name)?,
+            None => None,
+        };
+        let file = match file {
+            Some(file) => file,
+            None => return Ok(error_response(StatusCode::NOT_FOUND, "file not found")),
+        };
+        let length = file.data.len();
+        let mut response = Response::new(if head {
+            Body::empty()
+        } else {
+            Body::from(file.data)
+        });
+        let headers = response.headers_mut();
+        headers.insert(CONTENT_TYPE, HeaderValue::from_static(content_type(&path)));
+        headers.insert(CONTENT_LENGTH, HeaderValue::from(length));
+        headers.insert(
+            LAST_MODIFIED,
+            HeaderValue::from_str(&httpdate::fmt_http_date(file.modified))?,
+        );
+        Ok(response)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ACCOUNT: &str = "11111111111111111111111111111111";
+
+    fn form(fields: &[(&str, Option<&str>, &str)]) -> (String, String) {
+        let mut body = String::new();
+        for (name, filename, value) in fields {
+            body.push_str("--boundary\r\nContent-Disposition: form-data; name=\"");
+            body.push_str(name);
+            if let Some(filename) = filename {
+                body.push_str("\"; filename=\"");
+                body.push_str(filename);
+            }
+            body.push_str("\"\r\n\r\n");
+            body.push_str(value);
+            body.push_str("\r\n");
+        }
+        body.push_str("--boundary--\r\n");
+        ("multipart/form-data; boundary=boundary".to_string(), body)
+    }
+
+    async fn json_body(
+        response: impl std::future::Future<Output = reqwest::Result<reqwest::Response>>,
+    ) -> Value {
+        let text = response.await.unwrap().text().await.unwrap();
+        serde_json::from_str(&text).unwrap()
+    }
+
+    #[tokio::test]
+    async fn serves_files() {
+        let url = spawn("127.0.0.1:0".parse().unwrap(), Store::in_memory()).unwrap();
+        let client = reqwest::Client::new();
+        let post_form = |path: &str, fields: &[(&str, Option<&str>, &str)]| {
+            let (content_type, body) = form(fields);
+            client
+                .post(format!("{}/{}", url, path))
+                .header("content-type", content_type)
+                .body(body)
+                .send()
+        };
+        let post_json = |path: &str, body: Value| {
+            client
+                .post(format!("{}/{}", url, path))
+                .body(body.to_string())
+                .send()
+        };
+
+        let upload = [
+            ("storage_account", None, ACCOUNT),
+            ("file", Some("a file.txt"), "hello"),
+        ];
+        let response = json_body(post_form("upload", &upload)).await;
+        let location = format!("{}/{}/a file.txt", url, ACCOUNT);
+        assert_eq!(response["finalized_locations"], json!([location]));
+        let response = json_body(post_form("upload", &upload)).await;
+        assert_eq!(response["upload_errors"][0]["file"], "a file.txt");
+
+        let hosted = client.get(&location).send().await.unwrap();
+        assert_eq!(hosted.headers()[CONTENT_TYPE], "text/plain");
+        assert!(hosted.headers().contains_key(LAST_MODIFIED));
+        assert_eq!(hosted.text().await.unwrap(), "hello");
+        let head = client.head(&location).send().await.unwrap();
+        assert_eq!(head.headers()[CONTENT_LENGTH], "5");
+
+        let edit = [
+            ("storage_account", None, ACCOUNT),
+            ("url", None, location.as_str()),
+            ("file", Some("a file.txt"), "edited"),
+        ];
+        let edited = post_form("edit", &edit);
+        assert!(edited.await.unwrap().status().is_success());
+        let data = json_body(post_json(
+            "get-object-data",
+            json!({ "location": location }),
+        ))
+        .await;
+        assert_eq!(data["file_data"]["size"], 6);
+        let keys = json_body(post_json(
+            "list-objects",
+            json!({ "storageAccount": ACCOUNT }),
+        ))
+        .await;
+        assert_eq!(keys, json!({ "keys": ["a file.txt"] }));
+
+        let delete = post_json("delete-file", json!({ "location": location }));
+        assert!(delete.await.unwrap().status().is_success());
+        let gone = client.get(&location).send().await.unwrap();
+        assert_eq!(gone.status(), StatusCode::NOT_FOUND);
+        let transaction = post_json("make-immutable", json!({ "transaction": "" }));
+        assert_eq!(
+            transaction.await.unwrap().status(),
+            StatusCode::NOT_IMPLEMENTED
+        );
+    }
+}
@@ -0,0 +1,32 @@
+use clap::Parser;
+use shadow_drive_emulator::{Store, DEFAULT_ADDR};
+use std::net::SocketAddr;
+use std::path::PathBuf;
+
+/// Serve the Shadow Drive upload, edit, delete and listing endpoints and the
+/// hosted files locally. Point the CLI at it with `--drive-host` and
+/// `--storage-server`, and at `solana-test-validator` with `--url`.
+#[derive(Debug, Parser)]
+#[clap(name = "shadow-drive-emulator", version)]
+struct Opts {
+    /// Address to listen on.
+    #[clap(long, default_value = DEFAULT_ADDR)]
+    bind: SocketAddr,
+    /// Keep files in this directory, so that they outlast the emulator.
+    /// Files are kept in memory otherwise.
+    #[clap(long, value_name = "DIR")]
+    data_dir: Option<PathBuf>,
+}
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info")).init();
+    let opts = Opts::parse();
+    let store = match &opts.data_dir {
+        Some(dir) => Store::on_disk(dir)?,
+        None => Store::in_memory(),
+    };
+    shadow_drive_emulator::spawn(opts.bind, store)?;
+    tokio::signal::ctrl_c().await?;
+    Ok(())
+}
@@ -0,0 +1,117 @@
+//! Just enough `multipart/form-data` parsing for the upload and edit forms.
+use anyhow::anyhow;
+
+/// A field of a form, with the filename of file fields.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Part {
+    pub name: String,
+    pub filename: Option<String>,
+    pub data: Vec<u8>,
+}
+
+impl Part {
+    /// The field's value, for text fields.
+    pub fn text(&self) -> anyhow::Result<&str> {
+        std::str::from_utf8(&self.data).map_err(|_| anyhow!("field {} is not UTF-8", self.name))
+    }
+}
+
+/// The boundary of a `multipart/form-data` Content-Type.
+pub fn boundary(content_type: &str) -> Option<&str> {
+    let (mime, params) = content_type.split_once(';')?;
+    if !mime.trim().eq_ignore_ascii_case("multipart/form-data") {
+        return None;
+    }
+    params.split(';').find_map(|param| {
+        let (key, value) = param.split_once('=')?;
+        key.trim()
+            .eq_ignore_ascii_case("boundary")
+            .then(|| value.trim().trim_matches('"'))
+            .filter(|boundary| !boundary.is_empty())
+    })
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
+
+/// `name` and `filename` of a `Content-Disposition: form-data` header value.
+fn disposition(value: &str) -> (Option<String>, Option<String>) {
+    let (mut name, mut filename) = (None, None);
+    for param in value.split(';').skip(1) {
+        if let Some((key, value)) = param.split_once('=') {
+            let value = value.trim().trim_matches('"').to_string();
+            match key.trim() {
+                "name" => name = Some(value),
+                "filename" => filename = Some(value),
+                _ => {}
+            }
+        }
+    }
+    (name, filename)
+}
+
+/// The parts of a form body delimited by `boundary`.
+pub fn parse(body: &[u8], boundary: &str) -> anyhow::Result<Vec<Part>> {
+    let delimiter = format!("--{}", boundary).into_bytes();
+    let next_delimiter = format!("\r\n--{}", boundary).into_bytes();
+    let start = find(body, &delimiter).ok_or_else(|| anyhow!("multipart boundary not found"))?;
+    let mut rest = &body[start + delimiter.len()..];
+    let mut parts = Vec::new();
+    while !rest.starts_with(b"--") {
+        rest = rest
+            .strip_prefix(b"\r\n")
+            .ok_or_else(|| anyhow!("malformed multipart body"))?;
+        let headers_end =
+            find(rest, b"\r\n\r\n").ok_or_else(|| anyhow!("unterminated multipart headers"))?;
+        let headers = std::str::from_utf8(&rest[..headers_end])?;
+        let (name, filename) = headers
+            .lines()
+            .find_map(|line| {
+                let (header, value) = line.split_once(':')?;
+                header
+                    .trim()
+                    .eq_ignore_ascii_case("content-disposition")
+                    .then(|| disposition(value))
+            })
+            .unwrap_or_default();
+        rest = &rest[headers_end + 4..];
+        let end =
+            find(rest, &next_delimiter).ok_or_else(|| anyhow!("unterminated multipart part"))?;
+        parts.push(Part {
+            name: name.ok_or_else(|| anyhow!("multipart part without a name"))?,
+            filename,
+            data: rest[..end].to_vec(),
+        });
+        rest = &rest[end + next_delimiter.len()..];
+    }
+    Ok(parts)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_form() {
+        let content_type = "multipart/form-data; boundary=\"xyz\"";
+        let body = "--xyz\r\n\
+            Content-Disposition: form-data; name=\"storage_account\"\r\n\r\n\
+            abc\r\n\
+            --xyz\r\n\
+            Content-Disposition: form-data; name=\"file\"; filename=\"a.txt\"\r\n\
+            Content-Type: text/plain\r\n\r\n\
+            line 1\r\nline 2\r\n\
+            --xyz--\r\n";
+        let parts = parse(body.as_bytes(), boundary(content_type).unwrap()).unwrap();
+        assert_eq!(parts.len(), 2);
+        assert_eq!(parts[0].text().unwrap(), "abc");
+        assert_eq!(parts[1].filename.as_deref(), Some("a.txt"));
+        assert_eq!(parts[1].data, b"line 1\r\nline 2");
+        assert!(boundary("application/json").is_none());
+        assert!(boundary("multipart/form-data; boundary=").is_none());
+        assert!(parse(b"--xyz\r\nno headers", "xyz").is_err());
+    }
+}
@@ -0,0 +1,189 @@
+//! Files of the emulated storage accounts, kept in memory or in a directory
+//! with one subdirectory per storage account.
+use anyhow::anyhow;
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::SystemTime;
+
+/// A file's contents and when it was last written.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StoredFile {
+    pub data: Vec<u8>,
+    pub modified: SystemTime,
+}
+
+/// Storage accounts by address, each with its files by name.
+pub struct Store {
+    dir: Option<PathBuf>,
+    files: Mutex<BTreeMap<String, BTreeMap<String, StoredFile>>>,
+}
+
+/// Storage account addresses are base58 public keys.
+fn check_account(account: &str) -> anyhow::Result<()> {
+    match bs58::decode(account).into_vec() {
+        Ok(bytes) if bytes.len() == 32 => Ok(()),
+        _ => Err(anyhow!("invalid storage account: {}", account)),
+    }
+}
+
+/// Filenames are a single path segment, so that they can never leave the account.
+fn check_name(name: &str) -> anyhow::Result<()> {
+    if name.is_empty() || name == "." || name == ".." || name.contains(['/', '\\']) {
+        return Err(anyhow!("invalid filename: {:?}", name));
+    }
+    Ok(())
+}
+
+impl Store {
+    /// Files which are gone when the emulator stops.
+    pub fn in_memory() -> Self {
+        Self {
+            dir: None,
+            files: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    /// Files kept in `dir`, which is created if needed, and so kept between runs.
+    pub fn on_disk(dir: &Path) -> anyhow::Result<Self> {
+        fs::create_dir_all(dir)
+            .map_err(|e| anyhow!("unable to create {}: {}", dir.display(), e))?;
+        Ok(Self {
+            dir: Some(dir.to_path_buf()),
+            files: Mutex::new(BTreeMap::new()),
+        })
+    }
+
+    pub fn get(&self, account: &str, name: &str) -> anyhow::Result<Option<StoredFile>> {
+        check_account(account)?;
+        check_name(name)?;
+        let dir = match &self.dir {
+            Some(dir) => dir,
+            None => {
+                let files = self.files.lock().unwrap();
+                return Ok(files
+                    .get(account)
+                    .and_then(|files| files.get(name))
+                    .cloned());
+            }
+        };
+        let path = dir.join(account).join(name);
+        match fs::read(&path) {
+            Ok(data) => Ok(Some(StoredFile {
+                data,
+                modified: fs::metadata(&path)?.modified()?,
+            })),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Write `data` to `name`, replacing any file of that name.
+    pub fn put(&self, account: &str, name: &str, data: Vec<u8>) -> anyhow::Result<()> {
+        check_account(account)?;
+        check_name(name)?;
+        match &self.dir {
+            Some(dir) => {
+                let dir = dir.join(account);
+                fs::create_dir_all(&dir)?;
+                fs::write(dir.join(name), data)?;
+            }
+            None => {
+                let file = StoredFile {
+                    data,
+                    modified: SystemTime::now(),
+                };
+                let mut files = self.files.lock().unwrap();
+                files
+                    .entry(account.to_string())
+                    .or_default()
+                    .insert(name.to_string(), file);
+            }
+        }
+        Ok(())
+    }
+
+    /// Delete `name`, returning whether it existed.
+    pub fn delete(&self, account: &str, name: &str) -> anyhow::Result<bool> {
+        check_account(account)?;
+        check_name(name)?;
+        match &self.dir {
+            Some(dir) => match fs::remove_file(dir.join(account).join(name)) {
+                Ok(()) => Ok(true),
+                Err(e) if e.kind() == ErrorKind::NotFound => Ok(false),
+                Err(e) => Err(e.into()),
+            },
+            None => {
+                let mut files = self.files.lock().unwrap();
+                Ok(files
+                    .get_mut(account)
+                    .and_then(|files| files.remove(name))
+                    .is_some())
+            }
+        }
+    }
+
+    /// The names and sizes of an account's files, by name.
+    pub fn list(&self, account: &str) -> anyhow::Result<Vec<(String, u64)>> {
+        check_account(account)?;
+        let dir = match &self.dir {
+            Some(dir) => dir.join(account),
+            None => {
+                let files = self.files.lock().unwrap();
+                return Ok(files.get(account).map_or_else(Vec::new, |files| {
+                    files
+                        .iter()
+                        .map(|(name, file)| (name.clone(), file.data.len() as u64))
+                        .collect()
+                }));
+            }
+        };
+        let entries = match fs::read_dir(&dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e.into()),
+        };
+        let mut files = Vec::new();
+        for entry in entries {
+            let entry = entry?;
+            let metadata = entry.metadata()?;
+            if metadata.is_file() {
+                files.push((
+                    entry.file_name().to_string_lossy().into_owned(),
+                    metadata.len(),
+                ));
+            }
+        }
+        files.sort();
+        Ok(files)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ACCOUNT: &str = "11111111111111111111111111111111";
+
+    #[test]
+    fn stores_in_memory_and_on_disk() {
+        let dir = std::env::temp_dir().join(format!("shdw-emulator-{}", std::process::id()));
+        for store in [Store::in_memory(), Store::on_disk(&dir).unwrap()] {
+            store.put(ACCOUNT, "b.txt", b"bb".to_vec()).unwrap();
+            store.put(ACCOUNT, "a.txt", b"a".to_vec()).unwrap();
+            assert_eq!(
+                store.list(ACCOUNT).unwrap(),
+                [("a.txt".to_string(), 1), ("b.txt".to_string(), 2)]
+            );
+            assert_eq!(store.get(ACCOUNT, "b.txt").unwrap().unwrap().data, b"bb");
+            assert!(store.delete(ACCOUNT, "b.txt").unwrap());
+            assert!(!store.delete(ACCOUNT, "b.txt").unwrap());
+            assert!(store.get(ACCOUNT, "b.txt").unwrap().is_none());
+            assert!(store.put(ACCOUNT, "../escape", Vec::new()).is_err());
+            assert!(store.list("not-an-account").is_err());
+        }
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
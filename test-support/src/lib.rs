@@ -0,0 +1,231 @@
+//! A scripted HTTP server for tests, standing in for an RPC node, a sign-in portal
+//! or a Shadow Drive server. Every request is answered by a closure and kept, so
+//! that tests can check what clients sent, e.g. their headers.
+//!
+//! Only the workspace's tests depend on this crate, it is never published.
+use anyhow::anyhow;
+use hyper::header::{HeaderName, HeaderValue, CONTENT_TYPE};
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, HeaderMap, Method, Request, Response, Server, StatusCode};
+use log::error;
+use serde_json::{json, Value};
+use std::convert::Infallible;
+use std::net::TcpListener;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// A request a [MockServer] received.
+#[derive(Debug, Clone)]
+pub struct Received {
+    pub method: Method,
+    pub path: String,
+    pub headers: HeaderMap,
+    pub body: Vec<u8>,
+}
+
+impl Received {
+    /// The value of header `name`, if it is present and text.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.get(name).and_then(|value| value.to_str().ok())
+    }
+
+    /// The body as JSON, or null.
+    pub fn json(&self) -> Value {
+        serde_json::from_slice(&self.body).unwrap_or_default()
+    }
+
+    /// The method of a JSON-RPC request, e.g. `getVersion`.
+    pub fn rpc_method(&self) -> String {
+        self.json()["method"]
+            .as_str()
+            .unwrap_or_default()
+            .to_string()
+    }
+}
+
+/// How a [MockServer] answers a request.
+#[derive(Debug, Clone)]
+pub struct MockResponse {
+    pub status: StatusCode,
+    pub headers: Vec<(HeaderName, HeaderValue)>,
+    pub body: Vec<u8>,
+    /// How long to wait before answering.
+    pub delay: Duration,
+}
+
+impl MockResponse {
+    pub fn new(status: u16, body: impl Into<Vec<u8>>) -> Self {
+        Self {
+            status: StatusCode::from_u16(status).expect("valid status"),
+            headers: Vec::new(),
+            body: body.into(),
+            delay: Duration::ZERO,
+        }
+    }
+
+    /// `200 OK` with a JSON body.
+    pub fn json(body: Value) -> Self {
+        Self::new(200, body.to_string()).with_header(CONTENT_TYPE.as_str(), "application/json")
+    }
+
+    /// A successful JSON-RPC response.
+    pub fn rpc_result(result: Value) -> Self {
+        Self::json(json!({ "jsonrpc": "2.0", "result": result, "id": 0 }))
+    }
+
+    /// A JSON-RPC error response.
+    pub fn rpc_error(code: i64, message: &str) -> Self {
+        Self::json(json!({
+            "jsonrpc": "2.0",
+            "error": { "code": code, "message": message },
+            "id": 0,
+        }))
+    }
+
+    pub fn with_header(mut self, name: &str, value: &str) -> Self {
+        self.headers.push((
+            HeaderName::from_bytes(name.as_bytes()).expect("valid header name"),
+            HeaderValue::from_str(value).expect("valid header value"),
+        ));
+        self
+    }
+
+    pub fn with_delay(mut self, delay: Duration) -> Self {
+        self.delay = delay;
+        self
+    }
+}
+
+type Respond = dyn Fn(&Received) -> MockResponse + Send + Sync;
+
+/// A server on a free local port answering with `respond`.
+pub struct MockServer {
+    url: String,
+    received: Arc<Mutex<Vec<Received>>>,
+}
+
+impl MockServer {
+    /// Serve in the background. Must be called within a Tokio runtime.
+    pub fn spawn<F>(respond: F) -> anyhow::Result<Self>
+    where
+        F: Fn(&Received) -> MockResponse + Send + Sync + 'static,
+    {
+        let listener = TcpListener::bind("127.0.0.1:0")
+            .map_err(|e| anyhow!("unable to listen for the mock server: {}", e))?;
+        let url = format!("http://{}", listener.local_addr()?);
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let respond: Arc<Respond> = Arc::new(respond);
+        let log = received.clone();
+        let make_service = make_service_fn(move |_| {
+            let (respond, log) = (respond.clone(), log.clone());
+            async move {
+                Ok::<_, Infallible>(service_fn(move |request| {
+                    handle(respond.clone(), log.clone(), request)
+                }))
+            }
+        });
+        let server = Server::from_tcp(listener)?.serve(make_service);
+        tokio::spawn(async move {
+            if let Err(err) = server.await {
+                error!("mock server stopped: {}", err);
+            }
+        });
+        Ok(Self { url, received })
+    }
+
+    /// Answer every request with `response`.
+    pub fn always(response: MockResponse) -> anyhow::Result<Self> {
+        Self::spawn(move |_| response.clone())
+    }
+
+    /// Answer requests with `responses` in turn, starting over after the last.
+    pub fn sequence(responses: Vec<MockResponse>) -> anyhow::Result<Self> {
+        let next = Mutex::new(responses.into_iter().cycle());
+        Self::spawn(move |_| next.lock().unwrap().next().expect("at least one response"))
+    }
+
+    /// A JSON-RPC node answering each request by its method and params, e.g.
+    /// `Ok(json!("1.14.0"))`, or `Err((code, message))` for a JSON-RPC error.
+    pub fn rpc<F>(respond: F) -> anyhow::Result<Self>
+    where
+        F: Fn(&str, &Value) -> Result<Value, (i64, String)> + Send + Sync + 'static,
+    {
+        Self::spawn(move |request| {
+            let body = request.json();
+            let method = body["method"].as_str().unwrap_or_default();
+            match respond(method, &body["params"]) {
+                Ok(result) => MockResponse::rpc_result(result),
+                Err((code, message)) => MockResponse::rpc_error(code, &message),
+            }
+        })
+    }
+
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    /// Requests received so far, oldest first.
+    pub fn received(&self) -> Vec<Received> {
+        self.received.lock().unwrap().clone()
+    }
+}
+
+async fn handle(
+    respond: Arc<Respond>,
+    log: Arc<Mutex<Vec<Received>>>,
+    request: Request<Body>,
+) -> Result<Response<Body>, Infallible> {
+    let (parts, body) = request.into_parts();
+    let body = hyper::body::to_bytes(body).await.unwrap_or_default();
+    let received = Received {
+        method: parts.method,
+        path: parts.uri.path().to_string(),
+        headers: parts.headers,
+        body: body.to_vec(),
+    };
+    let reply = respond(&received);
+    log.lock().unwrap().push(received);
+    tokio::time::sleep(reply.delay).await;
+    let mut response = Response::new(Body::from(reply.body));
+    *response.status_mut() = reply.status;
+    response.headers_mut().extend(reply.headers);
+    Ok(response)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn answers_and_keeps_requests() {
+        let server = MockServer::rpc(|method, _| match method {
+            "getVersion" => Ok(json!("1.14.0")),
+            _ => Err((-32601, "Method not found".to_string())),
+        })
+        .unwrap();
+        let client = reqwest::Client::new();
+        let response = client
+            .post(server.url())
+            .header("x-tenant", "acme")
+            .body(r#"{"jsonrpc":"2.0","id":1,"method":"getVersion"}"#)
+            .send()
+            .await
+            .unwrap();
+        let body: Value = serde_json::from_str(&response.text().await.unwrap()).unwrap();
+        assert_eq!(body["result"], "1.14.0");
+        let received = server.received();
+        assert_eq!(received[0].header("x-tenant"), Some("acme"));
+        assert_eq!(received[0].rpc_method(), "getVersion");
+
+        let server = MockServer::sequence(vec![
+            MockResponse::new(503, ""),
+            MockResponse::new(200, "ok").with_header("retry-after", "1"),
+        ])
+        .unwrap();
+        let statuses = [503, 200, 503];
+        for status in statuses {
+            let response = client.get(server.url()).send().await.unwrap();
+            assert_eq!(response.status().as_u16(), status);
+        }
+    }
+}